@@ -0,0 +1,282 @@
+//! Per-agent activity statistics.
+//!
+//! Every signal, risk review and execution is counted against the agent
+//! address that made it, so monitoring can spot an agent that suddenly
+//! submits far more signals or notional than usual.
+//!
+//! Agents are also limited to `VaultConfig.max_calls_per_ledger` mutating
+//! calls per ledger, so a runaway loop in the off-chain stack fails fast
+//! instead of flooding the contract. The counters live in temporary storage
+//! keyed by ledger sequence and simply expire.
+//!
+//! An agent can also be given a notional allowance per epoch of
+//! `VaultConfig.allowance_epoch_secs`: each execution it makes is charged
+//! against it, as is the value of the payments it makes, and anything
+//! beyond it fails until the next epoch, limiting what a compromised key
+//! can move.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env};
+
+use crate::{portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, Fill, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum AgentKey {
+    LedgerCalls(Address, u32),  // (agent, ledger sequence) -> mutating calls (temporary)
+    Allowance(Address),  // agent -> notional it may execute per epoch
+    EpochSpent(Address, u64),  // (agent, epoch) -> notional executed (temporary)
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+#[contracttype]
+pub struct AgentStats {
+    pub signals_submitted: u32,
+    pub approvals: u32,
+    pub rejections: u32,
+    pub executions: u32,
+    pub total_notional: i128,  // base-asset value of executed fills
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Get an agent's activity counters
+    pub fn get_agent_stats(env: Env, agent: Address) -> AgentStats {
+        storage::get_persistent(&env, &DataKey::AgentStats(agent)).unwrap_or_default()
+    }
+
+    /// Set how many mutating calls an agent may make per ledger (0 = no limit)
+    pub fn set_agent_rate_limit(env: Env, max_calls_per_ledger: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.max_calls_per_ledger = max_calls_per_ledger;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Cap the notional an agent may execute per epoch (0 removes the cap)
+    pub fn set_agent_allowance(env: Env, agent: Address, max_notional: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if max_notional < 0 {
+            panic!("Allowance must be non-negative");
+        }
+
+        let key = AgentKey::Allowance(agent);
+        if max_notional == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &max_notional);
+        }
+    }
+
+    /// Set the length of the allowance epoch
+    pub fn set_allowance_epoch(env: Env, allowance_epoch_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if allowance_epoch_secs == 0 {
+            panic!("Epoch must be positive");
+        }
+
+        config.allowance_epoch_secs = allowance_epoch_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Notional an agent may still execute this epoch (None = uncapped)
+    pub fn get_remaining_allowance(env: Env, agent: Address) -> Option<i128> {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let allowance: i128 = env.storage().instance().get(&AgentKey::Allowance(agent.clone()))?;
+        let spent: i128 = env.storage().temporary().get(&epoch_key(&env, &config, &agent)).unwrap_or(0);
+        Some((allowance - spent).max(0))
+    }
+}
+
+fn epoch_key(env: &Env, config: &VaultConfig, agent: &Address) -> AgentKey {
+    AgentKey::EpochSpent(agent.clone(), env.ledger().timestamp() / config.allowance_epoch_secs)
+}
+
+/// Charge `notional` to the agent's allowance for the current epoch
+pub(crate) fn spend_allowance(env: &Env, config: &VaultConfig, agent: &Address, notional: i128) {
+    let allowance: i128 = match env.storage().instance().get(&AgentKey::Allowance(agent.clone())) {
+        Some(allowance) => allowance,
+        None => return,
+    };
+
+    let key = epoch_key(env, config, agent);
+    let spent: i128 = env.storage().temporary().get(&key).unwrap_or(0) + notional;
+    if spent > allowance {
+        panic!("Agent allowance exhausted");
+    }
+
+    // Keep the entry for the rest of the epoch (ledgers close about every 5s)
+    let ledgers = (config.allowance_epoch_secs / 5 + 1).min(u32::MAX as u64) as u32;
+    env.storage().temporary().set(&key, &spent);
+    env.storage().temporary().extend_ttl(&key, ledgers, ledgers);
+}
+
+/// Count a mutating call by `agent` in the current ledger
+pub(crate) fn throttle(env: &Env, config: &VaultConfig, agent: &Address) {
+    if config.max_calls_per_ledger == 0 {
+        return;
+    }
+
+    let key = AgentKey::LedgerCalls(agent.clone(), env.ledger().sequence());
+    let calls: u32 = env.storage().temporary().get(&key).unwrap_or(0);
+    if calls >= config.max_calls_per_ledger {
+        panic!("Agent rate limit exceeded");
+    }
+    env.storage().temporary().set(&key, &(calls + 1));
+}
+
+fn update(env: &Env, agent: &Address, apply: impl FnOnce(&mut AgentStats)) {
+    let key = DataKey::AgentStats(agent.clone());
+    let mut stats: AgentStats = storage::get_persistent(env, &key).unwrap_or_default();
+    apply(&mut stats);
+    storage::set_persistent(env, &key, &stats);
+}
+
+pub(crate) fn record_signal(env: &Env, agent: &Address) {
+    update(env, agent, |stats| stats.signals_submitted += 1);
+}
+
+pub(crate) fn record_review(env: &Env, agent: &Address, approved: bool) {
+    update(env, agent, |stats| {
+        if approved {
+            stats.approvals += 1;
+        } else {
+            stats.rejections += 1;
+        }
+    });
+}
+
+/// Count one execution and the notional of all its legs, charging it to
+/// the agent's allowance
+pub(crate) fn record_execution(env: &Env, config: &VaultConfig, agent: &Address, fills: &[Fill]) {
+    let mut notional = 0;
+    for fill in fills {
+        notional += portfolio::notional(env, &fill.asset, fill.amount, fill.price);
+    }
+    spend_allowance(env, config, agent, notional);
+    update(env, agent, |stats| {
+        stats.executions += 1;
+        stats.total_notional += notional;
+    });
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::{RiskMetrics, TradeAction};
+    use soroban_sdk::testutils::Ledger;
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_agent_stats() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+
+        let submit = |amount: i128| {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+
+        let first = submit(1_0000000);
+        let second = submit(2_0000000);
+        assert!(vault.client.approve_trade(&vault.risk_agent, &first, &metrics));
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &second, &RiskMetrics { var_95: 900, ..metrics }));
+        vault.client.execute_trade(&vault.payment_agent, &first, &100_0000000, &None);
+
+        assert_eq!(vault.client.get_agent_stats(&vault.trading_agent).signals_submitted, 2);
+        let risk = vault.client.get_agent_stats(&vault.risk_agent);
+        assert_eq!((risk.approvals, risk.rejections), (1, 1));
+        let payment = vault.client.get_agent_stats(&vault.payment_agent);
+        assert_eq!((payment.executions, payment.total_notional), (1, 100_0000000));
+    }
+
+    #[test]
+    fn test_agent_rate_limit() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.client.set_agent_rate_limit(&2);
+
+        let submit = || {
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+
+        assert!(submit().is_ok());
+        assert!(submit().is_ok());
+        assert!(submit().is_err());
+
+        // Other agents have their own budget
+        assert!(vault.client.try_execute_trade(&vault.payment_agent, &1, &45000_0000000, &None).is_ok());
+
+        // The count starts over in the next ledger
+        env.ledger().with_mut(|l| l.sequence_number += 1);
+        assert!(submit().is_ok());
+    }
+
+    #[test]
+    fn test_agent_allowance() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+        vault.client.set_agent_allowance(&vault.payment_agent, &250_0000000);
+
+        let trade = || {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None)
+        };
+
+        assert!(trade().is_ok());
+        assert!(trade().is_ok());
+        assert_eq!(vault.client.get_remaining_allowance(&vault.payment_agent), Some(50_0000000));
+        assert!(trade().is_err());
+
+        // A new epoch restores the allowance
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert_eq!(vault.client.get_remaining_allowance(&vault.payment_agent), Some(250_0000000));
+        assert!(trade().is_ok());
+        assert_eq!(vault.client.get_remaining_allowance(&vault.trading_agent), None);
+    }
+}
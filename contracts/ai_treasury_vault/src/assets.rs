@@ -0,0 +1,320 @@
+//! Asset metadata registry.
+//!
+//! Each traded asset can be registered with its token contract, the number of
+//! decimals its `amount`s are expressed in and the feed id the price oracle
+//! knows it by. Valuation code scales quantities by the registered decimals;
+//! unregistered assets fall back to the 7-decimal `PRICE_SCALE` convention.
+//!
+//! Token addresses may be Stellar Asset Contracts wrapping XLM or classic
+//! assets as well as native Soroban tokens; both are driven through the
+//! standard token interface, so balances held by the vault can be read
+//! directly from the token contract.
+//!
+//! Illiquid assets can be given a tighter per-trade cap than the global
+//! `max_single_trade`, set through the timelocked config-change queue. It is
+//! checked when a signal is submitted and again when it executes, so
+//! lowering it also holds back queued signals.
+//!
+//! Tokens the vault does not manage (airdrops, mistaken transfers) can be
+//! rescued by the admin. The base token, registered asset tokens, the stake
+//! token and tokens set aside for grants are never rescuable.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, token, Address, Env, String};
+
+use crate::oracle::PRICE_SCALE;
+use crate::{compliance, grants, guard, portfolio, staking};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Largest number of decimals an asset may use without overflowing valuation
+pub const MAX_ASSET_DECIMALS: u32 = 18;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum AssetKey {
+    TradeLimit(String),  // asset -> largest amount per trade
+    TokenAsset(Address),  // token contract -> asset registered with it
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct AssetInfo {
+    pub symbol: String,
+    pub token_address: Address,
+    pub decimals: u32,
+    pub oracle_feed_id: String,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Register (or update) how an asset's amounts and prices are interpreted
+    pub fn register_asset(
+        env: Env,
+        symbol: String,
+        token_address: Address,
+        decimals: u32,
+        oracle_feed_id: String,
+    ) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if decimals > MAX_ASSET_DECIMALS {
+            panic!("Too many decimals");
+        }
+        if let Some(previous) = asset_info(&env, &symbol) {
+            env.storage().instance().remove(&AssetKey::TokenAsset(previous.token_address));
+        }
+        env.storage().instance().set(&AssetKey::TokenAsset(token_address.clone()), &symbol);
+
+        let info = AssetInfo {
+            symbol: symbol.clone(),
+            token_address,
+            decimals,
+            oracle_feed_id,
+        };
+        env.storage().instance().set(&DataKey::Asset(symbol), &info);
+    }
+
+    /// Get the registered metadata for an asset
+    pub fn get_asset_info(env: Env, symbol: String) -> Option<AssetInfo> {
+        asset_info(&env, &symbol)
+    }
+
+    /// Get the vault's balance in the token contract backing an asset
+    pub fn get_token_balance(env: Env, symbol: String) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        token_balance(&env, &config, &symbol)
+    }
+
+    /// Send tokens the vault does not manage to `to` (admin)
+    pub fn rescue_token(env: Env, token: Address, to: Address, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("rescue"));
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if is_managed_token(&env, &config, &token) {
+            panic!("Token is managed by the vault");
+        }
+        compliance::check_recipient(&env, &config, &to);
+
+        token::Client::new(&env, &token).transfer(&env.current_contract_address(), &to, &amount);
+        env.events().publish((symbol_short!("rescue"), symbol_short!("token"), token), amount);
+    }
+
+    /// Get the per-trade cap set for an asset, if any
+    pub fn get_asset_trade_limit(env: Env, asset: String) -> Option<i128> {
+        env.storage().instance().get(&AssetKey::TradeLimit(asset))
+    }
+}
+
+pub(crate) fn asset_info(env: &Env, symbol: &String) -> Option<AssetInfo> {
+    env.storage().instance().get(&DataKey::Asset(symbol.clone()))
+}
+
+/// Decimals `symbol` amounts are expressed in (7 unless registered)
+pub(crate) fn decimals(env: &Env, symbol: &String) -> u32 {
+    match asset_info(env, symbol) {
+        Some(info) => info.decimals,
+        None => PRICE_SCALE.ilog10(),
+    }
+}
+
+/// Smallest units per whole unit of `symbol` (10^decimals)
+pub(crate) fn unit_scale(env: &Env, symbol: &String) -> i128 {
+    10i128.pow(decimals(env, symbol))
+}
+
+/// Token contract backing `symbol`; the base asset uses `VaultConfig.base_token`
+pub(crate) fn token_address(env: &Env, config: &VaultConfig, symbol: &String) -> Option<Address> {
+    if *symbol == config.base_asset && config.base_token.is_some() {
+        return config.base_token.clone();
+    }
+    asset_info(env, symbol).map(|info| info.token_address)
+}
+
+/// Balance the vault holds in the token contract backing `symbol`
+pub(crate) fn token_balance(env: &Env, config: &VaultConfig, symbol: &String) -> i128 {
+    match token_address(env, config, symbol) {
+        Some(address) => token::Client::new(env, &address).balance(&env.current_contract_address()),
+        None => 0,
+    }
+}
+
+/// Asset whose position `token` backs: the base asset or a registered asset
+pub(crate) fn asset_for_token(env: &Env, config: &VaultConfig, token: &Address) -> Option<String> {
+    if config.base_token.as_ref() == Some(token) {
+        return Some(config.base_asset.clone());
+    }
+    env.storage().instance().get(&AssetKey::TokenAsset(token.clone()))
+}
+
+/// Whether `token` backs the base asset, a registered or held asset, stake
+/// or grants
+fn is_managed_token(env: &Env, config: &VaultConfig, token: &Address) -> bool {
+    if asset_for_token(env, config, token).is_some()
+        || staking::stake_token(env).as_ref() == Some(token)
+        || grants::granted(env, token) > 0
+    {
+        return true;
+    }
+    // Assets registered before the token index existed
+    portfolio::held_assets(env)
+        .iter()
+        .any(|asset| token_address(env, config, &asset).as_ref() == Some(token))
+}
+
+/// Refuse a trade of `amount` above the asset's own cap
+pub(crate) fn check_trade_limit(env: &Env, symbol: &String, amount: i128) {
+    let limit: Option<i128> = env.storage().instance().get(&AssetKey::TradeLimit(symbol.clone()));
+    if limit.is_some_and(|limit| amount > limit) {
+        panic!("Trade amount exceeds asset limit");
+    }
+}
+
+/// Identifier the price oracle uses for `symbol`
+pub(crate) fn feed_id(env: &Env, symbol: &String) -> String {
+    match asset_info(env, symbol) {
+        Some(info) => info.oracle_feed_id,
+        None => symbol.clone(),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::token::StellarAssetClient;
+
+    #[test]
+    fn test_registered_decimals_drive_valuation() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+
+        // BTC amounts use 8 decimals and the oracle knows it as "BTCUSD"
+        let btc = String::from_str(&env, "BTC");
+        let feed = String::from_str(&env, "BTCUSD");
+        vault.client.register_asset(&btc, &Address::generate(&env), &8, &feed);
+        oracle.set_price(&feed, &50000_0000000);
+        assert_eq!(vault.client.get_asset_info(&btc).unwrap().decimals, 8);
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &1_00000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &40000_0000000, &None);
+
+        // 1 BTC bought for 40k base, marked at 50k
+        assert_eq!(
+            vault.client.get_position(&String::from_str(&env, "XLM")),
+            -40000_0000000
+        );
+        assert_eq!(vault.client.compute_nav(), 10000_0000000);
+
+        assert!(vault.client
+            .try_register_asset(&btc, &Address::generate(&env), &19, &feed)
+            .is_err());
+    }
+
+    #[test]
+    fn test_stellar_asset_contracts() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+
+        // XLM as the base token and a classic USDC asset, both wrapped in SACs
+        let xlm_token = vault.register_base_token(&env);
+        let issuer = Address::generate(&env);
+        let usdc_sac = env.register_stellar_asset_contract_v2(issuer);
+        let usdc_token = StellarAssetClient::new(&env, &usdc_sac.address());
+        vault.client.register_asset(&usdc, &usdc_sac.address(), &7, &usdc);
+        assert_eq!(token::Client::new(&env, &usdc_sac.address()).decimals(), 7);
+
+        let alice = Address::generate(&env);
+        xlm_token.mint(&alice, &500_0000000);
+        vault.client.deposit(&alice, &500_0000000);
+        usdc_token.mint(&vault.client.address, &25_0000000);
+
+        assert_eq!(vault.client.get_token_balance(&xlm), 500_0000000);
+        assert_eq!(vault.client.get_token_balance(&usdc), 25_0000000);
+        assert_eq!(vault.client.get_token_balance(&String::from_str(&env, "BTC")), 0);
+
+        vault.client.withdraw(&alice, &200_0000000);
+        assert_eq!(vault.client.get_token_balance(&xlm), 300_0000000);
+        assert_eq!(token::Client::new(&env, &xlm_token.address).balance(&alice), 200_0000000);
+
+        // The booked base position cannot be paid out beyond the token balance
+        token::Client::new(&env, &xlm_token.address)
+            .transfer(&vault.client.address, &Address::generate(&env), &250_0000000);
+        assert!(vault.client.try_withdraw(&alice, &100_0000000).is_err());
+    }
+
+    #[test]
+    fn test_rescue_token() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let base = vault.register_base_token(&env);
+        let usdc = env.register_stellar_asset_contract_v2(Address::generate(&env));
+        vault.client.register_asset(&String::from_str(&env, "USDC"), &usdc.address(), &7, &String::from_str(&env, "USDC"));
+        let airdrop = env.register_stellar_asset_contract_v2(Address::generate(&env));
+        StellarAssetClient::new(&env, &airdrop.address()).mint(&vault.client.address, &10_0000000);
+        base.mint(&vault.client.address, &10_0000000);
+
+        // Managed tokens stay put; stray ones can be recovered
+        let to = Address::generate(&env);
+        assert!(vault.client.try_rescue_token(&base.address, &to, &1_0000000).is_err());
+        assert!(vault.client.try_rescue_token(&usdc.address(), &to, &1_0000000).is_err());
+        vault.client.rescue_token(&airdrop.address(), &to, &10_0000000);
+        assert_eq!(token::Client::new(&env, &airdrop.address()).balance(&to), 10_0000000);
+    }
+
+    #[test]
+    fn test_asset_trade_limit() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+        let xlm = String::from_str(&env, "XLM");
+
+        let submit = |asset: &String, amount: i128| {
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                asset,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+
+        let queued = submit(&btc, 5_0000000).unwrap().unwrap();
+        vault.apply_change(&env, &ConfigChange::AssetTradeLimit(btc.clone(), 1_0000000));
+        assert_eq!(vault.client.get_asset_trade_limit(&btc), Some(1_0000000));
+
+        // Submission and execution both respect the tighter cap
+        assert!(submit(&btc, 2_0000000).is_err());
+        assert!(submit(&btc, 1_0000000).is_ok());
+        assert!(submit(&xlm, 5_0000000).is_ok());
+        assert!(vault.client.try_execute_trade(&vault.payment_agent, &queued, &100_0000000, &None).is_err());
+
+        vault.apply_change(&env, &ConfigChange::AssetTradeLimit(btc.clone(), 0));
+        assert_eq!(vault.client.get_asset_trade_limit(&btc), None);
+        vault.client.execute_trade(&vault.payment_agent, &queued, &100_0000000, &None);
+    }
+}
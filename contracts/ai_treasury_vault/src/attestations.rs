@@ -0,0 +1,175 @@
+//! Inference run attestations.
+//!
+//! Before its signals are submitted, a batch of AI inferences is anchored
+//! on-chain by a trading agent: the run id, hashes of the run's inputs and
+//! outputs, and when it ran. Anchors can never be overwritten. A signal
+//! names the run it came from in its `Provenance`; unknown runs are refused,
+//! and once the admin turns on `set_require_attestations` every signal has
+//! to name one. The run behind each executed trade is kept after its signal
+//! expires, so an auditor can walk from a trade back to the exact inputs and
+//! outputs of the inference that produced it.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, BytesN, Env};
+
+use crate::models::Provenance;
+use crate::roles;
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum AttestationKey {
+    Attestation(u64),  // run_id -> anchored run (persistent)
+    RequireAttestations,
+    TradeRun(u64),  // trade_id -> run behind it (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Attestation {
+    pub input_hash: BytesN<32>,  // hash of the inference inputs
+    pub output_hash: BytesN<32>,  // hash of the inference outputs
+    pub timestamp: u64,  // when the run happened
+    pub anchored_by: Address,
+    pub anchored_at: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Anchor the fingerprint of an off-chain inference run
+    ///
+    /// A run id can be anchored only once.
+    pub fn anchor_attestation(
+        env: Env,
+        caller: Address,
+        run_id: u64,
+        input_hash: BytesN<32>,
+        output_hash: BytesN<32>,
+        timestamp: u64,
+    ) {
+        roles::require_role(&env, &caller, &roles::TRADING_AGENT);
+
+        let key = AttestationKey::Attestation(run_id);
+        if env.storage().persistent().has(&key) {
+            panic!("Run already anchored");
+        }
+        let now = env.ledger().timestamp();
+        if timestamp > now {
+            panic!("Run timestamp is in the future");
+        }
+
+        let attestation = Attestation {
+            input_hash,
+            output_hash,
+            timestamp,
+            anchored_by: caller,
+            anchored_at: now,
+        };
+        env.storage().persistent().set(&key, &attestation);
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("attest"), symbol_short!("anchor"), run_id), attestation.output_hash);
+    }
+
+    /// Get an anchored inference run
+    pub fn get_attestation(env: Env, run_id: u64) -> Option<Attestation> {
+        env.storage().persistent().get(&AttestationKey::Attestation(run_id))
+    }
+
+    /// Require (or stop requiring) every signal to reference an anchored run
+    pub fn set_require_attestations(env: Env, required: bool) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().set(&AttestationKey::RequireAttestations, &required);
+        storage::extend_instance(&env);
+    }
+
+    /// Get the inference run behind an executed trade
+    pub fn get_trade_run(env: Env, trade_id: u64) -> Option<u64> {
+        env.storage().persistent().get(&AttestationKey::TradeRun(trade_id))
+    }
+}
+
+/// Check the run a new signal references; returns its id
+pub(crate) fn check_attestation(env: &Env, provenance: &Option<Provenance>) -> Option<u64> {
+    let run_id = provenance.as_ref().and_then(|provenance| provenance.run_id);
+    match run_id {
+        Some(run_id) => {
+            if !env.storage().persistent().has(&AttestationKey::Attestation(run_id)) {
+                panic!("Unknown inference run");
+            }
+        }
+        None => {
+            if env.storage().instance().get(&AttestationKey::RequireAttestations).unwrap_or(false) {
+                panic!("Signal must reference an inference run");
+            }
+        }
+    }
+    run_id
+}
+
+/// Keep the inference run behind an executed trade
+pub(crate) fn record_trade_run(env: &Env, trade_id: u64, run_id: Option<u64>) {
+    if let Some(run_id) = run_id {
+        let key = AttestationKey::TradeRun(trade_id);
+        env.storage().persistent().set(&key, &run_id);
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::inference::InferenceSignature;
+    use crate::models::ModelRef;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+    use soroban_sdk::{String, Symbol};
+
+    #[test]
+    fn test_attestations() {
+        let env = Env::default();
+        let vault = setup(&env);
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        let inputs = BytesN::from_array(&env, &[1; 32]);
+        let outputs = BytesN::from_array(&env, &[2; 32]);
+
+        // Runs are anchored once and never in the future
+        vault.client.anchor_attestation(&vault.trading_agent, &7, &inputs, &outputs, &900);
+        assert!(vault.client.try_anchor_attestation(&vault.trading_agent, &7, &inputs, &inputs, &900).is_err());
+        assert!(vault.client.try_anchor_attestation(&vault.trading_agent, &8, &inputs, &outputs, &1001).is_err());
+        assert!(vault.client.try_anchor_attestation(&vault.risk_agent, &8, &inputs, &outputs, &900).is_err());
+        assert_eq!(vault.client.get_attestation(&7).unwrap().output_hash, outputs);
+
+        let lstm = Symbol::new(&env, "lstm");
+        vault.client.register_model(&lstm, &1, &BytesN::from_array(&env, &[7; 32]));
+        let submit = |run_id: Option<u64>| {
+            let model = ModelRef { model_id: lstm.clone(), version: 1 };
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &Some(Provenance { model, run_id, signature: InferenceSignature::Unsigned }),
+            )
+        };
+
+        // Only anchored runs, and a run is needed once required
+        assert!(submit(Some(8)).is_err());
+        vault.client.set_require_attestations(&true);
+        assert!(submit(None).is_err());
+        let signal_id = submit(Some(7)).unwrap().unwrap();
+
+        // The executed trade stays traceable to its run
+        let trade_id = vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        assert_eq!(vault.client.get_trade_run(&trade_id), Some(7));
+    }
+}
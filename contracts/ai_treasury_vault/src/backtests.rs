@@ -0,0 +1,129 @@
+//! Backtest commitments.
+//!
+//! Before a strategy goes live the team commits the hash of its backtest
+//! report and the period it covered. Commitments are append-only and record
+//! how many trades the strategy had executed when they were made, so anyone
+//! holding the report can later prove it was registered before the live
+//! deployment rather than fitted to its results.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, BytesN, Env, String, Vec};
+
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum BacktestKey {
+    Backtests(String),  // strategy_name -> committed reports, oldest first (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct BacktestPeriod {
+    pub start: u64,
+    pub end: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct BacktestCommitment {
+    pub report_hash: BytesN<32>,
+    pub period: BacktestPeriod,  // market data the backtest covered
+    pub committed_at: u64,
+    pub live_trades: u32,  // trades the strategy had executed at commit time
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Commit the hash of a strategy's backtest report
+    pub fn commit_backtest(env: Env, strategy: String, report_hash: BytesN<32>, period: BacktestPeriod) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let now = env.ledger().timestamp();
+        if period.start >= period.end || period.end > now {
+            panic!("Invalid backtest period");
+        }
+        let mut backtests = backtests(&env, &strategy);
+        if backtests.iter().any(|backtest| backtest.report_hash == report_hash) {
+            panic!("Backtest already committed");
+        }
+
+        backtests.push_back(BacktestCommitment {
+            report_hash: report_hash.clone(),
+            period,
+            committed_at: now,
+            live_trades: Self::get_strategy_performance(env.clone(), strategy.clone()).total_trades,
+        });
+        let key = BacktestKey::Backtests(strategy.clone());
+        env.storage().persistent().set(&key, &backtests);
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+
+        env.events().publish((symbol_short!("backtest"), symbol_short!("commit"), strategy), report_hash);
+    }
+
+    /// Get a strategy's committed backtests, oldest first
+    pub fn get_backtests(env: Env, strategy: String) -> Vec<BacktestCommitment> {
+        backtests(&env, &strategy)
+    }
+
+    /// Find the commitment of a backtest report, if it was committed
+    pub fn verify_backtest(env: Env, strategy: String, report_hash: BytesN<32>) -> Option<BacktestCommitment> {
+        backtests(&env, &strategy)
+            .iter()
+            .find(|backtest| backtest.report_hash == report_hash)
+    }
+}
+
+fn backtests(env: &Env, strategy: &String) -> Vec<BacktestCommitment> {
+    env.storage().persistent()
+        .get(&BacktestKey::Backtests(strategy.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_backtest_commitments() {
+        let env = Env::default();
+        let vault = setup(&env);
+        env.ledger().with_mut(|l| l.timestamp = 5000);
+        let lstm = String::from_str(&env, "LSTM");
+        let report = BytesN::from_array(&env, &[4; 32]);
+        let period = BacktestPeriod { start: 1000, end: 4000 };
+
+        // Periods must be past and non-empty, and each report is committed once
+        let future = BacktestPeriod { start: 1000, end: 6000 };
+        assert!(vault.client.try_commit_backtest(&lstm, &report, &future).is_err());
+        vault.client.commit_backtest(&lstm, &report, &period);
+        assert!(vault.client.try_commit_backtest(&lstm, &report, &period).is_err());
+
+        // A report committed after going live says so
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &String::from_str(&env, "BTC"),
+            &TradeAction::Buy,
+            &100000,
+            &lstm,
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        let later = BytesN::from_array(&env, &[5; 32]);
+        vault.client.commit_backtest(&lstm, &later, &period);
+
+        let committed = vault.client.verify_backtest(&lstm, &report).unwrap();
+        assert_eq!(committed, BacktestCommitment { report_hash: report, period, committed_at: 5000, live_trades: 0 });
+        assert_eq!(vault.client.verify_backtest(&lstm, &later).unwrap().live_trades, 1);
+        assert_eq!(vault.client.get_backtests(&lstm).len(), 2);
+        assert!(vault.client.verify_backtest(&String::from_str(&env, "DQN"), &later).is_none());
+    }
+}
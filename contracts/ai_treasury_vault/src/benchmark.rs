@@ -0,0 +1,209 @@
+//! Buy-and-hold benchmark tracking.
+//!
+//! When a benchmark asset is configured every snapshot records its oracle
+//! price next to the vault's NAV per share. `get_alpha` compares the vault's
+//! return with simply holding the benchmark over the same period, starting
+//! from the first snapshot taken after the benchmark was set.
+//! `get_return_since` gives the vault's own return from any snapshot, and
+//! `diff_snapshots` compares any two.
+
+use soroban_sdk::{contractimpl, contracttype, Env, String};
+
+use crate::oracle::PRICE_SCALE;
+use crate::{deposits, portfolio, records};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, PortfolioSnapshot, VaultConfig};
+
+/// Change between two snapshots
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SnapshotDiff {
+    pub value_change: i128,  // change in total value
+    pub trades: u64,  // trades executed in between
+    pub return_bps: i32,  // return of NAV per share
+    pub elapsed_secs: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the asset the vault's performance is measured against
+    pub fn set_benchmark(env: Env, asset: String) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if config.price_oracle.is_none() {
+            panic!("Price oracle not configured");
+        }
+
+        config.benchmark_asset = Some(asset);
+        env.storage().instance().set(&DataKey::Config, &config);
+        // Returns are only comparable from a snapshot priced in this benchmark
+        env.storage().instance().remove(&DataKey::BenchmarkBase);
+    }
+
+    /// Vault return minus benchmark return since the benchmark was set (bps)
+    pub fn get_alpha(env: Env) -> i32 {
+        let base: PortfolioSnapshot = env.storage().instance()
+            .get(&DataKey::BenchmarkBase)
+            .expect("No benchmark history");
+        let latest: PortfolioSnapshot = env.storage().instance()
+            .get(&DataKey::LatestSnapshot)
+            .unwrap();
+
+        let vault_return = return_bps(base.share_price, latest.share_price);
+        let benchmark_return = return_bps(base.benchmark_price, latest.benchmark_price);
+        (vault_return - benchmark_return) as i32
+    }
+
+    /// Return of NAV per share from a past snapshot to the latest one (bps)
+    pub fn get_return_since(env: Env, snapshot_id: u64) -> i32 {
+        let from = records::load_snapshot(&env, snapshot_id).expect("No such snapshot");
+        let latest: PortfolioSnapshot = env.storage().instance()
+            .get(&DataKey::LatestSnapshot)
+            .unwrap();
+
+        snapshot_return(&from, &latest)
+    }
+
+    /// Compare snapshot `a` with the later snapshot `b`
+    pub fn diff_snapshots(env: Env, a: u64, b: u64) -> SnapshotDiff {
+        if b < a {
+            panic!("Invalid snapshot range");
+        }
+        let from = records::load_snapshot(&env, a).expect("No such snapshot");
+        let to = records::load_snapshot(&env, b).expect("No such snapshot");
+
+        SnapshotDiff {
+            value_change: to.total_value - from.total_value,
+            trades: to.total_trades - from.total_trades,
+            return_bps: snapshot_return(&from, &to),
+            elapsed_secs: to.timestamp - from.timestamp,
+        }
+    }
+}
+
+/// Return of NAV per share between two snapshots (bps)
+fn snapshot_return(from: &PortfolioSnapshot, to: &PortfolioSnapshot) -> i32 {
+    // Snapshots from before share prices were recorded only have a value
+    if from.share_price > 0 {
+        return_bps(from.share_price, to.share_price) as i32
+    } else {
+        return_bps(from.total_value, to.total_value) as i32
+    }
+}
+
+/// Oracle price of the benchmark, or 0 when none is configured
+pub(crate) fn benchmark_price(env: &Env, config: &VaultConfig) -> i128 {
+    match &config.benchmark_asset {
+        Some(asset) => portfolio::price_of(env, config, asset),
+        None => 0,
+    }
+}
+
+/// NAV per share scaled by `PRICE_SCALE`; 1.0 before anyone has deposited
+pub(crate) fn share_price(env: &Env, total_value: i128) -> i128 {
+    let total_shares = deposits::total_shares(env);
+    if total_shares == 0 {
+        PRICE_SCALE
+    } else {
+        total_value * PRICE_SCALE / total_shares
+    }
+}
+
+/// Remember the first snapshot taken against the current benchmark
+pub(crate) fn record_snapshot(env: &Env, snapshot: &PortfolioSnapshot) {
+    if snapshot.benchmark_price > 0 && !env.storage().instance().has(&DataKey::BenchmarkBase) {
+        env.storage().instance().set(&DataKey::BenchmarkBase, snapshot);
+    }
+}
+
+fn return_bps(from: i128, to: i128) -> i128 {
+    (to - from) * 10000 / from
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::Address;
+
+    #[test]
+    fn test_alpha_against_buy_and_hold() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+        vault.client.set_benchmark(&btc);
+        assert!(vault.client.try_get_alpha().is_err());
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        // Put half the vault into BTC, then snapshot the starting point
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &5_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+        assert_eq!(vault.client.get_latest_snapshot().benchmark_price, 100_0000000);
+
+        // BTC +20% lifts the half-invested vault by 10%: alpha is -10%
+        oracle.set_price(&btc, &120_0000000);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+        let snapshot = vault.client.get_latest_snapshot();
+        assert_eq!(snapshot.share_price, 1_1000000);
+        assert_eq!(vault.client.get_alpha(), -1000);
+    }
+
+    #[test]
+    fn test_return_since_snapshot() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &5_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+
+        let week_ago = vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+        oracle.set_price(&btc, &120_0000000);
+        let yesterday = vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+        oracle.set_price(&btc, &110_0000000);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+
+        assert_eq!(vault.client.get_return_since(&week_ago), 500);  // 1.00 -> 1.05
+        assert_eq!(vault.client.get_return_since(&yesterday), -454);  // 1.10 -> 1.05
+        assert!(vault.client.try_get_return_since(&9).is_err());
+
+        let diff = vault.client.diff_snapshots(&week_ago, &yesterday);
+        assert_eq!(diff, SnapshotDiff { value_change: 100_0000000, trades: 0, return_bps: 1000, elapsed_secs: 0 });
+        assert!(vault.client.try_diff_snapshots(&yesterday, &week_ago).is_err());
+    }
+}
@@ -0,0 +1,401 @@
+//! Borrowing against collateral in a lending market.
+//!
+//! The admin points the vault at a lending market, posts assets to it as
+//! collateral and borrows the base asset against them. Posted collateral
+//! stays on the position book, locked, while the market's debt is subtracted
+//! from NAV. The market reports the debt at
+//! which the vault would be liquidated; the health factor is that limit over
+//! the debt, in bps, so 10000 is the liquidation point.
+//!
+//! Borrowing and releasing collateral are refused when they would leave the
+//! health factor below the configured floor. When interest or a falling
+//! collateral price pushes it below the floor anyway, `check_health` repays
+//! debt out of the base position until the health factor is back at the
+//! target, ahead of any liquidation. Deleveraging may dip into the operating
+//! reserve; keeping the collateral matters more.
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, String};
+
+use crate::{assets, collateral, fees, guard, portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Health factor below which the vault deleverages unless the admin sets
+/// another floor, in bps
+pub const DEFAULT_HEALTH_FLOOR_BPS: u32 = 12000;
+
+/// Health factor deleveraging restores unless the admin sets another target
+pub const DEFAULT_HEALTH_TARGET_BPS: u32 = 15000;
+
+/// Interface of the lending markets the vault borrows from
+#[allow(dead_code)]
+#[contractclient(name = "LendingMarketClient")]
+pub trait ILendingMarket {
+    /// Called after `amount` of `token` has been transferred to the market;
+    /// credit it to `from` as collateral
+    fn deposit_collateral(env: Env, from: Address, token: Address, amount: i128);
+    /// Send `amount` of `from`'s collateral in `token` back to it
+    fn withdraw_collateral(env: Env, from: Address, token: Address, amount: i128);
+    /// Lend `amount` of the base token to `to`; returns the amount lent
+    fn borrow(env: Env, to: Address, amount: i128) -> i128;
+    /// Called after `amount` of the base token has been transferred to the
+    /// market; apply it to `from`'s debt
+    fn repay(env: Env, from: Address, amount: i128) -> i128;
+    /// Base asset `holder` owes, interest included
+    fn debt(env: Env, holder: Address) -> i128;
+    /// Debt at which `holder`'s collateral would be liquidated
+    fn borrow_limit(env: Env, holder: Address) -> i128;
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum BorrowKey {
+    BorrowMarket,
+    HealthFloor,
+    HealthTarget,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct HealthStatus {
+    pub debt: i128,
+    pub borrow_limit: i128,
+    pub health_bps: u32,  // u32::MAX without debt
+    pub floor_bps: u32,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Borrow from `market` from now on
+    pub fn set_borrow_market(env: Env, market: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let in_use = borrow_market(&env).is_some_and(|current| collateral::holds_any(&env, &current));
+        if debt(&env) > 0 || in_use {
+            panic!("Repay the current market first");
+        }
+        env.storage().instance().set(&BorrowKey::BorrowMarket, &market);
+        storage::extend_instance(&env);
+    }
+
+    /// Set the health factor that triggers deleveraging and the one it restores, in bps
+    pub fn set_health_bounds(env: Env, floor_bps: u32, target_bps: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if floor_bps <= 10000 || target_bps <= floor_bps {
+            panic!("Invalid health bounds");
+        }
+        env.storage().instance().set(&BorrowKey::HealthFloor, &floor_bps);
+        env.storage().instance().set(&BorrowKey::HealthTarget, &target_bps);
+    }
+
+    /// Post `amount` of a held asset to the market as collateral
+    pub fn post_collateral(env: Env, asset: String, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("borrow"));
+
+        let market = market(&env);
+        let token = assets::token_address(&env, &config, &asset).expect("Asset has no token");
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        collateral::lock(&env, &asset, &market, amount);
+
+        let vault = env.current_contract_address();
+        token::Client::new(&env, &token).transfer(&vault, &market, &amount);
+        LendingMarketClient::new(&env, &market).deposit_collateral(&vault, &token, &amount);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("borrow"), symbol_short!("post"), asset), amount);
+    }
+
+    /// Take `amount` of posted collateral back from the market
+    pub fn release_collateral(env: Env, asset: String, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("borrow"));
+
+        let market = market(&env);
+        let token = assets::token_address(&env, &config, &asset).expect("Asset has no token");
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        collateral::unlock(&env, &asset, &market, amount);
+
+        LendingMarketClient::new(&env, &market).withdraw_collateral(&env.current_contract_address(), &token, &amount);
+        require_healthy(&env);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("borrow"), symbol_short!("release"), asset), amount);
+    }
+
+    /// Borrow `amount` of base asset against the posted collateral
+    pub fn borrow(env: Env, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("borrow"));
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        let market = market(&env);
+        let base_client = base_client(&env, &config);
+        let vault = env.current_contract_address();
+        let balance = base_client.balance(&vault);
+        LendingMarketClient::new(&env, &market).borrow(&vault, &amount);
+        let received = base_client.balance(&vault) - balance;
+
+        portfolio::adjust_position(&env, &config.base_asset, received);
+        require_healthy(&env);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("borrow"), symbol_short!("borrow")), received);
+    }
+
+    /// Repay `amount` of debt out of the base position
+    pub fn repay(env: Env, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("borrow"));
+
+        if amount <= 0 || amount > debt(&env) {
+            panic!("Repayment exceeds debt");
+        }
+        let base_before = portfolio::position(&env, &config.base_asset);
+        repay_debt(&env, &config, amount);
+        collateral::check_unlocked(&env, &config.base_asset);
+        fees::check_reserve(&env, &config, base_before);
+        storage::extend_instance(&env);
+    }
+
+    /// Repay debt until the health factor is back at the target if it has
+    /// fallen below the floor; returns the health factor afterwards
+    ///
+    /// Anyone may call this; the caller is paid the keeper fee when it
+    /// deleverages.
+    pub fn check_health(env: Env, keeper: Address) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        keeper.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("borrow"));
+
+        let status = Self::get_health(env.clone());
+        if status.health_bps >= status.floor_bps {
+            return status.health_bps;
+        }
+
+        // The limit stays put as debt is repaid, so solve limit / (debt - x) = target
+        let target = health_target(&env) as i128;
+        let needed = status.debt - status.borrow_limit * 10000 / target;
+        let repayment = needed.min(collateral::available(&env, &config.base_asset));
+        if repayment > 0 {
+            repay_debt(&env, &config, repayment);
+            fees::pay_keeper(&env, &config, &keeper);
+        }
+        storage::extend_instance(&env);
+
+        let health = Self::get_health(env.clone()).health_bps;
+        env.events().publish((symbol_short!("borrow"), symbol_short!("delever")), (repayment, health));
+        health
+    }
+
+    /// Get the vault's debt, borrow limit and health factor
+    pub fn get_health(env: Env) -> HealthStatus {
+        let (debt, borrow_limit) = match borrow_market(&env) {
+            Some(market) => {
+                let client = LendingMarketClient::new(&env, &market);
+                let vault = env.current_contract_address();
+                (client.debt(&vault), client.borrow_limit(&vault))
+            }
+            None => (0, 0),
+        };
+        HealthStatus { debt, borrow_limit, health_bps: health_bps(debt, borrow_limit), floor_bps: health_floor(&env) }
+    }
+}
+
+fn borrow_market(env: &Env) -> Option<Address> {
+    env.storage().instance().get(&BorrowKey::BorrowMarket)
+}
+
+fn market(env: &Env) -> Address {
+    borrow_market(env).expect("Borrow market not configured")
+}
+
+fn base_client<'a>(env: &'a Env, config: &VaultConfig) -> token::Client<'a> {
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    token::Client::new(env, &base_token)
+}
+
+fn health_floor(env: &Env) -> u32 {
+    env.storage().instance().get(&BorrowKey::HealthFloor).unwrap_or(DEFAULT_HEALTH_FLOOR_BPS)
+}
+
+fn health_target(env: &Env) -> u32 {
+    env.storage().instance().get(&BorrowKey::HealthTarget).unwrap_or(DEFAULT_HEALTH_TARGET_BPS)
+}
+
+fn health_bps(debt: i128, borrow_limit: i128) -> u32 {
+    if debt <= 0 {
+        return u32::MAX;
+    }
+    (borrow_limit * 10000 / debt).clamp(0, u32::MAX as i128 - 1) as u32
+}
+
+fn require_healthy(env: &Env) {
+    let status = AITreasuryVaultV2::get_health(env.clone());
+    if status.health_bps < status.floor_bps {
+        panic!("Health factor below floor");
+    }
+}
+
+/// Send `amount` of base asset to the market against the vault's debt
+pub(crate) fn repay_debt(env: &Env, config: &VaultConfig, amount: i128) {
+    let market = market(env);
+    let base_client = base_client(env, config);
+    let vault = env.current_contract_address();
+    let balance = base_client.balance(&vault);
+    base_client.transfer(&vault, &market, &amount);
+    LendingMarketClient::new(env, &market).repay(&vault, &amount);
+    let spent = balance - base_client.balance(&vault);
+
+    portfolio::adjust_position(env, &config.base_asset, -spent);
+    env.events().publish((symbol_short!("borrow"), symbol_short!("repay")), spent);
+}
+
+/// Base asset the vault owes the market
+pub(crate) fn debt(env: &Env) -> i128 {
+    borrow_market(env).map_or(0, |market| {
+        LendingMarketClient::new(env, &market).debt(&env.current_contract_address())
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use super::*;
+    use crate::fees::FeeSchedule;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::contract;
+
+    /// Market lending base tokens up to a limit the test sets directly
+    #[contract]
+    pub struct MockMarket;
+
+    #[contractimpl]
+    impl MockMarket {
+        pub fn init(env: Env, base_token: Address) {
+            env.storage().instance().set(&symbol_short!("base"), &base_token);
+        }
+
+        pub fn set_borrow_limit(env: Env, limit: i128) {
+            env.storage().instance().set(&symbol_short!("limit"), &limit);
+        }
+
+        pub fn deposit_collateral(_env: Env, _from: Address, _token: Address, _amount: i128) {}
+
+        pub fn withdraw_collateral(env: Env, from: Address, token: Address, amount: i128) {
+            token::Client::new(&env, &token).transfer(&env.current_contract_address(), &from, &amount);
+        }
+
+        pub fn borrow(env: Env, to: Address, amount: i128) -> i128 {
+            let base: Address = env.storage().instance().get(&symbol_short!("base")).unwrap();
+            token::Client::new(&env, &base).transfer(&env.current_contract_address(), &to, &amount);
+            env.storage().instance().set(&to, &(Self::debt(env.clone(), to.clone()) + amount));
+            amount
+        }
+
+        pub fn repay(env: Env, from: Address, amount: i128) -> i128 {
+            env.storage().instance().set(&from, &(Self::debt(env.clone(), from.clone()) - amount));
+            amount
+        }
+
+        pub fn debt(env: Env, holder: Address) -> i128 {
+            env.storage().instance().get(&holder).unwrap_or(0)
+        }
+
+        pub fn borrow_limit(env: Env, _holder: Address) -> i128 {
+            env.storage().instance().get(&symbol_short!("limit")).unwrap_or(0)
+        }
+    }
+
+    #[test]
+    fn test_borrow_and_deleverage() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+
+        let usdc = String::from_str(&env, "USDC");
+        let usdc_token = StellarAssetClient::new(&env, &env.register_stellar_asset_contract_v2(vault.admin.clone()).address());
+        vault.client.register_asset(&usdc, &usdc_token.address, &7, &usdc);
+        oracle.set_price(&usdc, &1_0000000);
+        usdc_token.mint(&vault.client.address, &300_0000000);
+        env.as_contract(&vault.client.address, || portfolio::adjust_position(&env, &usdc, 300_0000000));
+
+        let market = env.register_contract(None, MockMarket);
+        let market_client = MockMarketClient::new(&env, &market);
+        market_client.init(&token.address);
+        market_client.set_borrow_limit(&200_0000000);
+        token.mint(&market, &1000_0000000);
+        vault.client.set_borrow_market(&market);
+
+        // Collateral stays on the book; borrowed cash is offset by the debt
+        vault.client.post_collateral(&usdc, &300_0000000);
+        assert_eq!(vault.client.get_position(&usdc), 300_0000000);
+        let nav = vault.client.compute_nav();
+        vault.client.borrow(&100_0000000);
+        assert_eq!(vault.client.get_position(&xlm), 1100_0000000);
+        assert_eq!(vault.client.compute_nav(), nav);
+        assert_eq!(vault.client.get_health().health_bps, 20000);
+        assert!(vault.client.try_borrow(&80_0000000).is_err());
+
+        // A falling limit drops health below the floor; a keeper restores the target
+        let keeper = Address::generate(&env);
+        assert_eq!(vault.client.check_health(&keeper), 20000);
+        market_client.set_borrow_limit(&110_0000000);
+        assert_eq!(vault.client.check_health(&keeper), 15000);
+        let status = vault.client.get_health();
+        assert_eq!(status.debt, 73_3333333);
+        assert_eq!(vault.client.get_position(&xlm), 1073_3333333);
+        assert_eq!(vault.client.compute_nav(), nav);
+    }
+
+    #[test]
+    fn test_health_check_without_repayment_pays_no_keeper() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+        vault.client.set_fee_schedule(&FeeSchedule { management_fee_bps: 0, performance_fee_bps: 0, keeper_fee: 1_0000000 });
+
+        let market = env.register_contract(None, MockMarket);
+        let market_client = MockMarketClient::new(&env, &market);
+        market_client.init(&token.address);
+        market_client.set_borrow_limit(&200_0000000);
+        token.mint(&market, &1000_0000000);
+        vault.client.set_borrow_market(&market);
+        vault.client.borrow(&100_0000000);
+
+        // Health is below the floor but every base unit is locked
+        market_client.set_borrow_limit(&110_0000000);
+        env.as_contract(&vault.client.address, || collateral::lock(&env, &xlm, &market, 1100_0000000));
+        let keeper = Address::generate(&env);
+        for _ in 0..3 {
+            assert_eq!(vault.client.check_health(&keeper), 11000);
+        }
+        assert_eq!(token::Client::new(&env, &token.address).balance(&keeper), 0);
+        assert_eq!(vault.client.get_position(&xlm), 1100_0000000);
+    }
+}
@@ -0,0 +1,195 @@
+//! Budget envelopes.
+//!
+//! The admin defines spending categories (ops, marketing, infra), each
+//! capping how much of one token may be paid out per budget epoch. Every
+//! scheduled payment names a category in that token, and executing it is
+//! charged against the category's envelope for the current epoch; payments
+//! beyond the cap fail until the next epoch starts.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, Symbol, Vec};
+
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Budget epoch length unless the admin sets one (30 days)
+pub const DEFAULT_BUDGET_EPOCH_SECS: u64 = 30 * 86400;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum BudgetKey {
+    BudgetEpochSecs,
+    BudgetCategories,  // every category with an envelope
+    Envelope(Symbol),  // category -> cap and spend in the current epoch
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct BudgetEnvelope {
+    pub token: Address,
+    pub cap: i128,  // most paid out per epoch
+    pub epoch: u64,  // epoch `spent` belongs to
+    pub spent: i128,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct BudgetStatus {
+    pub category: Symbol,
+    pub token: Address,
+    pub cap: i128,
+    pub spent: i128,
+    pub remaining: i128,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Cap payments in `category` at `cap` of `token` per budget epoch
+    pub fn set_budget(env: Env, category: Symbol, token: Address, cap: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if cap <= 0 {
+            panic!("Budget must be positive");
+        }
+
+        // Spend so far this epoch carries over unless the token changes
+        let epoch = current_epoch(&env);
+        let spent = match envelope(&env, &category) {
+            Some(envelope) if envelope.token == token && envelope.epoch == epoch => envelope.spent,
+            _ => 0,
+        };
+        env.storage().instance().set(&BudgetKey::Envelope(category.clone()), &BudgetEnvelope { token, cap, epoch, spent });
+
+        let mut categories = categories(&env);
+        if !categories.contains(&category) {
+            categories.push_back(category);
+            env.storage().instance().set(&BudgetKey::BudgetCategories, &categories);
+        }
+        storage::extend_instance(&env);
+    }
+
+    /// Remove a category; payments already scheduled in it can no longer execute
+    pub fn remove_budget(env: Env, category: Symbol) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().remove(&BudgetKey::Envelope(category.clone()));
+        let mut categories = categories(&env);
+        if let Some(index) = categories.first_index_of(&category) {
+            categories.remove(index);
+            env.storage().instance().set(&BudgetKey::BudgetCategories, &categories);
+        }
+    }
+
+    /// Set the length of the budget epoch
+    pub fn set_budget_epoch(env: Env, budget_epoch_secs: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if budget_epoch_secs == 0 {
+            panic!("Epoch must be positive");
+        }
+        env.storage().instance().set(&BudgetKey::BudgetEpochSecs, &budget_epoch_secs);
+    }
+
+    /// Get every category's cap and spend in the current epoch
+    pub fn get_budget_status(env: Env) -> Vec<BudgetStatus> {
+        let epoch = current_epoch(&env);
+        let mut statuses = Vec::new(&env);
+        for category in categories(&env).iter() {
+            if let Some(envelope) = envelope(&env, &category) {
+                let spent = if envelope.epoch == epoch { envelope.spent } else { 0 };
+                statuses.push_back(BudgetStatus {
+                    category,
+                    token: envelope.token,
+                    cap: envelope.cap,
+                    spent,
+                    remaining: envelope.cap - spent,
+                });
+            }
+        }
+        statuses
+    }
+}
+
+fn categories(env: &Env) -> Vec<Symbol> {
+    env.storage().instance().get(&BudgetKey::BudgetCategories).unwrap_or(Vec::new(env))
+}
+
+fn envelope(env: &Env, category: &Symbol) -> Option<BudgetEnvelope> {
+    env.storage().instance().get(&BudgetKey::Envelope(category.clone()))
+}
+
+fn current_epoch(env: &Env) -> u64 {
+    let epoch_secs: u64 = env.storage().instance()
+        .get(&BudgetKey::BudgetEpochSecs)
+        .unwrap_or(DEFAULT_BUDGET_EPOCH_SECS);
+    env.ledger().timestamp() / epoch_secs
+}
+
+/// Refuse a payment in a category that does not exist or pays another token
+pub(crate) fn check_category(env: &Env, category: &Symbol, token: &Address) {
+    match envelope(env, category) {
+        Some(envelope) if envelope.token == *token => {}
+        Some(_) => panic!("Token does not match the budget category"),
+        None => panic!("Unknown budget category"),
+    }
+}
+
+/// Charge `amount` to the category's envelope for the current epoch
+pub(crate) fn charge(env: &Env, category: &Symbol, token: &Address, amount: i128) {
+    check_category(env, category, token);
+    let mut envelope = envelope(env, category).unwrap();
+
+    let epoch = current_epoch(env);
+    if envelope.epoch != epoch {
+        envelope.epoch = epoch;
+        envelope.spent = 0;
+    }
+    if envelope.spent + amount > envelope.cap {
+        panic!("Budget exhausted");
+    }
+    envelope.spent += amount;
+    env.storage().instance().set(&BudgetKey::Envelope(category.clone()), &envelope);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::{symbol_short, BytesN};
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_budget_envelopes() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.set_budget_epoch(&1000);
+        let ops = symbol_short!("ops");
+        vault.client.set_budget(&ops, &token.address, &100_0000000);
+
+        // Payments must name an existing category in its token
+        let vendor = Address::generate(&env);
+        let invoices = [1, 2].map(|n| vault.client.register_invoice(&BytesN::from_array(&env, &[n; 32]), &vendor, &60_0000000, &0));
+        assert!(vault.client.try_schedule_payment(&vendor, &token.address, &60_0000000, &0, &symbol_short!("infra"), &invoices[0]).is_err());
+        assert!(vault.client.try_schedule_payment(&vendor, &Address::generate(&env), &60_0000000, &0, &ops, &invoices[0]).is_err());
+
+        // The second payment would overrun the envelope this epoch
+        let first = vault.client.schedule_payment(&vendor, &token.address, &60_0000000, &0, &ops, &invoices[0]);
+        let second = vault.client.schedule_payment(&vendor, &token.address, &60_0000000, &0, &ops, &invoices[1]);
+        vault.client.execute_payment(&vault.payment_agent, &first);
+        assert!(vault.client.try_execute_payment(&vault.payment_agent, &second).is_err());
+        let status = vault.client.get_budget_status().get(0).unwrap();
+        assert_eq!((status.spent, status.remaining), (60_0000000, 40_0000000));
+
+        // A new epoch refills it
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        vault.client.execute_payment(&vault.payment_agent, &second);
+        assert_eq!(vault.client.get_budget_status().get(0).unwrap().spent, 60_0000000);
+    }
+}
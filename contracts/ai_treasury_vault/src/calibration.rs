@@ -0,0 +1,120 @@
+//! Confidence calibration.
+//!
+//! Each execution that realizes P&L is counted in the bucket of its
+//! signal's confidence (0-9, 10-19, ... 90-100), together with whether it
+//! was profitable. A well-calibrated model wins more often in its higher
+//! buckets; when the hit rates flatten out or invert, its stated confidence
+//! no longer means much and `min_confidence` is filtering on noise.
+
+use soroban_sdk::{contractimpl, contracttype, Env, Vec};
+
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client};
+
+/// Width of a confidence bucket
+pub const CALIBRATION_BUCKET_WIDTH: u32 = 10;
+const BUCKETS: u32 = 100 / CALIBRATION_BUCKET_WIDTH;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum CalibrationKey {
+    Calibration,  // outcomes per confidence bucket
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct CalibrationBucket {
+    pub min_confidence: u32,  // lower bound of the bucket
+    pub outcomes: u32,  // executions that realized P&L
+    pub profitable: u32,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Get realized outcomes per confidence bucket, lowest bucket first
+    pub fn get_calibration(env: Env) -> Vec<CalibrationBucket> {
+        buckets(&env)
+    }
+}
+
+fn buckets(env: &Env) -> Vec<CalibrationBucket> {
+    env.storage().instance()
+        .get(&CalibrationKey::Calibration)
+        .unwrap_or_else(|| {
+            let mut buckets = Vec::new(env);
+            for i in 0..BUCKETS {
+                buckets.push_back(CalibrationBucket {
+                    min_confidence: i * CALIBRATION_BUCKET_WIDTH,
+                    outcomes: 0,
+                    profitable: 0,
+                });
+            }
+            buckets
+        })
+}
+
+/// Count an execution's realized P&L in its confidence bucket
+pub(crate) fn record_outcome(env: &Env, confidence: u32, profit_loss: i128) {
+    if profit_loss == 0 {
+        return;
+    }
+
+    let mut buckets = buckets(env);
+    let index = (confidence / CALIBRATION_BUCKET_WIDTH).min(BUCKETS - 1);
+    let mut bucket = buckets.get(index).unwrap();
+    bucket.outcomes += 1;
+    if profit_loss > 0 {
+        bucket.profitable += 1;
+    }
+    buckets.set(index, bucket);
+    env.storage().instance().set(&CalibrationKey::Calibration, &buckets);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_calibration() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+
+        let trade = |action: TradeAction, confidence: u32, price: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &confidence,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &price, &None);
+        };
+
+        // Opening trades realize nothing and are not counted
+        trade(TradeAction::Buy, 95, 100_0000000);
+        trade(TradeAction::Buy, 95, 100_0000000);
+        assert_eq!(vault.client.get_calibration().get(9).unwrap().outcomes, 0);
+
+        trade(TradeAction::Sell, 100, 110_0000000);
+        trade(TradeAction::Sell, 72, 90_0000000);
+
+        let calibration = vault.client.get_calibration();
+        assert_eq!(calibration.len(), 10);
+        assert_eq!(
+            calibration.get(9).unwrap(),
+            CalibrationBucket { min_confidence: 90, outcomes: 1, profitable: 1 },
+        );
+        assert_eq!(
+            calibration.get(7).unwrap(),
+            CalibrationBucket { min_confidence: 70, outcomes: 1, profitable: 0 },
+        );
+    }
+}
@@ -0,0 +1,245 @@
+//! Timelocked config changes with a risk-agent veto.
+//!
+//! Sensitive settings such as `max_single_trade`, the risk limits, the
+//! approval tiers and quorum, short selling, per-asset trade caps and the
+//! timelock itself are changed only by queueing a `ConfigChange` that
+//! applies after `VaultConfig.timelock_secs`, which can never drop below
+//! `MIN_TIMELOCK_SECS`. While it waits, any risk agent may veto it; a vetoed
+//! change can then only be applied once the governance role (e.g. a DAO
+//! contract acting on a vote) overrides the veto. Governance members are
+//! added through the queue as well, and never include the admin, so the
+//! admin cannot lift a veto on its own.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env, String};
+
+use crate::assets::AssetKey;
+use crate::{roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Shortest timelock the admin can set (24h)
+pub const MIN_TIMELOCK_SECS: u64 = 86400;
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum ConfigChange {
+    MaxSingleTrade(i128),
+    RiskLimits(i32, i32),  // (max_var_95, min_sharpe_ratio)
+    Timelock(u64),
+    GrantGovernance(Address),
+    ApprovalTiers(i128, i128),  // (auto_approve_below, cosign_above); 0 turns a tier off
+    RiskQuorum(u32),
+    ShortSelling(bool, u32),  // (allow_shorting, short_margin_bps)
+    AssetTradeLimit(String, i128),  // (asset, max_amount); 0 removes the cap
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PendingChange {
+    pub change_id: u32,
+    pub change: ConfigChange,
+    pub proposed_at: u64,
+    pub executable_at: u64,
+    pub vetoed_by: Option<Address>,
+    pub veto_overridden: bool,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Queue a config change; it can be applied after the timelock
+    pub fn queue_config_change(env: Env, change: ConfigChange) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        check_change(&env, &config, &change);
+
+        let change_id: u32 = env.storage().instance().get(&DataKey::ChangeCounter).unwrap_or(0) + 1;
+        let now = env.ledger().timestamp();
+        let pending = PendingChange {
+            change_id,
+            change: change.clone(),
+            proposed_at: now,
+            executable_at: now + config.timelock_secs,
+            vetoed_by: None,
+            veto_overridden: false,
+        };
+        env.storage().instance().set(&DataKey::PendingChange(change_id), &pending);
+        env.storage().instance().set(&DataKey::ChangeCounter, &change_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("change"), symbol_short!("queued"), change_id), change);
+
+        change_id
+    }
+
+    /// Apply a queued change once its timelock has passed (admin)
+    pub fn apply_config_change(env: Env, change_id: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let pending = pending_change(&env, change_id);
+        if env.ledger().timestamp() < pending.executable_at {
+            panic!("Timelock has not elapsed");
+        }
+        if pending.vetoed_by.is_some() && !pending.veto_overridden {
+            panic!("Change was vetoed");
+        }
+
+        check_change(&env, &config, &pending.change);
+
+        match pending.change {
+            ConfigChange::MaxSingleTrade(max_single_trade) => {
+                config.max_single_trade = max_single_trade;
+            }
+            ConfigChange::RiskLimits(max_var_95, min_sharpe_ratio) => {
+                config.max_var_95 = max_var_95;
+                config.min_sharpe_ratio = min_sharpe_ratio;
+            }
+            ConfigChange::Timelock(timelock_secs) => {
+                config.timelock_secs = timelock_secs;
+            }
+            ConfigChange::GrantGovernance(account) => {
+                roles::grant(&env, &roles::GOVERNANCE, &account);
+            }
+            ConfigChange::ApprovalTiers(auto_approve_below, cosign_above) => {
+                config.auto_approve_below = auto_approve_below;
+                config.cosign_above = cosign_above;
+            }
+            ConfigChange::RiskQuorum(risk_quorum) => {
+                config.risk_quorum = risk_quorum;
+            }
+            ConfigChange::ShortSelling(allow_shorting, short_margin_bps) => {
+                config.allow_shorting = allow_shorting;
+                config.short_margin_bps = short_margin_bps;
+            }
+            ConfigChange::AssetTradeLimit(asset, max_amount) => {
+                let key = AssetKey::TradeLimit(asset);
+                if max_amount == 0 {
+                    env.storage().instance().remove(&key);
+                } else {
+                    env.storage().instance().set(&key, &max_amount);
+                }
+            }
+        }
+        env.storage().instance().set(&DataKey::Config, &config);
+        env.storage().instance().remove(&DataKey::PendingChange(change_id));
+
+        env.events().publish((symbol_short!("change"), symbol_short!("applied"), change_id), ());
+    }
+
+    /// Drop a queued change (admin)
+    pub fn cancel_config_change(env: Env, change_id: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        pending_change(&env, change_id);
+        env.storage().instance().remove(&DataKey::PendingChange(change_id));
+
+        env.events().publish((symbol_short!("change"), symbol_short!("cancelled"), change_id), ());
+    }
+
+    /// Block a queued change until governance overrides the veto (risk agent)
+    pub fn veto_pending_change(env: Env, caller: Address, change_id: u32) {
+        roles::require_role(&env, &caller, &roles::RISK_AGENT);
+
+        let mut pending = pending_change(&env, change_id);
+        pending.vetoed_by = Some(caller.clone());
+        pending.veto_overridden = false;
+        env.storage().instance().set(&DataKey::PendingChange(change_id), &pending);
+
+        env.events().publish((symbol_short!("change"), symbol_short!("vetoed"), change_id), caller);
+    }
+
+    /// Lift a risk-agent veto after a governance vote (governance role)
+    pub fn override_veto(env: Env, caller: Address, change_id: u32) {
+        roles::require_role(&env, &caller, &roles::GOVERNANCE);
+
+        let mut pending = pending_change(&env, change_id);
+        if pending.vetoed_by.is_none() {
+            panic!("Change is not vetoed");
+        }
+        pending.veto_overridden = true;
+        env.storage().instance().set(&DataKey::PendingChange(change_id), &pending);
+
+        env.events().publish((symbol_short!("change"), symbol_short!("override"), change_id), caller);
+    }
+
+    /// Get a queued config change
+    pub fn get_pending_change(env: Env, change_id: u32) -> Option<PendingChange> {
+        env.storage().instance().get(&DataKey::PendingChange(change_id))
+    }
+}
+
+fn check_change(env: &Env, config: &VaultConfig, change: &ConfigChange) {
+    match change {
+        ConfigChange::Timelock(timelock_secs) if *timelock_secs < MIN_TIMELOCK_SECS => {
+            panic!("Timelock below minimum");
+        }
+        ConfigChange::GrantGovernance(account) if *account == config.admin => {
+            panic!("Admin cannot hold the governance role");
+        }
+        ConfigChange::ApprovalTiers(auto_approve_below, cosign_above) => {
+            if *auto_approve_below < 0 || *cosign_above < 0 {
+                panic!("Tier thresholds must be non-negative");
+            }
+            if *cosign_above != 0 && cosign_above < auto_approve_below {
+                panic!("Co-sign tier below auto-approve tier");
+            }
+        }
+        ConfigChange::RiskQuorum(risk_quorum) if *risk_quorum > roles::members(env, &roles::RISK_AGENT).len() => {
+            panic!("Quorum exceeds number of risk agents");
+        }
+        ConfigChange::ShortSelling(_, short_margin_bps) => {
+            if *short_margin_bps < 10000 {
+                panic!("Short margin must be at least 100%");
+            }
+            if *short_margin_bps < config.maintenance_margin_bps {
+                panic!("Short margin must cover the maintenance margin");
+            }
+        }
+        ConfigChange::AssetTradeLimit(_, max_amount) if *max_amount < 0 => {
+            panic!("Trade limit must be non-negative");
+        }
+        _ => {}
+    }
+}
+
+fn pending_change(env: &Env, change_id: u32) -> PendingChange {
+    env.storage().instance()
+        .get(&DataKey::PendingChange(change_id))
+        .expect("No such pending change")
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_veto_and_governance_override() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let governance = Address::generate(&env);
+        assert!(vault.client.try_grant_role(&roles::GOVERNANCE, &governance).is_err());
+        assert!(vault.client.try_queue_config_change(&ConfigChange::GrantGovernance(vault.admin.clone())).is_err());
+        assert!(vault.client.try_queue_config_change(&ConfigChange::Timelock(0)).is_err());
+        let grant_id = vault.client.queue_config_change(&ConfigChange::GrantGovernance(governance.clone()));
+
+        // Raise max_single_trade 100x
+        let change = ConfigChange::MaxSingleTrade(100 * 1000000_0000000);
+        let change_id = vault.client.queue_config_change(&change);
+        assert!(vault.client.try_apply_config_change(&change_id).is_err());
+
+        vault.client.veto_pending_change(&vault.risk_agent, &change_id);
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert!(vault.client.try_apply_config_change(&change_id).is_err());
+
+        // Only governance can lift the veto
+        assert!(vault.client.try_override_veto(&vault.admin, &change_id).is_err());
+        vault.client.apply_config_change(&grant_id);
+        vault.client.override_veto(&governance, &change_id);
+        vault.client.apply_config_change(&change_id);
+
+        assert_eq!(vault.client.get_config().max_single_trade, 100 * 1000000_0000000);
+        assert!(vault.client.get_pending_change(&change_id).is_none());
+    }
+}
@@ -0,0 +1,139 @@
+//! Clawback handling for regulated assets.
+//!
+//! The issuer of a Stellar asset with clawback enabled can take tokens back
+//! from the vault at any time, which leaves the position book claiming a
+//! balance the vault no longer has. The token interface does not expose the
+//! issuer's flags, so the admin marks such assets; for those,
+//! `reconcile_clawback` compares the book with the token balance and writes
+//! any shortfall off as a clawback. The written-off quantity is booked as a
+//! sale at zero, so NAV and realized P&L both reflect the loss.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Env, String};
+
+use crate::{assets, collateral, pnl, portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ClawbackKey {
+    ClawbackAsset(String),  // asset whose issuer can claw back
+    ClawedBack(String),  // asset -> total quantity written off
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Mark (or unmark) an asset whose issuer has clawback enabled
+    pub fn set_clawback_asset(env: Env, asset: String, enabled: bool) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let key = ClawbackKey::ClawbackAsset(asset);
+        if enabled {
+            env.storage().instance().set(&key, &true);
+        } else {
+            env.storage().instance().remove(&key);
+        }
+    }
+
+    /// Write off any part of a marked asset's position that was clawed back;
+    /// returns the quantity written off
+    pub fn reconcile_clawback(env: Env, asset: String) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let clawed = reconcile(&env, &config, &asset);
+        storage::extend_instance(&env);
+        clawed
+    }
+
+    /// Get the total quantity of an asset lost to clawbacks
+    pub fn get_clawed_back(env: Env, asset: String) -> i128 {
+        env.storage().instance().get(&ClawbackKey::ClawedBack(asset)).unwrap_or(0)
+    }
+}
+
+/// Bring a marked asset's position down to the token balance backing it
+pub(crate) fn reconcile(env: &Env, config: &VaultConfig, asset: &String) -> i128 {
+    if !env.storage().instance().has(&ClawbackKey::ClawbackAsset(asset.clone())) {
+        return 0;
+    }
+    if assets::token_address(env, config, asset).is_none() {
+        return 0;
+    }
+
+    let held = portfolio::position(env, asset);
+    let clawed = held - assets::token_balance(env, config, asset) - collateral::locked(env, asset);
+    if held <= 0 || clawed <= 0 {
+        return 0;
+    }
+
+    if *asset != config.base_asset {
+        pnl::book_fill(env, asset, held, TradeAction::Sell, clawed, 0);
+    }
+    portfolio::adjust_position(env, asset, -clawed);
+
+    let key = ClawbackKey::ClawedBack(asset.clone());
+    let total: i128 = env.storage().instance().get(&key).unwrap_or(0);
+    env.storage().instance().set(&key, &(total + clawed));
+
+    env.events().publish((symbol_short!("clawback"), symbol_short!("detected"), asset.clone()), clawed);
+    clawed
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Events, IssuerFlags};
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::{FromVal, IntoVal};
+
+    #[test]
+    fn test_clawback_reconciliation() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let usdc = String::from_str(&env, "USDC");
+        oracle.set_price(&usdc, &1_0000000);
+
+        let token = env.register_stellar_asset_contract_v2(vault.admin.clone());
+        token.issuer().set_flag(IssuerFlags::ClawbackEnabledFlag);
+        let sac = StellarAssetClient::new(&env, &token.address());
+        vault.client.register_asset(&usdc, &token.address(), &7, &usdc);
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &usdc,
+            &TradeAction::Buy,
+            &100_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &1_0000000, &None);
+        sac.mint(&vault.client.address, &100_0000000);
+
+        // Unmarked assets are left alone
+        sac.clawback(&vault.client.address, &40_0000000);
+        assert_eq!(vault.client.reconcile_clawback(&usdc), 0);
+        assert_eq!(vault.client.get_position(&usdc), 100_0000000);
+
+        vault.client.set_clawback_asset(&usdc, &true);
+        assert_eq!(vault.client.reconcile_clawback(&usdc), 40_0000000);
+        let topics = (symbol_short!("clawback"), symbol_short!("detected"), usdc.clone()).into_val(&env);
+        let detected = env.events().all().iter().any(|(contract, event_topics, data)| {
+            contract == vault.client.address
+                && event_topics == topics
+                && i128::from_val(&env, &data) == 40_0000000
+        });
+        assert!(detected);
+        assert_eq!(vault.client.get_position(&usdc), 60_0000000);
+        assert_eq!(vault.client.get_clawed_back(&usdc), 40_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, -40_0000000);
+        assert_eq!(vault.client.compute_nav(), -40_0000000);
+
+        // Nothing further to write off
+        assert_eq!(vault.client.reconcile_clawback(&usdc), 0);
+    }
+}
@@ -0,0 +1,158 @@
+//! Collateral posted to external protocols.
+//!
+//! Assets the vault posts as collateral stay on the position book, since the
+//! vault still owns them, but are locked: the tokens sit with the protocol
+//! holding them, so trades and withdrawals must not spend them. Each posting
+//! is tracked per asset and holder, and trade execution and withdrawals
+//! refuse to take a position below the quantity locked in it.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, String, Vec};
+
+use crate::portfolio;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum CollateralKey {
+    CollateralPositions,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct CollateralPosition {
+    pub asset: String,
+    pub holder: Address,  // protocol the collateral is posted to
+    pub quantity: i128,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Get every asset posted as collateral and who holds it
+    pub fn get_collateral_positions(env: Env) -> Vec<CollateralPosition> {
+        collateral_positions(&env)
+    }
+}
+
+fn collateral_positions(env: &Env) -> Vec<CollateralPosition> {
+    env.storage().instance()
+        .get(&CollateralKey::CollateralPositions)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Record `amount` of `asset` as posted to `holder`
+pub(crate) fn lock(env: &Env, asset: &String, holder: &Address, amount: i128) {
+    if portfolio::position(env, asset) - locked(env, asset) < amount {
+        panic!("Insufficient position");
+    }
+    adjust(env, asset, holder, amount);
+}
+
+/// Record `amount` of `asset` as returned by `holder`
+pub(crate) fn unlock(env: &Env, asset: &String, holder: &Address, amount: i128) {
+    if amount > posted_to(env, asset, holder) {
+        panic!("Release exceeds posted collateral");
+    }
+    adjust(env, asset, holder, -amount);
+}
+
+fn adjust(env: &Env, asset: &String, holder: &Address, delta: i128) {
+    let mut positions = collateral_positions(env);
+    let index = positions.iter().position(|p| p.asset == *asset && p.holder == *holder);
+    match index {
+        Some(index) => {
+            let mut position = positions.get(index as u32).unwrap();
+            position.quantity += delta;
+            if position.quantity == 0 {
+                positions.remove(index as u32);
+            } else {
+                positions.set(index as u32, position);
+            }
+        }
+        None => positions.push_back(CollateralPosition { asset: asset.clone(), holder: holder.clone(), quantity: delta }),
+    }
+    env.storage().instance().set(&CollateralKey::CollateralPositions, &positions);
+}
+
+/// Quantity of `asset` posted to `holder`
+pub(crate) fn posted_to(env: &Env, asset: &String, holder: &Address) -> i128 {
+    collateral_positions(env).iter()
+        .find(|p| p.asset == *asset && p.holder == *holder)
+        .map_or(0, |p| p.quantity)
+}
+
+/// Whether anything is posted to `holder`
+pub(crate) fn holds_any(env: &Env, holder: &Address) -> bool {
+    collateral_positions(env).iter().any(|p| p.holder == *holder)
+}
+
+/// Quantity of `asset` locked as collateral, on the book but not in the
+/// vault's token balance
+pub(crate) fn locked(env: &Env, asset: &String) -> i128 {
+    collateral_positions(env).iter()
+        .filter(|p| p.asset == *asset)
+        .map(|p| p.quantity)
+        .sum()
+}
+
+/// Quantity of `asset` held and free to spend
+pub(crate) fn available(env: &Env, asset: &String) -> i128 {
+    portfolio::position(env, asset) - locked(env, asset)
+}
+
+/// Refuse to leave less of `asset` on the book than is locked in it
+pub(crate) fn check_unlocked(env: &Env, asset: &String) {
+    let locked = locked(env, asset);
+    if locked > 0 && portfolio::position(env, asset) < locked {
+        panic!("Collateral is locked");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_locked_collateral_cannot_be_spent() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let xlm = String::from_str(&env, "XLM");
+        let market = Address::generate(&env);
+        env.as_contract(&vault.client.address, || lock(&env, &xlm, &market, 900_0000000));
+        let positions = vault.client.get_collateral_positions();
+        assert_eq!(positions.get(0).unwrap(), CollateralPosition { asset: xlm.clone(), holder: market, quantity: 900_0000000 });
+
+        // Only the 100 left unlocked can be withdrawn or traded away
+        assert!(vault.client.try_withdraw(&alice, &200_0000000).is_err());
+        vault.client.withdraw(&alice, &50_0000000);
+
+        let buy = |amount: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None).is_ok()
+        };
+        assert!(!buy(1_0000000));
+        assert!(buy(4000000));
+        assert_eq!(vault.client.get_position(&xlm), 910_0000000);
+    }
+}
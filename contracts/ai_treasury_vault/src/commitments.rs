@@ -0,0 +1,150 @@
+//! Commit-reveal signal submission.
+//!
+//! A trading agent that does not want its intent visible before the vault
+//! can act on it first commits `sha256(xdr(SignalIntent) || salt)`, then
+//! reveals the intent and salt at least `MIN_REVEAL_DELAY_LEDGERS` later.
+//! The reveal goes through the same checks as `submit_trading_signal` and
+//! yields an ordinary signal id. Commitments are temporary and expire with
+//! the signals they would have produced.
+
+use soroban_sdk::{contractimpl, contracttype, xdr::ToXdr, Address, Bytes, BytesN, Env, String};
+
+use crate::models::Provenance;
+use crate::{agents, roles, staking, storage};
+use crate::{new_signal, AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+/// Ledgers that must close between a commitment and its reveal
+pub const MIN_REVEAL_DELAY_LEDGERS: u32 = 1;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum CommitKey {
+    SignalCommit(BytesN<32>),  // commitment hash -> Commitment (temporary)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Commitment {
+    pub committer: Address,
+    pub ledger: u32,  // sequence the commitment was made in
+}
+
+/// The signal fields a commitment hides
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SignalIntent {
+    pub asset: String,
+    pub action: TradeAction,
+    pub amount: i128,
+    pub strategy: String,
+    pub confidence: u32,
+    pub expected_return: i32,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Commit to a signal without revealing it
+    pub fn commit_signal(env: Env, caller: Address, hash: BytesN<32>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::TRADING_AGENT);
+        agents::throttle(&env, &config, &caller);
+        staking::require_bond(&env, &caller);
+
+        let key = CommitKey::SignalCommit(hash);
+        if env.storage().temporary().has(&key) {
+            panic!("Commitment already exists");
+        }
+
+        let commitment = Commitment { committer: caller, ledger: env.ledger().sequence() };
+        env.storage().temporary().set(&key, &commitment);
+        env.storage().temporary().extend_ttl(&key, storage::DAY_IN_LEDGERS, storage::DAY_IN_LEDGERS);
+        storage::extend_instance(&env);
+    }
+
+    /// Reveal a committed signal and submit it; returns the signal id
+    pub fn reveal_signal(
+        env: Env,
+        caller: Address,
+        intent: SignalIntent,
+        salt: BytesN<32>,
+        provenance: Option<Provenance>,
+    ) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::TRADING_AGENT);
+        agents::throttle(&env, &config, &caller);
+        staking::require_bond(&env, &caller);
+
+        let key = CommitKey::SignalCommit(commitment_hash(&env, &intent, &salt));
+        let commitment: Commitment = env.storage().temporary().get(&key).expect("No matching commitment");
+        if commitment.committer != caller {
+            panic!("Commitment belongs to another agent");
+        }
+        if env.ledger().sequence() < commitment.ledger + MIN_REVEAL_DELAY_LEDGERS {
+            panic!("Reveal too early");
+        }
+        env.storage().temporary().remove(&key);
+
+        let signal_id = new_signal(
+            &env,
+            &config,
+            &caller,
+            intent.asset,
+            intent.action,
+            intent.amount,
+            intent.strategy,
+            intent.confidence,
+            intent.expected_return,
+            provenance,
+        );
+        storage::extend_instance(&env);
+
+        signal_id
+    }
+}
+
+/// `sha256(xdr(intent) || salt)`
+pub(crate) fn commitment_hash(env: &Env, intent: &SignalIntent, salt: &BytesN<32>) -> BytesN<32> {
+    let mut preimage = intent.clone().to_xdr(env);
+    preimage.append(&Bytes::from(salt.clone()));
+    env.crypto().sha256(&preimage).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_commit_reveal() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let intent = SignalIntent {
+            asset: String::from_str(&env, "BTC"),
+            action: TradeAction::Buy,
+            amount: 100000,
+            strategy: String::from_str(&env, "LSTM"),
+            confidence: 85,
+            expected_return: 250,
+        };
+        let salt = BytesN::from_array(&env, &[7; 32]);
+        let hash = commitment_hash(&env, &intent, &salt);
+
+        vault.client.commit_signal(&vault.trading_agent, &hash);
+        assert!(vault.client.try_commit_signal(&vault.trading_agent, &hash).is_err());
+
+        // Not in the same ledger, and only with the committed fields
+        assert!(vault.client.try_reveal_signal(&vault.trading_agent, &intent, &salt, &None).is_err());
+        env.ledger().with_mut(|l| l.sequence_number += MIN_REVEAL_DELAY_LEDGERS);
+        let altered = SignalIntent { amount: 200000, ..intent.clone() };
+        assert!(vault.client.try_reveal_signal(&vault.trading_agent, &altered, &salt, &None).is_err());
+
+        let signal_id = vault.client.reveal_signal(&vault.trading_agent, &intent, &salt, &None);
+        let trade_id = vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        assert_eq!(vault.client.get_trade(&trade_id).amount, 100000);
+
+        // A commitment can only be revealed once
+        assert!(vault.client.try_reveal_signal(&vault.trading_agent, &intent, &salt, &None).is_err());
+    }
+}
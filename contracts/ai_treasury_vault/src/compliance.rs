@@ -0,0 +1,123 @@
+//! Withdrawal address allowlist for compliance mode.
+//!
+//! With `VaultConfig.compliance_mode` on, the vault only sends tokens to
+//! addresses the admin has registered: withdrawals and keeper payouts to
+//! anyone else are refused. Adding an address is timelocked by
+//! `VaultConfig.timelock_secs` so a compromised admin key cannot add and
+//! drain to a new address in one step; removing one takes effect at once.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env};
+
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ComplianceKey {
+    AllowedRecipient(Address),
+    PendingRecipient(Address),  // address -> time it can be confirmed
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Only pay out to allowlisted addresses
+    pub fn set_compliance_mode(env: Env, enabled: bool) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.compliance_mode = enabled;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Propose an address for the allowlist; returns when it can be confirmed
+    pub fn propose_recipient(env: Env, recipient: Address) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let executable_at = env.ledger().timestamp() + config.timelock_secs;
+        env.storage().instance().set(&ComplianceKey::PendingRecipient(recipient.clone()), &executable_at);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("recipient"), symbol_short!("proposed")), recipient);
+        executable_at
+    }
+
+    /// Add a proposed address to the allowlist once its timelock has passed
+    pub fn confirm_recipient(env: Env, recipient: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let pending = ComplianceKey::PendingRecipient(recipient.clone());
+        let executable_at: u64 = env.storage().instance().get(&pending).expect("Recipient not proposed");
+        if env.ledger().timestamp() < executable_at {
+            panic!("Timelock not expired");
+        }
+
+        env.storage().instance().remove(&pending);
+        env.storage().instance().set(&ComplianceKey::AllowedRecipient(recipient.clone()), &true);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("recipient"), symbol_short!("added")), recipient);
+    }
+
+    /// Remove an address from the allowlist (or drop its proposal)
+    pub fn remove_recipient(env: Env, recipient: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().remove(&ComplianceKey::PendingRecipient(recipient.clone()));
+        env.storage().instance().remove(&ComplianceKey::AllowedRecipient(recipient.clone()));
+
+        env.events().publish((symbol_short!("recipient"), symbol_short!("removed")), recipient);
+    }
+
+    /// Whether an address is on the allowlist
+    pub fn is_allowed_recipient(env: Env, recipient: Address) -> bool {
+        env.storage().instance().has(&ComplianceKey::AllowedRecipient(recipient))
+    }
+}
+
+/// Refuse to pay `recipient` in compliance mode unless it is allowlisted
+pub(crate) fn check_recipient(env: &Env, config: &VaultConfig, recipient: &Address) {
+    if config.compliance_mode
+        && !env.storage().instance().has(&ComplianceKey::AllowedRecipient(recipient.clone()))
+    {
+        panic!("Recipient not allowlisted");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_compliance_allowlist() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &100_0000000);
+        vault.client.deposit(&alice, &100_0000000);
+        vault.client.set_compliance_mode(&true);
+
+        // Not allowlisted yet, and the proposal waits out the timelock
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_err());
+        let executable_at = vault.client.propose_recipient(&alice);
+        assert!(vault.client.try_confirm_recipient(&alice).is_err());
+
+        env.ledger().with_mut(|l| l.timestamp = executable_at);
+        vault.client.confirm_recipient(&alice);
+        assert!(vault.client.is_allowed_recipient(&alice));
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_ok());
+
+        vault.client.remove_recipient(&alice);
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_err());
+
+        vault.client.set_compliance_mode(&false);
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_ok());
+    }
+}
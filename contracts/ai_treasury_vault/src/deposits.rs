@@ -0,0 +1,649 @@
+//! Share-based deposits and withdrawals in the base token.
+//!
+//! Depositors receive shares priced at the current NAV, so gains and losses
+//! made by the trading agents accrue pro rata to every share holder. Shares
+//! from each deposit are locked for `VaultConfig.lockup_secs` and redeemed
+//! oldest first. Shares redeemed before the lock-up plus the exit-fee window
+//! has passed pay `exit_fee_bps`; the fee is not paid out, so it accrues to
+//! the remaining share holders through NAV. Value left behind once every
+//! share has been redeemed (exit fees, dust, late P&L) is credited to the
+//! admin as shares on the next deposit, so the first depositor into an
+//! emptied vault cannot capture it.
+//!
+//! Lock-ups stop applying while the admin's dead man's switch has fired
+//! (see `liveness`).
+//!
+//! Other contracts can hold shares too. A contract depositing on its own
+//! behalf satisfies `from.require_auth()` by being the direct invoker, but it
+//! must authorize the nested token `transfer` with
+//! `authorize_as_current_contract`. With `VaultConfig.integrator_allowlist`
+//! set, only contracts holding the integrator role may deposit.
+//!
+//! Permissioned deployments can set `VaultConfig.eligibility_contract` to a
+//! registry (e.g. a KYC provider) implementing `EligibilityRegistry`; every
+//! depositor must then pass its `is_eligible` check before shares are minted.
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, Vec};
+
+use crate::{assets, clawback, collateral, compliance, fees, flows, guard, liveness, portfolio, roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// External registry deciding who may hold shares
+#[allow(dead_code)]
+#[contractclient(name = "EligibilityRegistryClient")]
+pub trait EligibilityRegistry {
+    fn is_eligible(env: Env, address: Address) -> bool;
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum WaiverKey {
+    HaltEpoch,  // halts ended so far; lock-up waivers hold the one they were granted in
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct DepositLot {
+    pub shares: i128,
+    pub deposited_at: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the token contract deposits and withdrawals are made in
+    pub fn set_base_token(env: Env, token: Address) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.base_token = Some(token);
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Update deposit limits (caps of 0 disable the cap)
+    pub fn set_deposit_limits(
+        env: Env,
+        min_deposit: i128,
+        max_deposit_per_address: i128,
+        max_total_deposits: i128,
+    ) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if min_deposit < 0 || max_deposit_per_address < 0 || max_total_deposits < 0 {
+            panic!("Deposit limits must be non-negative");
+        }
+
+        config.min_deposit = min_deposit;
+        config.max_deposit_per_address = max_deposit_per_address;
+        config.max_total_deposits = max_total_deposits;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Set the lock-up period applied to newly deposited capital
+    pub fn set_lockup_period(env: Env, lockup_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.lockup_secs = lockup_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Set the early-withdrawal fee and the cooldown after the lock-up it applies to
+    pub fn set_exit_fee(env: Env, exit_fee_bps: u32, exit_fee_window_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if exit_fee_bps > 10000 {
+            panic!("Exit fee exceeds 100%");
+        }
+
+        config.exit_fee_bps = exit_fee_bps;
+        config.exit_fee_window_secs = exit_fee_window_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Let a holder redeem locked shares for the rest of the current halt
+    /// (admin, only while halted)
+    pub fn waive_lockup(env: Env, holder: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if !config.halted {
+            panic!("Lock-up can only be waived while halted");
+        }
+
+        storage::set_persistent(&env, &DataKey::LockupWaived(holder), &halt_epoch(&env));
+    }
+
+    /// Restrict contract depositors to holders of the integrator role
+    pub fn set_integrator_allowlist(env: Env, enabled: bool) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.integrator_allowlist = enabled;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Check depositors against an external registry (None removes it)
+    pub fn set_eligibility_contract(env: Env, eligibility_contract: Option<Address>) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.eligibility_contract = eligibility_contract;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Deposit base tokens and mint shares at the current NAV
+    pub fn deposit(env: Env, from: Address, amount: i128) -> i128 {
+        from.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("deposit"));
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        if config.halted {
+            panic!("System is halted");
+        }
+
+        if config.integrator_allowlist
+            && is_contract(&from)
+            && !roles::members(&env, &roles::INTEGRATOR).contains(&from)
+        {
+            panic!("Contract depositor not allowlisted");
+        }
+
+        if let Some(registry) = &config.eligibility_contract {
+            if !EligibilityRegistryClient::new(&env, registry).is_eligible(&from) {
+                panic!("Depositor not eligible");
+            }
+        }
+
+        let base_token = match assets::token_address(&env, &config, &config.base_asset) {
+            Some(token) => token,
+            None => panic!("Base token not configured"),
+        };
+
+        if amount <= 0 || amount < config.min_deposit {
+            panic!("Deposit below minimum");
+        }
+
+        let deposited = deposited_of(&env, &from) + amount;
+        if config.max_deposit_per_address > 0 && deposited > config.max_deposit_per_address {
+            panic!("Deposit exceeds per-address cap");
+        }
+
+        let total_deposited: i128 = env.storage().instance()
+            .get(&DataKey::TotalDeposited).unwrap_or(0) + amount;
+        if config.max_total_deposits > 0 && total_deposited > config.max_total_deposits {
+            panic!("Deposit exceeds vault cap");
+        }
+
+        // Price shares against NAV before the new capital arrives
+        let mut total_shares = total_shares(&env);
+        if total_shares == 0 {
+            let leftover = portfolio::nav(&env, &config);
+            if leftover > 0 {
+                credit_shares(&env, &config.admin, leftover);
+                total_shares = leftover;
+            }
+        }
+        let shares = if total_shares == 0 {
+            amount
+        } else {
+            let nav = portfolio::nav(&env, &config);
+            if nav <= 0 {
+                panic!("Vault NAV is not positive");
+            }
+            amount * total_shares / nav
+        };
+
+        if shares <= 0 {
+            panic!("Deposit too small to mint shares");
+        }
+
+        portfolio::adjust_position(&env, &config.base_asset, amount);
+        flows::record_flow(&env, &config, amount);
+
+        let mut lots = lots_of(&env, &from);
+        lots.push_back(DepositLot {
+            shares,
+            deposited_at: env.ledger().timestamp(),
+        });
+
+        storage::set_persistent(&env, &DataKey::Shares(from.clone()), &(shares_of(&env, &from) + shares));
+        storage::set_persistent(&env, &DataKey::Deposited(from.clone()), &deposited);
+        storage::set_persistent(&env, &DataKey::DepositLots(from.clone()), &lots);
+        env.storage().instance().set(&DataKey::TotalShares, &(total_shares + shares));
+        env.storage().instance().set(&DataKey::TotalDeposited, &total_deposited);
+        storage::extend_instance(&env);
+
+        token::Client::new(&env, &base_token)
+            .transfer(&from, &env.current_contract_address(), &amount);
+
+        shares
+    }
+
+    /// Burn shares and withdraw their NAV value in base tokens, less any exit fee
+    pub fn withdraw(env: Env, from: Address, shares: i128) -> i128 {
+        from.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("withdraw"));
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        let base_token = match assets::token_address(&env, &config, &config.base_asset) {
+            Some(token) => token,
+            None => panic!("Base token not configured"),
+        };
+
+        compliance::check_recipient(&env, &config, &from);
+
+        let holder_shares = shares_of(&env, &from);
+        if shares <= 0 || shares > holder_shares {
+            panic!("Insufficient shares");
+        }
+
+        let waived = liveness::admin_inactive(&env, &config) || lockup_waived(&env, &config, &from);
+        if !waived && shares > unlocked_shares(&env, &config, &from) {
+            panic!("Shares are locked");
+        }
+
+        let (lots, early_shares) = consume_lots(&env, &config, &from, shares);
+
+        // Price shares on what the vault still holds
+        clawback::reconcile(&env, &config, &config.base_asset);
+
+        let total_shares = total_shares(&env);
+        let nav = portfolio::nav(&env, &config);
+        let gross = shares * nav / total_shares;
+        let fee = early_shares * nav / total_shares * config.exit_fee_bps as i128 / 10000;
+        let amount = gross - fee;
+
+        if amount <= 0 {
+            panic!("Nothing to withdraw");
+        }
+
+        if collateral::available(&env, &config.base_asset) < amount
+            || assets::token_balance(&env, &config, &config.base_asset) < amount
+        {
+            panic!("Insufficient base asset liquidity");
+        }
+
+        // Release principal pro rata so caps reflect capital still at work
+        let deposited = deposited_of(&env, &from);
+        let released = deposited * shares / holder_shares;
+        let total_deposited: i128 = env.storage().instance()
+            .get(&DataKey::TotalDeposited).unwrap_or(0);
+
+        storage::set_persistent(&env, &DataKey::Shares(from.clone()), &(holder_shares - shares));
+        storage::set_persistent(&env, &DataKey::Deposited(from.clone()), &(deposited - released));
+        storage::set_persistent(&env, &DataKey::DepositLots(from.clone()), &lots);
+        env.storage().instance().set(&DataKey::TotalShares, &(total_shares - shares));
+        env.storage().instance().set(&DataKey::TotalDeposited, &(total_deposited - released));
+        let base_before = portfolio::position(&env, &config.base_asset);
+        portfolio::adjust_position(&env, &config.base_asset, -amount);
+        fees::check_reserve(&env, &config, base_before);
+        flows::record_flow(&env, &config, -amount);
+        storage::extend_instance(&env);
+
+        token::Client::new(&env, &base_token)
+            .transfer(&env.current_contract_address(), &from, &amount);
+
+        amount
+    }
+
+    /// Get the share balance of a depositor
+    pub fn get_shares(env: Env, holder: Address) -> i128 {
+        shares_of(&env, &holder)
+    }
+
+    /// Get the shares a holder can redeem now without the lock-up blocking
+    pub fn get_unlocked_shares(env: Env, holder: Address) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        unlocked_shares(&env, &config, &holder)
+    }
+
+    /// Get the total number of shares outstanding
+    pub fn get_total_shares(env: Env) -> i128 {
+        total_shares(&env)
+    }
+}
+
+pub(crate) fn shares_of(env: &Env, holder: &Address) -> i128 {
+    storage::get_persistent(env, &DataKey::Shares(holder.clone()))
+        .unwrap_or(0)
+}
+
+pub(crate) fn total_shares(env: &Env) -> i128 {
+    env.storage().instance()
+        .get(&DataKey::TotalShares)
+        .unwrap_or(0)
+}
+
+fn lots_of(env: &Env, holder: &Address) -> Vec<DepositLot> {
+    storage::get_persistent(env, &DataKey::DepositLots(holder.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+fn unlocked_shares(env: &Env, config: &VaultConfig, holder: &Address) -> i128 {
+    let now = env.ledger().timestamp();
+    let mut unlocked = 0;
+    for lot in lots_of(env, holder).iter() {
+        if lot.deposited_at + config.lockup_secs <= now {
+            unlocked += lot.shares;
+        }
+    }
+    unlocked
+}
+
+/// Mint shares that were not paid for with a deposit, redeemable at once
+fn credit_shares(env: &Env, holder: &Address, shares: i128) {
+    let mut lots = lots_of(env, holder);
+    lots.push_back(DepositLot { shares, deposited_at: 0 });
+    storage::set_persistent(env, &DataKey::Shares(holder.clone()), &(shares_of(env, holder) + shares));
+    storage::set_persistent(env, &DataKey::DepositLots(holder.clone()), &lots);
+    env.storage().instance().set(&DataKey::TotalShares, &(total_shares(env) + shares));
+}
+
+/// Remove `shares` from a holder's lots, oldest deposit first
+///
+/// Also returns how many of the removed shares are still inside the
+/// lock-up plus exit-fee window.
+fn consume_lots(
+    env: &Env,
+    config: &VaultConfig,
+    holder: &Address,
+    shares: i128,
+) -> (Vec<DepositLot>, i128) {
+    let fee_cutoff = config.lockup_secs + config.exit_fee_window_secs;
+    let now = env.ledger().timestamp();
+    let mut remaining = shares;
+    let mut early_shares = 0;
+    let mut lots = lots_of(env, holder);
+
+    while remaining > 0 {
+        let mut lot = match lots.first() {
+            Some(lot) => lot,
+            None => break,
+        };
+        let taken = if lot.shares > remaining { remaining } else { lot.shares };
+        if lot.deposited_at + fee_cutoff > now {
+            early_shares += taken;
+        }
+
+        if lot.shares > remaining {
+            lot.shares -= remaining;
+            lots.set(0, lot);
+            break;
+        }
+        remaining -= lot.shares;
+        lots.pop_front();
+    }
+
+    (lots, early_shares)
+}
+
+/// Whether an address is a contract (`C...` strkey) rather than an account
+fn is_contract(address: &Address) -> bool {
+    let strkey = address.to_string();
+    let mut buf = [0u8; 56];
+    if strkey.len() as usize != buf.len() {
+        return false;
+    }
+    strkey.copy_into_slice(&mut buf);
+    buf[0] == b'C'
+}
+
+fn deposited_of(env: &Env, holder: &Address) -> i128 {
+    storage::get_persistent(env, &DataKey::Deposited(holder.clone()))
+        .unwrap_or(0)
+}
+
+/// Bumped whenever trading resumes, lapsing the lock-up waivers of the halt
+fn halt_epoch(env: &Env) -> u32 {
+    env.storage().instance().get(&WaiverKey::HaltEpoch).unwrap_or(0)
+}
+
+pub(crate) fn end_halt(env: &Env) {
+    env.storage().instance().set(&WaiverKey::HaltEpoch, &(halt_epoch(env) + 1));
+}
+
+/// Whether the admin waived `holder`'s lock-up during the current halt
+fn lockup_waived(env: &Env, config: &VaultConfig, holder: &Address) -> bool {
+    config.halted
+        && storage::get_persistent::<u32>(env, &DataKey::LockupWaived(holder.clone())) == Some(halt_epoch(env))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::auth::{ContractContext, InvokerContractAuthEntry, SubContractInvocation};
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::{contract, vec, IntoVal, Symbol};
+
+    #[test]
+    fn test_deposit_and_withdraw() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+
+        let shares = vault.client.deposit(&alice, &400_0000000);
+        assert_eq!(shares, 400_0000000);
+        assert_eq!(vault.client.compute_nav(), 400_0000000);
+
+        let withdrawn = vault.client.withdraw(&alice, &100_0000000);
+        assert_eq!(withdrawn, 100_0000000);
+        assert_eq!(vault.client.get_shares(&alice), 300_0000000);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&alice), 700_0000000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit below minimum")]
+    fn test_minimum_deposit() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_deposit_limits(&10_0000000, &0, &0);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &5_0000000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit exceeds per-address cap")]
+    fn test_per_address_cap() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_deposit_limits(&0, &500_0000000, &2000_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &300_0000000);
+        vault.client.deposit(&alice, &300_0000000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Deposit exceeds vault cap")]
+    fn test_global_cap() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_deposit_limits(&0, &0, &500_0000000);
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        token.mint(&bob, &1000_0000000);
+        vault.client.deposit(&alice, &300_0000000);
+        vault.client.deposit(&bob, &300_0000000);
+    }
+
+    #[test]
+    fn test_lockup_period() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_lockup_period(&86400);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &100_0000000);
+
+        env.ledger().with_mut(|l| l.timestamp += 43200);
+        vault.client.deposit(&alice, &100_0000000);
+        assert_eq!(vault.client.get_unlocked_shares(&alice), 0);
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_err());
+
+        // Only the first deposit has matured
+        env.ledger().with_mut(|l| l.timestamp += 43200);
+        assert_eq!(vault.client.get_unlocked_shares(&alice), 100_0000000);
+        assert!(vault.client.try_withdraw(&alice, &150_0000000).is_err());
+        vault.client.withdraw(&alice, &100_0000000);
+        assert_eq!(vault.client.get_unlocked_shares(&alice), 0);
+
+        // Admin may release the rest while trading is halted, and only then
+        vault.client.deposit(&alice, &100_0000000);
+        vault.client.emergency_halt(&vault.admin);
+        vault.client.waive_lockup(&alice);
+        vault.client.withdraw(&alice, &50_0000000);
+        vault.client.resume_trading();
+        assert!(vault.client.try_withdraw(&alice, &50_0000000).is_err());
+        vault.client.emergency_halt(&vault.admin);
+        assert!(vault.client.try_withdraw(&alice, &50_0000000).is_err());
+        vault.client.waive_lockup(&alice);
+        vault.client.withdraw(&alice, &150_0000000);
+        assert_eq!(vault.client.get_shares(&alice), 0);
+    }
+
+    #[test]
+    fn test_exit_fee_accrues_to_holders() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_exit_fee(&100, &86400);  // 1% within a day
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        token.mint(&bob, &1000_0000000);
+        vault.client.deposit(&alice, &100_0000000);
+        vault.client.deposit(&bob, &100_0000000);
+
+        // Alice leaves early and pays 1 token, which stays with Bob's shares
+        assert_eq!(vault.client.withdraw(&alice, &100_0000000), 99_0000000);
+        assert_eq!(vault.client.compute_nav(), 101_0000000);
+
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert_eq!(vault.client.withdraw(&bob, &100_0000000), 101_0000000);
+    }
+
+    #[test]
+    fn test_leftover_nav_goes_to_admin() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_exit_fee(&100, &86400);
+
+        let alice = Address::generate(&env);
+        let bob = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        token.mint(&bob, &1000_0000000);
+
+        // Alice's exit fee stays in the vault after every share is burned
+        vault.client.deposit(&alice, &100_0000000);
+        vault.client.withdraw(&alice, &100_0000000);
+        assert_eq!(vault.client.get_total_shares(), 0);
+        assert_eq!(vault.client.compute_nav(), 1_0000000);
+
+        // Bob's shares are worth his deposit, not the leftover too
+        assert_eq!(vault.client.deposit(&bob, &100_0000000), 100_0000000);
+        assert_eq!(vault.client.get_shares(&vault.admin), 1_0000000);
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert_eq!(vault.client.withdraw(&bob, &100_0000000), 100_0000000);
+        assert_eq!(vault.client.withdraw(&vault.admin, &1_0000000), 1_0000000);
+    }
+
+    /// Integrator protocol that deposits its own tokens into the vault
+    #[contract]
+    pub struct MockIntegrator;
+
+    #[contractimpl]
+    impl MockIntegrator {
+        pub fn deposit_into(env: Env, vault: Address, token: Address, amount: i128) -> i128 {
+            let me = env.current_contract_address();
+            env.authorize_as_current_contract(vec![
+                &env,
+                InvokerContractAuthEntry::Contract(SubContractInvocation {
+                    context: ContractContext {
+                        contract: token,
+                        fn_name: Symbol::new(&env, "transfer"),
+                        args: (me.clone(), vault.clone(), amount).into_val(&env),
+                    },
+                    sub_invocations: vec![&env],
+                }),
+            ]);
+            AITreasuryVaultV2Client::new(&env, &vault).deposit(&me, &amount)
+        }
+    }
+
+    #[test]
+    fn test_contract_depositor() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let integrator = env.register_contract(None, MockIntegrator);
+        let integrator_client = MockIntegratorClient::new(&env, &integrator);
+        token.mint(&integrator, &100_0000000);
+
+        vault.client.set_integrator_allowlist(&true);
+        assert!(integrator_client
+            .try_deposit_into(&vault.client.address, &token.address, &50_0000000)
+            .is_err());
+        vault.client.grant_role(&roles::INTEGRATOR, &integrator);
+
+        // No mocked auths: the integrator authorizes as the invoking contract
+        env.set_auths(&[]);
+        let shares = integrator_client.deposit_into(&vault.client.address, &token.address, &50_0000000);
+        assert_eq!(shares, 50_0000000);
+        assert_eq!(vault.client.get_shares(&integrator), 50_0000000);
+    }
+
+    /// KYC registry stand-in with an explicit list of eligible addresses
+    #[contract]
+    pub struct MockRegistry;
+
+    #[contractimpl]
+    impl MockRegistry {
+        pub fn approve(env: Env, address: Address) {
+            env.storage().instance().set(&address, &true);
+        }
+
+        pub fn is_eligible(env: Env, address: Address) -> bool {
+            env.storage().instance().has(&address)
+        }
+    }
+
+    #[test]
+    fn test_eligibility_registry() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let registry = env.register_contract(None, MockRegistry);
+        vault.client.set_eligibility_contract(&Some(registry.clone()));
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &100_0000000);
+        assert!(vault.client.try_deposit(&alice, &50_0000000).is_err());
+
+        MockRegistryClient::new(&env, &registry).approve(&alice);
+        assert_eq!(vault.client.deposit(&alice, &50_0000000), 50_0000000);
+
+        let bob = Address::generate(&env);
+        token.mint(&bob, &100_0000000);
+        vault.client.set_eligibility_contract(&None);
+        assert!(vault.client.try_deposit(&bob, &50_0000000).is_ok());
+    }
+}
@@ -0,0 +1,327 @@
+//! Fee schedule and trade cost estimates.
+//!
+//! Swap costs come from the fee tiers registered for each DEX the vault can
+//! route through; the cheapest tier is assumed, falling back to
+//! `VaultConfig.swap_fee_bps` when none is registered. Management and
+//! performance fees accrue from the moment the schedule is set: management
+//! pro rata over time, performance on NAV per share above the high-water
+//! mark. `estimate_trade_cost` reports the share of those accruals carried
+//! by the capital a trade would move. The keeper fee is paid to whoever
+//! triggers scheduled upkeep such as `maybe_snapshot`.
+//!
+//! An operating reserve of the base asset keeps fees and keeper rewards
+//! payable: no withdrawal or trade may take the base position below it,
+//! though trades that add to a position already under it still go through.
+
+use soroban_sdk::{contractimpl, contracttype, token, Address, Env, String, Vec};
+
+use crate::{assets, benchmark, compliance, portfolio};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+pub const SECONDS_PER_YEAR: u64 = 365 * 86400;
+
+#[derive(Clone)]
+#[contracttype]
+pub enum FeeKey {
+    DexFeeTier(Address),  // DEX -> swap fee in bps
+    Dexes,
+    Schedule,
+    Accrual,
+    OperatingReserve,  // base-asset units withdrawals and trades must leave
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct FeeSchedule {
+    pub management_fee_bps: u32,  // per year, on NAV
+    pub performance_fee_bps: u32,  // on gains above the high-water mark
+    pub keeper_fee: i128,  // flat, base-asset units per execution
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct FeeAccrual {
+    pub since: u64,  // management fee accrues from here
+    pub high_water_mark: i128,  // NAV per share, scaled by 1e7
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct TradeCost {
+    pub swap_fee: i128,
+    pub keeper_fee: i128,
+    pub management_fee: i128,
+    pub performance_fee: i128,
+    pub total: i128,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the swap fee assumed when no DEX fee tier is registered
+    pub fn set_swap_fee(env: Env, swap_fee_bps: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if swap_fee_bps > 10000 {
+            panic!("Swap fee exceeds 100%");
+        }
+
+        config.swap_fee_bps = swap_fee_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Register a DEX and the swap fee tier it charges, or update the tier
+    pub fn register_dex_fee_tier(env: Env, dex: Address, fee_bps: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if fee_bps > 10000 {
+            panic!("Swap fee exceeds 100%");
+        }
+
+        let mut dexes = dex_list(&env);
+        if !dexes.contains(&dex) {
+            dexes.push_back(dex.clone());
+            env.storage().instance().set(&FeeKey::Dexes, &dexes);
+        }
+        env.storage().instance().set(&FeeKey::DexFeeTier(dex), &fee_bps);
+    }
+
+    /// Get the swap fee tier registered for a DEX
+    pub fn get_dex_fee_tier(env: Env, dex: Address) -> Option<u32> {
+        env.storage().instance().get(&FeeKey::DexFeeTier(dex))
+    }
+
+    /// Set the base-asset reserve withdrawals and trades may not breach (0 disables it)
+    pub fn set_operating_reserve(env: Env, reserve: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if reserve < 0 {
+            panic!("Reserve must be non-negative");
+        }
+        env.storage().instance().set(&FeeKey::OperatingReserve, &reserve);
+    }
+
+    /// Get the base-asset operating reserve
+    pub fn get_operating_reserve(env: Env) -> i128 {
+        operating_reserve(&env)
+    }
+
+    /// Set the management, performance and keeper fees; accruals restart now
+    pub fn set_fee_schedule(env: Env, schedule: FeeSchedule) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if schedule.management_fee_bps > 10000 || schedule.performance_fee_bps > 10000 {
+            panic!("Fee exceeds 100%");
+        }
+        if schedule.keeper_fee < 0 {
+            panic!("Keeper fee must be non-negative");
+        }
+
+        let accrual = FeeAccrual {
+            since: env.ledger().timestamp(),
+            high_water_mark: benchmark::share_price(&env, portfolio::nav(&env, &config)),
+        };
+        env.storage().instance().set(&FeeKey::Schedule, &schedule);
+        env.storage().instance().set(&FeeKey::Accrual, &accrual);
+    }
+
+    /// Get the fee schedule
+    pub fn get_fee_schedule(env: Env) -> FeeSchedule {
+        fee_schedule(&env)
+    }
+
+    /// Estimate what trading `amount` of `asset` would cost, in the base asset
+    pub fn estimate_trade_cost(env: Env, asset: String, amount: i128) -> TradeCost {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let schedule = fee_schedule(&env);
+        let value = portfolio::value_of(&env, &config, &asset, amount.abs());
+
+        let swap_fee = value * swap_fee_bps(&env, &config) as i128 / 10000;
+        let (management_fee, performance_fee) = match accrual(&env) {
+            Some(accrual) => {
+                let elapsed = env.ledger().timestamp() - accrual.since;
+                let management_fee = value * schedule.management_fee_bps as i128 * elapsed as i128
+                    / (10000 * SECONDS_PER_YEAR as i128);
+
+                let share_price = benchmark::share_price(&env, portfolio::nav(&env, &config));
+                let gain = (share_price - accrual.high_water_mark).max(0);
+                let performance_fee = value * gain / share_price
+                    * schedule.performance_fee_bps as i128 / 10000;
+
+                (management_fee, performance_fee)
+            }
+            None => (0, 0),
+        };
+
+        TradeCost {
+            swap_fee,
+            keeper_fee: schedule.keeper_fee,
+            management_fee,
+            performance_fee,
+            total: swap_fee + schedule.keeper_fee + management_fee + performance_fee,
+        }
+    }
+}
+
+fn dex_list(env: &Env) -> Vec<Address> {
+    env.storage().instance()
+        .get(&FeeKey::Dexes)
+        .unwrap_or(Vec::new(env))
+}
+
+fn fee_schedule(env: &Env) -> FeeSchedule {
+    env.storage().instance()
+        .get(&FeeKey::Schedule)
+        .unwrap_or(FeeSchedule { management_fee_bps: 0, performance_fee_bps: 0, keeper_fee: 0 })
+}
+
+fn accrual(env: &Env) -> Option<FeeAccrual> {
+    env.storage().instance().get(&FeeKey::Accrual)
+}
+
+pub(crate) fn operating_reserve(env: &Env) -> i128 {
+    env.storage().instance().get(&FeeKey::OperatingReserve).unwrap_or(0)
+}
+
+/// Refuse a change that lowered the base position from `before` to below
+/// the operating reserve
+pub(crate) fn check_reserve(env: &Env, config: &VaultConfig, before: i128) {
+    let reserve = operating_reserve(env);
+    let after = portfolio::position(env, &config.base_asset);
+    if reserve > 0 && after < before && after < reserve {
+        panic!("Operating reserve breached");
+    }
+}
+
+/// Pay the keeper fee to `keeper` out of the base asset
+pub(crate) fn pay_keeper(env: &Env, config: &VaultConfig, keeper: &Address) {
+    let fee = fee_schedule(env).keeper_fee;
+    if fee == 0 {
+        return;
+    }
+    compliance::check_recipient(env, config, keeper);
+
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    portfolio::adjust_position(env, &config.base_asset, -fee);
+    token::Client::new(env, &base_token).transfer(&env.current_contract_address(), keeper, &fee);
+}
+
+/// Registered DEX with the cheapest fee tier, and that tier
+pub(crate) fn cheapest_dex(env: &Env) -> Option<(Address, u32)> {
+    let mut best: Option<(Address, u32)> = None;
+    for dex in dex_list(env).iter() {
+        let fee_bps: u32 = env.storage().instance().get(&FeeKey::DexFeeTier(dex.clone())).unwrap();
+        if best.as_ref().is_none_or(|(_, best)| fee_bps < *best) {
+            best = Some((dex, fee_bps));
+        }
+    }
+    best
+}
+
+/// Cheapest registered DEX fee tier, or the configured default
+pub(crate) fn swap_fee_bps(env: &Env, config: &VaultConfig) -> u32 {
+    cheapest_dex(env).map_or(config.swap_fee_bps, |(_, fee_bps)| fee_bps)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_estimate_trade_cost() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        // Without tiers the configured default applies
+        vault.client.set_swap_fee(&50);
+        assert_eq!(vault.client.estimate_trade_cost(&btc, &2_0000000).swap_fee, 1_0000000);
+
+        vault.client.register_dex_fee_tier(&Address::generate(&env), &30);
+        vault.client.register_dex_fee_tier(&Address::generate(&env), &10);
+        vault.client.set_fee_schedule(&FeeSchedule {
+            management_fee_bps: 200,
+            performance_fee_bps: 2000,
+            keeper_fee: 1000000,
+        });
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &5_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+
+        // Half a year on, BTC +20% lifts NAV per share 10% above the high-water mark
+        env.ledger().with_mut(|l| l.timestamp += SECONDS_PER_YEAR / 2);
+        oracle.set_price(&btc, &120_0000000);
+
+        let cost = vault.client.estimate_trade_cost(&btc, &5_0000000);
+        assert_eq!(cost.swap_fee, 6000000);  // 10 bps of 600
+        assert_eq!(cost.keeper_fee, 1000000);
+        assert_eq!(cost.management_fee, 6_0000000);  // 1% of 600
+        assert_eq!(cost.performance_fee, 10_9090909);  // 20% of the 1/11 gain in 600
+        assert_eq!(cost.total, 17_6090909);
+    }
+
+    #[test]
+    fn test_operating_reserve() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.set_operating_reserve(&100_0000000);
+
+        let trade = |action: TradeAction, amount: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None)
+        };
+
+        // 1000 base: buying 901 or withdrawing below 100 breaches the reserve
+        assert!(trade(TradeAction::Buy, 9_0100000).is_err());
+        assert!(trade(TradeAction::Buy, 8_0000000).is_ok());
+        assert!(vault.client.try_withdraw(&alice, &100_0000001).is_err());
+        assert!(vault.client.try_withdraw(&alice, &100_0000000).is_ok());
+
+        // Selling back adds to the base position, even under the reserve
+        vault.client.set_operating_reserve(&500_0000000);
+        assert!(trade(TradeAction::Sell, 1_0000000).is_ok());
+        assert_eq!(vault.client.get_operating_reserve(), 500_0000000);
+    }
+}
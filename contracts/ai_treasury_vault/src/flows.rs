@@ -0,0 +1,132 @@
+//! External cash flows and time-weighted returns.
+//!
+//! Deposits and withdrawals between two snapshots are summed and stored
+//! against the snapshot that closes the period. `get_twr` then removes those
+//! flows from each period's change in NAV and chains the period returns, so
+//! the result reflects trading performance rather than capital moving in or
+//! out of the vault.
+
+use soroban_sdk::{contractimpl, contracttype, Env};
+
+use crate::oracle::PRICE_SCALE;
+use crate::{records, reporting, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, VaultConfig};
+
+/// Most snapshot periods `get_twr` chains in one call
+pub const MAX_TWR_PERIODS: u64 = 200;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum FlowKey {
+    PendingFlow,  // net flow since the latest snapshot
+    SnapshotFlow(u64),  // snapshot_id -> net flow in the period it closed
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Net deposits minus withdrawals in the period closed by a snapshot
+    pub fn get_snapshot_flow(env: Env, snapshot_id: u64) -> i128 {
+        env.storage().persistent()
+            .get(&FlowKey::SnapshotFlow(snapshot_id))
+            .unwrap_or(0)
+    }
+
+    /// Time-weighted return between two snapshots (bps)
+    pub fn get_twr(env: Env, from_snapshot: u64, to_snapshot: u64) -> i32 {
+        if to_snapshot <= from_snapshot || to_snapshot - from_snapshot > MAX_TWR_PERIODS {
+            panic!("Invalid snapshot range");
+        }
+
+        let snapshots = records::load_snapshots(&env, from_snapshot, to_snapshot);
+        if snapshots.len() as u64 != to_snapshot - from_snapshot + 1 {
+            panic!("No such snapshot");
+        }
+
+        let mut growth = PRICE_SCALE;
+        let mut previous = snapshots.get(0).unwrap();
+        for snapshot in snapshots.iter().skip(1) {
+            // Flows are taken to arrive at the end of the period
+            let flow = Self::get_snapshot_flow(env.clone(), snapshot.snapshot_id);
+            if previous.total_value > 0 {
+                growth = growth * (snapshot.total_value - flow) / previous.total_value;
+            }
+            previous = snapshot;
+        }
+
+        ((growth - PRICE_SCALE) * 10000 / PRICE_SCALE) as i32
+    }
+}
+
+/// Add a deposit (positive) or withdrawal (negative) to the open period
+pub(crate) fn record_flow(env: &Env, config: &VaultConfig, amount: i128) {
+    let amount = reporting::to_reporting(env, config, amount);
+    let pending: i128 = env.storage().instance().get(&FlowKey::PendingFlow).unwrap_or(0);
+    env.storage().instance().set(&FlowKey::PendingFlow, &(pending + amount));
+}
+
+/// Close the open period at a new snapshot
+pub(crate) fn close_period(env: &Env, snapshot_id: u64) {
+    let pending: i128 = env.storage().instance().get(&FlowKey::PendingFlow).unwrap_or(0);
+    if pending != 0 {
+        let key = FlowKey::SnapshotFlow(snapshot_id);
+        env.storage().persistent().set(&key, &pending);
+        env.storage().persistent().extend_ttl(
+            &key,
+            storage::PERSISTENT_LIFETIME_THRESHOLD,
+            storage::PERSISTENT_BUMP_AMOUNT,
+        );
+        env.storage().instance().remove(&FlowKey::PendingFlow);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::{Address, String};
+
+    #[test]
+    fn test_time_weighted_return() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &2100_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let start = vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &5_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+
+        // +10% on 1000, then 1100 more arrives before the period closes
+        oracle.set_price(&btc, &120_0000000);
+        vault.client.deposit(&alice, &1100_0000000);
+        let middle = vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+        assert_eq!(vault.client.get_snapshot_flow(&middle), 1100_0000000);
+
+        // BTC gives back its gain: 2200 -> 2100
+        oracle.set_price(&btc, &100_0000000);
+        let end = vault.client.create_snapshot(&vault.trading_agent, &0, &2, &0);
+
+        assert_eq!(vault.client.get_twr(&start, &middle), 1000);
+        assert_eq!(vault.client.get_twr(&start, &end), 500);  // 1.10 * 2100/2200
+        assert!(vault.client.try_get_twr(&end, &start).is_err());
+    }
+}
@@ -0,0 +1,230 @@
+//! Treasury grants.
+//!
+//! A grant vests `total` of a token to its grantee linearly from `start` to
+//! `end`, with nothing claimable before the cliff. Creating a grant sets its
+//! full amount aside: a managed token's position is drawn down at once and
+//! the amount is tracked per token as granted instead, so grants never count
+//! towards NAV or trading capital. Governance can revoke a grant; whatever
+//! has vested by then stays claimable and the rest goes back to the book.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, token, Address, Env};
+
+use crate::{assets, compliance, fees, guard, portfolio, roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum GrantKey {
+    GrantCount,
+    Grant(u64),  // grant_id -> grant and amount claimed (persistent)
+    Granted(Address),  // token -> amount set aside for grants and not yet claimed
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Grant {
+    pub grant_id: u64,
+    pub grantee: Address,
+    pub token: Address,
+    pub total: i128,
+    pub start: u64,
+    pub cliff: u64,
+    pub end: u64,
+    pub claimed: i128,
+    pub revoked_at: Option<u64>,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Grant `total` of `token` to `grantee`, vesting from `start` to `end`
+    /// with nothing claimable before `cliff`
+    pub fn create_grant(
+        env: Env,
+        grantee: Address,
+        token: Address,
+        total: i128,
+        start: u64,
+        cliff: u64,
+        end: u64,
+    ) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if total <= 0 {
+            panic!("Amount must be positive");
+        }
+        if start >= end || cliff < start || cliff > end {
+            panic!("Invalid vesting schedule");
+        }
+
+        // Set the grant aside from trading capital
+        if let Some(asset) = assets::asset_for_token(&env, &config, &token) {
+            let base_before = portfolio::position(&env, &config.base_asset);
+            portfolio::adjust_position(&env, &asset, -total);
+            fees::check_reserve(&env, &config, base_before);
+        }
+        set_granted(&env, &token, granted(&env, &token) + total);
+
+        let grant_id: u64 = env.storage().instance().get(&GrantKey::GrantCount).unwrap_or(0) + 1;
+        store_grant(&env, &Grant {
+            grant_id,
+            grantee,
+            token,
+            total,
+            start,
+            cliff,
+            end,
+            claimed: 0,
+            revoked_at: None,
+        });
+        env.storage().instance().set(&GrantKey::GrantCount, &grant_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("grant"), symbol_short!("create"), grant_id), total);
+        grant_id
+    }
+
+    /// Claim everything a grant has vested so far; returns the amount
+    pub fn claim_grant(env: Env, grant_id: u64) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let mut grant = grant(&env, grant_id);
+        grant.grantee.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("grant"));
+
+        let amount = vested(&env, &grant) - grant.claimed;
+        if amount <= 0 {
+            panic!("Nothing vested to claim");
+        }
+        compliance::check_recipient(&env, &config, &grant.grantee);
+        grant.claimed += amount;
+        store_grant(&env, &grant);
+        set_granted(&env, &grant.token, granted(&env, &grant.token) - amount);
+        storage::extend_instance(&env);
+
+        token::Client::new(&env, &grant.token)
+            .transfer(&env.current_contract_address(), &grant.grantee, &amount);
+        env.events().publish((symbol_short!("grant"), symbol_short!("claim"), grant_id), amount);
+        amount
+    }
+
+    /// Stop a grant vesting (governance); the unvested rest returns to the book
+    pub fn revoke_grant(env: Env, caller: Address, grant_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::GOVERNANCE);
+
+        let mut grant = grant(&env, grant_id);
+        if grant.revoked_at.is_some() {
+            panic!("Grant already revoked");
+        }
+        let unvested = grant.total - vested(&env, &grant);
+        grant.revoked_at = Some(env.ledger().timestamp());
+        store_grant(&env, &grant);
+
+        if unvested > 0 {
+            set_granted(&env, &grant.token, granted(&env, &grant.token) - unvested);
+            if let Some(asset) = assets::asset_for_token(&env, &config, &grant.token) {
+                portfolio::adjust_position(&env, &asset, unvested);
+            }
+        }
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("grant"), symbol_short!("revoke"), grant_id), unvested);
+    }
+
+    /// Get a grant
+    pub fn get_grant(env: Env, grant_id: u64) -> Option<Grant> {
+        env.storage().persistent().get(&GrantKey::Grant(grant_id))
+    }
+
+    /// Get the amount a grant has vested so far, claimed or not
+    pub fn get_vested(env: Env, grant_id: u64) -> i128 {
+        vested(&env, &grant(&env, grant_id))
+    }
+
+    /// Get the amount of `token` set aside for grants and not yet claimed
+    pub fn get_granted(env: Env, token: Address) -> i128 {
+        granted(&env, &token)
+    }
+}
+
+fn vested(env: &Env, grant: &Grant) -> i128 {
+    let now = grant.revoked_at.unwrap_or(env.ledger().timestamp());
+    if now < grant.cliff {
+        return 0;
+    }
+    if now >= grant.end {
+        return grant.total;
+    }
+    grant.total * (now - grant.start) as i128 / (grant.end - grant.start) as i128
+}
+
+/// Amount of `token` held for grants
+pub(crate) fn granted(env: &Env, token: &Address) -> i128 {
+    env.storage().instance().get(&GrantKey::Granted(token.clone())).unwrap_or(0)
+}
+
+fn set_granted(env: &Env, token: &Address, amount: i128) {
+    let key = GrantKey::Granted(token.clone());
+    if amount == 0 {
+        env.storage().instance().remove(&key);
+    } else {
+        env.storage().instance().set(&key, &amount);
+    }
+}
+
+fn grant(env: &Env, grant_id: u64) -> Grant {
+    env.storage().persistent()
+        .get(&GrantKey::Grant(grant_id))
+        .expect("No such grant")
+}
+
+fn store_grant(env: &Env, grant: &Grant) {
+    let key = GrantKey::Grant(grant.grant_id);
+    env.storage().persistent().set(&key, grant);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_grant_cliff_and_revocation() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let governance = Address::generate(&env);
+        env.as_contract(&vault.client.address, || roles::grant(&env, &roles::GOVERNANCE, &governance));
+
+        // The grant leaves trading capital as soon as it is made
+        let grantee = Address::generate(&env);
+        let grant_id = vault.client.create_grant(&grantee, &token.address, &400_0000000, &1000, &2000, &5000);
+        assert_eq!(vault.client.compute_nav(), 600_0000000);
+        assert_eq!(vault.client.get_granted(&token.address), 400_0000000);
+
+        // Nothing before the cliff, then linear from the start
+        env.ledger().with_mut(|l| l.timestamp = 1999);
+        assert_eq!(vault.client.get_vested(&grant_id), 0);
+        assert!(vault.client.try_claim_grant(&grant_id).is_err());
+        env.ledger().with_mut(|l| l.timestamp = 2000);
+        assert_eq!(vault.client.claim_grant(&grant_id), 100_0000000);
+
+        // Revoking halfway keeps what vested and returns the rest
+        assert!(vault.client.try_revoke_grant(&vault.risk_agent, &grant_id).is_err());
+        env.ledger().with_mut(|l| l.timestamp = 3000);
+        vault.client.revoke_grant(&governance, &grant_id);
+        assert_eq!(vault.client.compute_nav(), 800_0000000);
+        env.ledger().with_mut(|l| l.timestamp = 9000);
+        assert_eq!(vault.client.claim_grant(&grant_id), 100_0000000);
+        assert_eq!(vault.client.get_granted(&token.address), 0);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&grantee), 200_0000000);
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 800_0000000);
+    }
+}
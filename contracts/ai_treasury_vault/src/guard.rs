@@ -0,0 +1,104 @@
+//! Reentrancy protection.
+//!
+//! Trade execution, deposits, withdrawals, stake movements, adapter
+//! allocations and keeper payouts call token, oracle and strategy contracts
+//! the vault does not control. The Soroban host already refuses to re-enter
+//! a contract that is on the call stack; the vault does not rely on that
+//! alone. Those entry points hold a lock in temporary storage for the
+//! length of the call, and write their own state before the external call
+//! that moves funds (checks, effects, interactions), so a callback never
+//! observes a half-applied update. A call that fails unwinds its storage
+//! writes, the lock included.
+
+use soroban_sdk::{contracttype, Env, Symbol};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum GuardKey {
+    Entered,  // entry point currently holding the lock (temporary)
+}
+
+/// Held for the length of a guarded call; releases the lock when dropped
+pub(crate) struct Lock<'a> {
+    env: &'a Env,
+}
+
+/// Take the reentrancy lock for `call`, refusing if another call holds it
+pub(crate) fn lock<'a>(env: &'a Env, call: Symbol) -> Lock<'a> {
+    if env.storage().temporary().has(&GuardKey::Entered) {
+        panic!("Reentrant call");
+    }
+    env.storage().temporary().set(&GuardKey::Entered, &call);
+    Lock { env }
+}
+
+impl Drop for Lock<'_> {
+    fn drop(&mut self) {
+        self.env.storage().temporary().remove(&GuardKey::Entered);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::AITreasuryVaultV2Client;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::{contract, contractimpl, symbol_short, Address};
+
+    /// Base token whose `transfer` calls back into the vault to withdraw
+    #[contract]
+    pub struct MaliciousToken;
+
+    #[contractimpl]
+    impl MaliciousToken {
+        pub fn set_vault(env: Env, vault: Address) {
+            env.storage().instance().set(&symbol_short!("vault"), &vault);
+        }
+
+        pub fn balance(_env: Env, _id: Address) -> i128 {
+            i128::MAX
+        }
+
+        pub fn transfer(env: Env, from: Address, _to: Address, amount: i128) {
+            let vault: Address = env.storage().instance().get(&symbol_short!("vault")).unwrap();
+            AITreasuryVaultV2Client::new(&env, &vault).withdraw(&from, &amount);
+        }
+    }
+
+    #[test]
+    fn test_malicious_token_cannot_reenter() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = env.register_contract(None, MaliciousToken);
+        MaliciousTokenClient::new(&env, &token).set_vault(&vault.client.address);
+        vault.client.set_base_token(&token);
+
+        // The callback into `withdraw` fails, and the deposit with it
+        let mallory = Address::generate(&env);
+        assert!(vault.client.try_deposit(&mallory, &100_0000000).is_err());
+        assert_eq!(vault.client.get_shares(&mallory), 0);
+        assert_eq!(vault.client.compute_nav(), 0);
+    }
+
+    #[test]
+    fn test_lock_blocks_guarded_calls() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &100_0000000);
+
+        env.as_contract(&vault.client.address, || {
+            env.storage().temporary().set(&GuardKey::Entered, &symbol_short!("execute"));
+        });
+        assert!(vault.client.try_deposit(&alice, &100_0000000).is_err());
+
+        env.as_contract(&vault.client.address, || {
+            env.storage().temporary().remove(&GuardKey::Entered);
+        });
+        vault.client.deposit(&alice, &100_0000000);
+        assert_eq!(vault.client.get_shares(&alice), 100_0000000);
+    }
+}
@@ -0,0 +1,537 @@
+//! Trade log integrity and maintenance.
+//!
+//! Every recorded trade extends a SHA-256 hash chain,
+//! `head = sha256(prev_head || xdr(TradeRecord))`, starting from 32 zero
+//! bytes. An off-chain mirror that replays its copy of the log and arrives at
+//! the same head holds exactly the on-chain history.
+//!
+//! Pruned trades are removed from persistent storage so rent stays bounded,
+//! but an `archived` event carrying the SHA-256 of each record is emitted
+//! first so off-chain mirrors can still prove what was deleted.
+//!
+//! Periodic checkpoints commit a Merkle root over a contiguous trade range.
+//! Leaves are `sha256(xdr(TradeRecord))` in trade-id order, parents are
+//! `sha256(left || right)`, and an odd node at the end of a level is paired
+//! with itself. A pruned trade stays provable against its checkpoint root.
+//!
+//! Trade ids are also indexed per strategy and per asset in fixed-size pages
+//! so audits of a single model or market don't need to scan the whole trade
+//! range.
+//!
+//! Dashboards read the latest trades in one call through `get_recent_trades`,
+//! capped at an admin-set maximum so the read stays within resource limits.
+
+use soroban_sdk::{
+    contractimpl, contracttype, symbol_short, xdr::ToXdr, Bytes, BytesN, Env, String, Vec,
+};
+
+use crate::{records, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeRecord, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum HistoryKey {
+    MaxRecentTrades,  // most trades `get_recent_trades` returns
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub struct HistoryCheckpoint {
+    pub checkpoint_id: u32,
+    pub root: BytesN<32>,
+    pub from_trade_id: u64,
+    pub up_to_trade_id: u64,
+    pub committed_at: u64,
+}
+
+/// Merkle proof that `trade` is part of a committed checkpoint
+#[derive(Clone)]
+#[contracttype]
+pub struct InclusionProof {
+    pub checkpoint_id: u32,
+    pub trade: TradeRecord,
+    pub siblings: Vec<BytesN<32>>,  // leaf level first
+}
+
+/// Trades younger than this can never be pruned
+pub const MIN_TRADE_RETENTION_SECS: u64 = 90 * 86400;
+
+/// Upper bound on trades archived per `prune_trades` call
+pub const MAX_PRUNE_BATCH: u64 = 100;
+
+/// Trade ids per index page
+pub const INDEX_PAGE_SIZE: u32 = 50;
+
+/// Default cap on trades returned by `get_recent_trades`
+pub const DEFAULT_MAX_RECENT_TRADES: u32 = 50;
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Archive and delete trades with an id below `before_id` (admin)
+    ///
+    /// Works forward from the last pruned id, at most `MAX_PRUNE_BATCH`
+    /// trades per call, and stops at the first trade still inside the
+    /// retention window. Returns the number of trades removed.
+    pub fn prune_trades(env: Env, before_id: u64) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let trade_counter: u64 = env.storage().instance()
+            .get(&DataKey::TradeCounter).unwrap_or(0);
+        if before_id > trade_counter + 1 {
+            panic!("Cannot prune trades that do not exist");
+        }
+
+        let now = env.ledger().timestamp();
+        let start = pruned_before(&env);
+        let end = before_id.min(start + MAX_PRUNE_BATCH);
+
+        let mut pruned = 0;
+        let mut next = start;
+        while next < end {
+            if let Some(trade) = records::load_trade(&env, &config, next) {
+                if trade.executed_at + MIN_TRADE_RETENTION_SECS > now {
+                    break;
+                }
+
+                env.events().publish(
+                    (symbol_short!("archived"), next),
+                    trade_hash(&env, &trade),
+                );
+                env.storage().persistent().remove(&DataKey::Trade(next));
+                env.storage().instance().remove(&DataKey::Trade(next));
+                pruned += 1;
+            }
+            next += 1;
+        }
+
+        env.storage().instance().set(&DataKey::PrunedBefore, &next);
+        pruned
+    }
+
+    /// Commit the Merkle root of all trades since the previous checkpoint (admin)
+    pub fn commit_history_root(env: Env, root: BytesN<32>, up_to_trade_id: u64) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let trade_counter: u64 = env.storage().instance()
+            .get(&DataKey::TradeCounter).unwrap_or(0);
+        let count: u32 = env.storage().instance()
+            .get(&DataKey::CheckpointCount).unwrap_or(0);
+        let from_trade_id = match get_checkpoint(&env, count) {
+            Some(previous) => previous.up_to_trade_id + 1,
+            None => 1,
+        };
+
+        if up_to_trade_id < from_trade_id || up_to_trade_id > trade_counter {
+            panic!("Invalid checkpoint range");
+        }
+
+        let checkpoint = HistoryCheckpoint {
+            checkpoint_id: count + 1,
+            root: root.clone(),
+            from_trade_id,
+            up_to_trade_id,
+            committed_at: env.ledger().timestamp(),
+        };
+        storage::set_persistent(&env, &DataKey::HistoryCheckpoint(count + 1), &checkpoint);
+        env.storage().instance().set(&DataKey::CheckpointCount, &(count + 1));
+
+        env.events().publish(
+            (symbol_short!("checkpnt"), count + 1),
+            (root, from_trade_id, up_to_trade_id),
+        );
+
+        count + 1
+    }
+
+    /// Get a history checkpoint by ID
+    pub fn get_history_checkpoint(env: Env, checkpoint_id: u32) -> HistoryCheckpoint {
+        get_checkpoint(&env, checkpoint_id).unwrap()
+    }
+
+    /// Check a trade against a committed checkpoint root
+    pub fn verify_trade_inclusion(env: Env, proof: InclusionProof) -> bool {
+        let checkpoint = match get_checkpoint(&env, proof.checkpoint_id) {
+            Some(checkpoint) => checkpoint,
+            None => return false,
+        };
+
+        let trade_id = proof.trade.trade_id;
+        if trade_id < checkpoint.from_trade_id || trade_id > checkpoint.up_to_trade_id {
+            return false;
+        }
+
+        let mut index = trade_id - checkpoint.from_trade_id;
+        let mut node = trade_hash(&env, &proof.trade);
+        for sibling in proof.siblings.iter() {
+            node = if index & 1 == 0 {
+                hash_pair(&env, &node, &sibling)
+            } else {
+                hash_pair(&env, &sibling, &node)
+            };
+            index /= 2;
+        }
+
+        node == checkpoint.root
+    }
+
+    /// Get one page of trade ids executed by a strategy, oldest first
+    pub fn get_trades_by_strategy(env: Env, strategy_name: String, page: u32) -> Vec<u64> {
+        storage::get_persistent(&env, &DataKey::StrategyTrades(strategy_name, page))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get the number of trades indexed for a strategy
+    pub fn get_strategy_trade_count(env: Env, strategy_name: String) -> u32 {
+        storage::get_persistent(&env, &DataKey::StrategyTradeCount(strategy_name))
+            .unwrap_or(0)
+    }
+
+    /// Get one page of trade ids on an asset, oldest first
+    pub fn get_trades_by_asset(env: Env, asset: String, page: u32) -> Vec<u64> {
+        storage::get_persistent(&env, &DataKey::AssetTrades(asset, page))
+            .unwrap_or(Vec::new(&env))
+    }
+
+    /// Get the number of trades indexed for an asset
+    pub fn get_asset_trade_count(env: Env, asset: String) -> u32 {
+        storage::get_persistent(&env, &DataKey::AssetTradeCount(asset))
+            .unwrap_or(0)
+    }
+
+    /// Get up to `n` of the most recent trades, newest first
+    ///
+    /// `n` is capped at the configured maximum; pruned trades are skipped.
+    pub fn get_recent_trades(env: Env, n: u32) -> Vec<TradeRecord> {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let n = n.min(max_recent_trades(&env));
+        let first = pruned_before(&env);
+        let mut trade_id: u64 = env.storage().instance()
+            .get(&DataKey::TradeCounter).unwrap_or(0);
+
+        let mut trades = Vec::new(&env);
+        while trades.len() < n && trade_id >= first {
+            if let Some(trade) = records::load_trade(&env, &config, trade_id) {
+                trades.push_back(trade);
+            }
+            trade_id -= 1;
+        }
+        trades
+    }
+
+    /// Set the most trades `get_recent_trades` returns (admin)
+    pub fn set_max_recent_trades(env: Env, max: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if max == 0 {
+            panic!("Maximum must be positive");
+        }
+        env.storage().instance().set(&HistoryKey::MaxRecentTrades, &max);
+        storage::extend_instance(&env);
+    }
+
+    /// Get the most trades `get_recent_trades` returns
+    pub fn get_max_recent_trades(env: Env) -> u32 {
+        max_recent_trades(&env)
+    }
+
+    /// Get the head of the trade log hash chain
+    pub fn get_trade_log_head(env: Env) -> BytesN<32> {
+        log_head(&env)
+    }
+
+    /// Get the lowest trade id still held in storage
+    pub fn get_pruned_before(env: Env) -> u64 {
+        pruned_before(&env)
+    }
+}
+
+fn max_recent_trades(env: &Env) -> u32 {
+    env.storage().instance()
+        .get(&HistoryKey::MaxRecentTrades)
+        .unwrap_or(DEFAULT_MAX_RECENT_TRADES)
+}
+
+pub(crate) fn pruned_before(env: &Env) -> u64 {
+    env.storage().instance()
+        .get(&DataKey::PrunedBefore)
+        .unwrap_or(1)
+}
+
+pub(crate) fn log_head(env: &Env) -> BytesN<32> {
+    env.storage().instance()
+        .get(&DataKey::TradeLogHead)
+        .unwrap_or(BytesN::from_array(env, &[0; 32]))
+}
+
+/// Extend the hash chain with a newly recorded trade
+pub(crate) fn append_to_log(env: &Env, trade: &TradeRecord) {
+    let mut preimage = Bytes::from(log_head(env));
+    preimage.append(&trade.clone().to_xdr(env));
+    let head: BytesN<32> = env.crypto().sha256(&preimage).into();
+    env.storage().instance().set(&DataKey::TradeLogHead, &head);
+}
+
+/// Add a newly recorded trade to the strategy and asset indexes
+pub(crate) fn index_trade(env: &Env, trade: &TradeRecord) {
+    push_to_index(
+        env,
+        DataKey::StrategyTradeCount(trade.strategy.clone()),
+        |page| DataKey::StrategyTrades(trade.strategy.clone(), page),
+        trade.trade_id,
+    );
+    push_to_index(
+        env,
+        DataKey::AssetTradeCount(trade.asset.clone()),
+        |page| DataKey::AssetTrades(trade.asset.clone(), page),
+        trade.trade_id,
+    );
+}
+
+/// Append a trade id to the last page of a paged index
+fn push_to_index(env: &Env, count_key: DataKey, page_key: impl Fn(u32) -> DataKey, trade_id: u64) {
+    let count: u32 = storage::get_persistent(env, &count_key).unwrap_or(0);
+    let key = page_key(count / INDEX_PAGE_SIZE);
+
+    let mut ids: Vec<u64> = storage::get_persistent(env, &key).unwrap_or(Vec::new(env));
+    ids.push_back(trade_id);
+
+    storage::set_persistent(env, &key, &ids);
+    storage::set_persistent(env, &count_key, &(count + 1));
+}
+
+fn get_checkpoint(env: &Env, checkpoint_id: u32) -> Option<HistoryCheckpoint> {
+    storage::get_persistent(env, &DataKey::HistoryCheckpoint(checkpoint_id))
+}
+
+pub(crate) fn hash_pair(env: &Env, left: &BytesN<32>, right: &BytesN<32>) -> BytesN<32> {
+    let mut preimage = Bytes::from(left.clone());
+    preimage.append(&Bytes::from(right.clone()));
+    env.crypto().sha256(&preimage).into()
+}
+
+/// SHA-256 of the XDR-encoded trade record
+pub(crate) fn trade_hash(env: &Env, trade: &TradeRecord) -> BytesN<32> {
+    env.crypto().sha256(&trade.clone().to_xdr(env)).into()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_prune_trades() {
+        let env = Env::default();
+        let vault = setup(&env);
+
+        for _ in 0..3 {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        }
+
+        // Nothing is old enough yet
+        assert_eq!(vault.client.prune_trades(&3), 0);
+        assert_eq!(vault.client.get_pruned_before(), 1);
+
+        env.ledger().with_mut(|l| l.timestamp += MIN_TRADE_RETENTION_SECS);
+        assert_eq!(vault.client.prune_trades(&3), 2);
+        assert_eq!(vault.client.get_pruned_before(), 3);
+        assert!(vault.client.try_get_trade(&1).is_err());
+        assert_eq!(vault.client.get_trade(&3).trade_id, 3);
+    }
+
+    #[test]
+    fn test_recent_trades() {
+        let env = Env::default();
+        let vault = setup(&env);
+        assert_eq!(vault.client.get_recent_trades(&10).len(), 0);
+
+        for _ in 0..4 {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        }
+
+        // Newest first, capped at the configured maximum
+        let ids = |n: u32| {
+            let mut ids = Vec::new(&env);
+            for trade in vault.client.get_recent_trades(&n).iter() {
+                ids.push_back(trade.trade_id);
+            }
+            ids
+        };
+        assert_eq!(ids(2), Vec::from_array(&env, [4, 3]));
+        vault.client.set_max_recent_trades(&3);
+        assert_eq!(ids(10), Vec::from_array(&env, [4, 3, 2]));
+
+        // Pruned trades are gone from the result
+        env.ledger().with_mut(|l| l.timestamp += MIN_TRADE_RETENTION_SECS);
+        vault.client.prune_trades(&3);
+        assert_eq!(ids(10), Vec::from_array(&env, [4, 3]));
+    }
+
+    #[test]
+    fn test_trade_log_hash_chain() {
+        let env = Env::default();
+        let vault = setup(&env);
+        assert_eq!(vault.client.get_trade_log_head(), BytesN::from_array(&env, &[0; 32]));
+
+        let mut expected = BytesN::from_array(&env, &[0; 32]);
+        for amount in [100000i128, 200000] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            let trade_id = vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+
+            // Replay the chain from the mirrored records
+            let mut preimage = Bytes::from(expected);
+            preimage.append(&vault.client.get_trade(&trade_id).to_xdr(&env));
+            expected = env.crypto().sha256(&preimage).into();
+        }
+
+        assert_eq!(vault.client.get_trade_log_head(), expected);
+    }
+
+    #[test]
+    fn test_history_root_inclusion() {
+        let env = Env::default();
+        let vault = setup(&env);
+
+        for _ in 0..3 {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        }
+
+        // Off-chain tree over trades 1..=3, last leaf paired with itself
+        let trades = [vault.client.get_trade(&1), vault.client.get_trade(&2), vault.client.get_trade(&3)];
+        let leaves = [
+            trade_hash(&env, &trades[0]),
+            trade_hash(&env, &trades[1]),
+            trade_hash(&env, &trades[2]),
+        ];
+        let left = hash_pair(&env, &leaves[0], &leaves[1]);
+        let right = hash_pair(&env, &leaves[2], &leaves[2]);
+        let root = hash_pair(&env, &left, &right);
+
+        assert_eq!(vault.client.commit_history_root(&root, &3), 1);
+
+        // Still provable after the record itself is pruned
+        env.ledger().with_mut(|l| l.timestamp += MIN_TRADE_RETENTION_SECS);
+        vault.client.prune_trades(&4);
+
+        let proof = InclusionProof {
+            checkpoint_id: 1,
+            trade: trades[2].clone(),
+            siblings: Vec::from_array(&env, [leaves[2].clone(), left]),
+        };
+        assert!(vault.client.verify_trade_inclusion(&proof));
+
+        let mut forged = proof.clone();
+        forged.trade.profit_loss = 1_000_000;
+        assert!(!vault.client.verify_trade_inclusion(&forged));
+    }
+
+    #[test]
+    fn test_trades_by_asset() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+        let eth = String::from_str(&env, "ETH");
+
+        for asset in [&btc, &eth, &btc] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                asset,
+                &TradeAction::Buy,
+                &1000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        }
+
+        assert_eq!(vault.client.get_asset_trade_count(&btc), 2);
+        assert_eq!(vault.client.get_trades_by_asset(&btc, &0), Vec::from_array(&env, [1u64, 3]));
+        assert_eq!(vault.client.get_trades_by_asset(&eth, &0), Vec::from_array(&env, [2u64]));
+    }
+
+    #[test]
+    fn test_trades_by_strategy() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let lstm = String::from_str(&env, "LSTM");
+        let macd = String::from_str(&env, "MACD");
+
+        for i in 0..(INDEX_PAGE_SIZE + 2) {
+            // Each trade is its own transaction with its own budget
+            env.budget().reset_default();
+            let strategy = if i % 3 == 0 { &macd } else { &lstm };
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &1000,
+                strategy,
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        }
+
+        assert_eq!(vault.client.get_strategy_trade_count(&macd), 18);
+        assert_eq!(vault.client.get_strategy_trade_count(&lstm), 34);
+
+        let first_page = vault.client.get_trades_by_strategy(&macd, &0);
+        assert_eq!(first_page.len(), 18);
+        assert_eq!(first_page.get(1), Some(4));
+        assert!(vault.client.get_trades_by_strategy(&macd, &1).is_empty());
+    }
+}
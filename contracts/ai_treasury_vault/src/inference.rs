@@ -0,0 +1,186 @@
+//! Signed inference output.
+//!
+//! Once the admin registers the inference service's Ed25519 public key,
+//! every signal must carry the service's signature over its payload, so a
+//! trading agent can no longer submit signals the model never produced.
+//! The signed message is `xdr(SignalPayload)`: this vault's address, the
+//! signal's legs, strategy, confidence, expected return, model version,
+//! inference run and when it was signed. A signature is accepted only within
+//! `MAX_SIGNATURE_AGE_SECS` of signing and only once.
+
+use soroban_sdk::{contractimpl, contracttype, xdr::ToXdr, Address, BytesN, Env, String, Symbol, Vec};
+
+use crate::models::Provenance;
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+/// How long a signed payload stays valid after it was signed
+pub const MAX_SIGNATURE_AGE_SECS: u64 = 3600;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum InferenceKey {
+    InferenceKey,  // Ed25519 public key of the inference service
+    UsedPayload(BytesN<32>),  // sha256 of a signed payload already submitted (temporary)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum InferenceSignature {
+    Unsigned,
+    Ed25519(u64, BytesN<64>),  // (signed_at, signature over the signal payload)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SignalLeg {
+    pub asset: String,
+    pub action: TradeAction,
+    pub amount: i128,
+}
+
+/// What the inference service signs for a signal
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SignalPayload {
+    pub vault: Address,
+    pub legs: Vec<SignalLeg>,  // one leg, or the sell then buy leg of a pair
+    pub strategy: String,
+    pub confidence: u32,
+    pub expected_return: i32,
+    pub model_id: Symbol,
+    pub model_version: u32,
+    pub run_id: Option<u64>,
+    pub signed_at: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set (or clear) the inference service key signals must be signed with
+    pub fn set_inference_key(env: Env, public_key: Option<BytesN<32>>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        match public_key {
+            Some(public_key) => env.storage().instance().set(&InferenceKey::InferenceKey, &public_key),
+            None => env.storage().instance().remove(&InferenceKey::InferenceKey),
+        }
+        storage::extend_instance(&env);
+    }
+
+    /// Get the inference service key
+    pub fn get_inference_key(env: Env) -> Option<BytesN<32>> {
+        env.storage().instance().get(&InferenceKey::InferenceKey)
+    }
+}
+
+/// Verify the inference signature on a new signal, if signatures are required
+pub(crate) fn check_signature(
+    env: &Env,
+    provenance: &Option<Provenance>,
+    legs: Vec<SignalLeg>,
+    strategy: &String,
+    confidence: u32,
+    expected_return: i32,
+) {
+    let public_key: BytesN<32> = match env.storage().instance().get(&InferenceKey::InferenceKey) {
+        Some(public_key) => public_key,
+        None => return,
+    };
+    let provenance = provenance.as_ref().expect("Signal must be signed");
+    let (signed_at, signature) = match &provenance.signature {
+        InferenceSignature::Ed25519(signed_at, signature) => (*signed_at, signature),
+        InferenceSignature::Unsigned => panic!("Signal must be signed"),
+    };
+
+    let now = env.ledger().timestamp();
+    if signed_at > now || now - signed_at > MAX_SIGNATURE_AGE_SECS {
+        panic!("Signature expired");
+    }
+
+    let payload = SignalPayload {
+        vault: env.current_contract_address(),
+        legs,
+        strategy: strategy.clone(),
+        confidence,
+        expected_return,
+        model_id: provenance.model.model_id.clone(),
+        model_version: provenance.model.version,
+        run_id: provenance.run_id,
+        signed_at,
+    };
+    let message = payload.to_xdr(env);
+    env.crypto().ed25519_verify(&public_key, &message, signature);
+
+    // Each signed payload produces one signal
+    let key = InferenceKey::UsedPayload(env.crypto().sha256(&message).into());
+    if env.storage().temporary().has(&key) {
+        panic!("Signature already used");
+    }
+    env.storage().temporary().set(&key, &true);
+    env.storage().temporary().extend_ttl(&key, storage::DAY_IN_LEDGERS, storage::DAY_IN_LEDGERS);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::models::ModelRef;
+    use crate::test::setup;
+    use ed25519_dalek::{Signer, SigningKey};
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_signed_signals() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let service = SigningKey::from_bytes(&[3; 32]);
+        let model = ModelRef { model_id: Symbol::new(&env, "lstm"), version: 1 };
+        vault.client.register_model(&model.model_id, &1, &BytesN::from_array(&env, &[7; 32]));
+        vault.client.set_inference_key(&Some(BytesN::from_array(&env, &service.verifying_key().to_bytes())));
+        env.ledger().with_mut(|l| l.timestamp = 10000);
+
+        let btc = String::from_str(&env, "BTC");
+        let strategy = String::from_str(&env, "LSTM");
+        let sign = |amount: i128, signed_at: u64| -> InferenceSignature {
+            let mut legs = Vec::new(&env);
+            legs.push_back(SignalLeg { asset: btc.clone(), action: TradeAction::Buy, amount });
+            let payload = SignalPayload {
+                vault: vault.client.address.clone(),
+                legs,
+                strategy: strategy.clone(),
+                confidence: 85,
+                expected_return: 250,
+                model_id: model.model_id.clone(),
+                model_version: 1,
+                run_id: None,
+                signed_at,
+            };
+            let message = payload.to_xdr(&env);
+            let mut buf = [0u8; 512];
+            let len = message.len() as usize;
+            message.copy_into_slice(&mut buf[..len]);
+            InferenceSignature::Ed25519(signed_at, BytesN::from_array(&env, &service.sign(&buf[..len]).to_bytes()))
+        };
+        let submit = |amount: i128, signature: InferenceSignature| {
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &amount,
+                &strategy,
+                &85,
+                &250,
+                &None,
+                &Some(Provenance { model: model.clone(), run_id: None, signature }),
+            )
+        };
+
+        // Unsigned, altered, stale and replayed signals are refused
+        assert!(submit(100000, InferenceSignature::Unsigned).is_err());
+        assert!(submit(200000, sign(100000, 10000)).is_err());
+        assert!(submit(100000, sign(100000, 5000)).is_err());
+        assert!(submit(100000, sign(100000, 9000)).is_ok());
+        assert!(submit(100000, sign(100000, 9000)).is_err());
+    }
+}
@@ -0,0 +1,156 @@
+//! Invoice registration and payment matching.
+//!
+//! Every scheduled payment settles a registered invoice: its beneficiary
+//! and amount must match the invoice, and an invoice can back only one live
+//! payment at a time. The invoice is marked paid when the payment executes
+//! and reopens if the payment is cancelled. Invoice hashes are unique, so
+//! the same invoice cannot be registered, and paid, twice.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, BytesN, Env};
+
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum InvoiceKey {
+    InvoiceCount,
+    Invoice(u64),  // invoice_id -> invoice and its status (persistent)
+    InvoiceHash(BytesN<32>),  // invoice hash -> invoice_id (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum InvoiceStatus {
+    Open,
+    Scheduled(u64),  // payment_id settling it
+    Paid(u64),  // payment_id that settled it
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Invoice {
+    pub invoice_id: u64,
+    pub hash: BytesN<32>,
+    pub payee: Address,
+    pub amount: i128,
+    pub due_ts: u64,
+    pub status: InvoiceStatus,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Register an invoice that scheduled payments can settle
+    pub fn register_invoice(env: Env, hash: BytesN<32>, payee: Address, amount: i128, due_ts: u64) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        let hash_key = InvoiceKey::InvoiceHash(hash.clone());
+        if env.storage().persistent().has(&hash_key) {
+            panic!("Invoice already registered");
+        }
+
+        let invoice_id: u64 = env.storage().instance().get(&InvoiceKey::InvoiceCount).unwrap_or(0) + 1;
+        store_invoice(&env, &Invoice { invoice_id, hash: hash.clone(), payee, amount, due_ts, status: InvoiceStatus::Open });
+        env.storage().persistent().set(&hash_key, &invoice_id);
+        env.storage().persistent().extend_ttl(&hash_key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+        env.storage().instance().set(&InvoiceKey::InvoiceCount, &invoice_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("invoice"), symbol_short!("register"), invoice_id), hash);
+        invoice_id
+    }
+
+    /// Get an invoice
+    pub fn get_invoice(env: Env, invoice_id: u64) -> Option<Invoice> {
+        env.storage().persistent().get(&InvoiceKey::Invoice(invoice_id))
+    }
+
+    /// Find the invoice registered under a hash
+    pub fn get_invoice_by_hash(env: Env, hash: BytesN<32>) -> Option<Invoice> {
+        let invoice_id: u64 = env.storage().persistent().get(&InvoiceKey::InvoiceHash(hash))?;
+        Self::get_invoice(env, invoice_id)
+    }
+}
+
+fn invoice(env: &Env, invoice_id: u64) -> Invoice {
+    env.storage().persistent()
+        .get(&InvoiceKey::Invoice(invoice_id))
+        .expect("No such invoice")
+}
+
+fn store_invoice(env: &Env, invoice: &Invoice) {
+    let key = InvoiceKey::Invoice(invoice.invoice_id);
+    env.storage().persistent().set(&key, invoice);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+/// Attach an open invoice to the payment scheduled to settle it
+pub(crate) fn schedule(env: &Env, invoice_id: u64, payment_id: u64, payee: &Address, amount: i128) {
+    let mut invoice = invoice(env, invoice_id);
+    if invoice.status != InvoiceStatus::Open {
+        panic!("Invoice is not open");
+    }
+    if invoice.payee != *payee || invoice.amount != amount {
+        panic!("Payment does not match the invoice");
+    }
+    invoice.status = InvoiceStatus::Scheduled(payment_id);
+    store_invoice(env, &invoice);
+}
+
+/// Mark the invoice a payment settles as paid
+pub(crate) fn settle(env: &Env, invoice_id: u64, payment_id: u64) {
+    let mut invoice = invoice(env, invoice_id);
+    if invoice.status != InvoiceStatus::Scheduled(payment_id) {
+        panic!("Invoice is not scheduled for this payment");
+    }
+    invoice.status = InvoiceStatus::Paid(payment_id);
+    store_invoice(env, &invoice);
+}
+
+/// Reopen the invoice of a cancelled payment
+pub(crate) fn reopen(env: &Env, invoice_id: u64) {
+    let mut invoice = invoice(env, invoice_id);
+    invoice.status = InvoiceStatus::Open;
+    store_invoice(env, &invoice);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_invoices_are_paid_once() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let ops = symbol_short!("ops");
+        vault.client.set_budget(&ops, &token.address, &1000_0000000);
+
+        let vendor = Address::generate(&env);
+        let hash = BytesN::from_array(&env, &[7; 32]);
+        let invoice_id = vault.client.register_invoice(&hash, &vendor, &80_0000000, &5000);
+        assert!(vault.client.try_register_invoice(&hash, &vendor, &80_0000000, &5000).is_err());
+
+        // The payment must match the invoice, and only one may settle it
+        let other = Address::generate(&env);
+        assert!(vault.client.try_schedule_payment(&other, &token.address, &80_0000000, &0, &ops, &invoice_id).is_err());
+        assert!(vault.client.try_schedule_payment(&vendor, &token.address, &90_0000000, &0, &ops, &invoice_id).is_err());
+        let payment_id = vault.client.schedule_payment(&vendor, &token.address, &80_0000000, &0, &ops, &invoice_id);
+        assert!(vault.client.try_schedule_payment(&vendor, &token.address, &80_0000000, &0, &ops, &invoice_id).is_err());
+
+        vault.client.execute_payment(&vault.payment_agent, &payment_id);
+        let invoice = vault.client.get_invoice_by_hash(&hash).unwrap();
+        assert_eq!(invoice.status, InvoiceStatus::Paid(payment_id));
+        assert!(vault.client.try_schedule_payment(&vendor, &token.address, &80_0000000, &0, &ops, &invoice_id).is_err());
+    }
+}
@@ -0,0 +1,628 @@
+//! Idle capital supplied to a lending pool.
+//!
+//! The admin points the vault at a Blend-compatible lending pool and can then
+//! supply idle base asset to it with `deploy_idle` and take it back with
+//! `recall_idle`. Supplied capital leaves the base position; the pool's
+//! bTokens the vault receives are tracked per position together with the
+//! principal behind them, and valued at the pool's current bToken rate, so
+//! NAV includes interest as it accrues. Each pool the vault is pointed at
+//! gets its own position, and the pool can only be switched once the
+//! current position is fully recalled.
+//!
+//! Interest is accounted for apart from trading P&L: it never passes through
+//! fills, the cost basis of traded positions or strategy stats. Each
+//! position keeps its principal as cost basis and the interest realized by
+//! recalls, and checkpoints the interest it has earned into yield epochs
+//! whenever it changes and at every keeper snapshot. `get_yield_report`
+//! shows both per position, including interest accrued since the last
+//! checkpoint in the current epoch.
+//!
+//! Pools that pay emissions on top of interest are harvested by keepers with
+//! `harvest`, for the keeper fee. Each position's harvest policy decides
+//! whether rewards are swapped to the base asset through the cheapest
+//! registered DEX and whether base-asset rewards are supplied back to the
+//! pool as principal; rewards that are not re-deployed are booked to their
+//! position as realized profit. Either way they count as harvested on the
+//! position rather than as interest.
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, String, Vec};
+
+use crate::venues::DexRouterClient;
+use crate::{assets, fees, guard, pnl, portfolio, profits, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Scale of a lending pool's bToken rate (underlying per bToken)
+pub const B_RATE_SCALE: i128 = 1_000_000_000;
+
+/// Yield epoch length unless the admin sets one (7 days)
+pub const DEFAULT_YIELD_EPOCH_SECS: u64 = 7 * 86400;
+
+/// Most recent yield epochs `get_yield_report` itemizes per position
+pub const MAX_REPORTED_EPOCHS: u64 = 8;
+
+/// Interface of the lending pools idle capital is supplied to
+#[allow(dead_code)]
+#[contractclient(name = "LendingPoolClient")]
+pub trait ILendingPool {
+    /// Called after `amount` base tokens have been transferred to the pool;
+    /// credit `from` with bTokens and return the bTokens minted
+    fn supply(env: Env, from: Address, amount: i128) -> i128;
+    /// Send `amount` base tokens to `to`, burning its bTokens; returns the
+    /// bTokens burned
+    fn withdraw(env: Env, to: Address, amount: i128) -> i128;
+    /// bTokens held by `holder`
+    fn b_tokens(env: Env, holder: Address) -> i128;
+    /// Underlying per bToken, scaled by `B_RATE_SCALE`
+    fn b_rate(env: Env) -> i128;
+    /// Token the pool pays its emissions in
+    fn reward_token(env: Env) -> Address;
+    /// Send the emissions accrued to `from` to `to`; returns the amount claimed
+    fn claim(env: Env, from: Address, to: Address) -> i128;
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum LendingKey {
+    PositionCount,
+    ActivePosition,  // position `deploy_idle` and `recall_idle` act on
+    YieldEpochSecs,
+    YieldPosition(u32),  // position_id -> pool, bTokens held and principal
+    HarvestPolicy(u32),  // position_id -> what `harvest` does with rewards
+    EpochInterest(u32, u64),  // (position_id, epoch) -> interest earned (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct YieldPosition {
+    pub position_id: u32,
+    pub pool: Address,
+    pub b_tokens: i128,
+    pub principal: i128,  // base asset supplied and not yet recalled
+    pub realized_interest: i128,  // recalled beyond the principal released
+    pub checkpointed: i128,  // interest earned up to the last checkpoint
+    pub harvested: i128,  // base-asset value of rewards harvested
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct HarvestPolicy {
+    pub swap_to_base: bool,
+    pub auto_compound: bool,  // supply swapped rewards back to the pool
+    pub max_slippage_bps: u32,  // below the oracle value, on top of the DEX fee
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct EpochInterest {
+    pub epoch: u64,
+    pub interest: i128,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct YieldReport {
+    pub position_id: u32,
+    pub pool: Address,
+    pub cost_basis: i128,
+    pub value: i128,
+    pub earned: i128,  // interest earned over the position's life
+    pub harvested: i128,
+    pub epochs: Vec<EpochInterest>,  // recent epochs, oldest first
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Supply idle capital to `pool` from now on, opening a position in it
+    pub fn set_lending_pool(env: Env, pool: Address) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if active_position(&env).is_some_and(|position| position.b_tokens > 0) {
+            panic!("Recall the current lending position first");
+        }
+
+        let position_id: u32 = env.storage().instance().get(&LendingKey::PositionCount).unwrap_or(0) + 1;
+        store_position(&env, &YieldPosition { position_id, pool, b_tokens: 0, principal: 0, realized_interest: 0, checkpointed: 0, harvested: 0 });
+        env.storage().instance().set(&LendingKey::PositionCount, &position_id);
+        env.storage().instance().set(&LendingKey::ActivePosition, &position_id);
+        storage::extend_instance(&env);
+        position_id
+    }
+
+    /// Supply `amount` of idle base asset to the lending pool
+    pub fn deploy_idle(env: Env, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("lending"));
+
+        let mut position = active_position(&env).expect("Lending pool not configured");
+        checkpoint(&env, &mut position);
+        let base_before = portfolio::position(&env, &config.base_asset);
+        if amount <= 0 || base_before < amount {
+            panic!("Insufficient base asset liquidity");
+        }
+
+        supply(&env, &config, &mut position, amount);
+        store_position(&env, &position);
+        portfolio::adjust_position(&env, &config.base_asset, -amount);
+        fees::check_reserve(&env, &config, base_before);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("lending"), symbol_short!("deploy"), position.position_id), amount);
+    }
+
+    /// Withdraw `amount` of base asset from the lending pool
+    pub fn recall_idle(env: Env, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("lending"));
+
+        let mut position = active_position(&env).expect("Lending pool not configured");
+        checkpoint(&env, &mut position);
+        if amount <= 0 || amount > position_value(&env, &position) {
+            panic!("Recall exceeds the lending position");
+        }
+
+        let base_token = assets::token_address(&env, &config, &config.base_asset)
+            .expect("Base token not configured");
+        let vault = env.current_contract_address();
+        let pool = LendingPoolClient::new(&env, &position.pool);
+        let value = position_value(&env, &position);
+        let base_client = token::Client::new(&env, &base_token);
+        let (base_balance, b_tokens_before) = (base_client.balance(&vault), pool.b_tokens(&vault));
+        pool.withdraw(&vault, &amount);
+        let received = base_client.balance(&vault) - base_balance;
+
+        // Principal leaves in proportion to the share of the position recalled
+        let released = position.principal * amount / value;
+        position.principal -= released;
+        position.realized_interest += received - released;
+        position.b_tokens -= b_tokens_before - pool.b_tokens(&vault);
+        store_position(&env, &position);
+        portfolio::adjust_position(&env, &config.base_asset, received);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("lending"), symbol_short!("recall"), position.position_id), received);
+    }
+
+    /// Claim a lending position's rewards and swap, re-deploy or book them
+    /// as its harvest policy says; returns their base-asset value
+    ///
+    /// Anyone may call this; the caller is paid the keeper fee.
+    pub fn harvest(env: Env, keeper: Address, position_id: u32) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        keeper.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("lending"));
+
+        let mut position = Self::get_yield_position(env.clone(), position_id).expect("No such lending position");
+        checkpoint(&env, &mut position);
+        let policy = harvest_policy(&env, position_id);
+
+        let vault = env.current_contract_address();
+        let pool = LendingPoolClient::new(&env, &position.pool);
+        let reward_token = pool.reward_token();
+        let reward_client = token::Client::new(&env, &reward_token);
+        let balance = reward_client.balance(&vault);
+        pool.claim(&vault, &vault);
+        let mut amount = reward_client.balance(&vault) - balance;
+        if amount <= 0 {
+            panic!("Nothing to harvest");
+        }
+        let mut asset = assets::asset_for_token(&env, &config, &reward_token).expect("Reward asset not registered");
+
+        if policy.swap_to_base && asset != config.base_asset {
+            amount = swap_to_base(&env, &config, &asset, &reward_token, amount, policy.max_slippage_bps);
+            asset = config.base_asset.clone();
+        }
+        let value = portfolio::value_of(&env, &config, &asset, amount);
+        position.harvested += value;
+
+        // Only the position being supplied to can take more principal
+        let compound = policy.auto_compound && asset == config.base_asset
+            && active_position(&env).is_some_and(|active| active.position_id == position_id);
+        if compound {
+            supply(&env, &config, &mut position, amount);
+        } else {
+            portfolio::adjust_position(&env, &asset, amount);
+            pnl::book_realized(&env, value);
+            profits::record_profit(&env, value);
+        }
+        store_position(&env, &position);
+        fees::pay_keeper(&env, &config, &keeper);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("lending"), symbol_short!("harvest"), position_id), (value, compound));
+        value
+    }
+
+    /// Set what `harvest` does with a lending position's rewards
+    pub fn set_harvest_policy(env: Env, position_id: u32, swap_to_base: bool, auto_compound: bool, max_slippage_bps: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if Self::get_yield_position(env.clone(), position_id).is_none() {
+            panic!("No such lending position");
+        }
+        if max_slippage_bps > 10000 {
+            panic!("Slippage must be at most 10000 bps");
+        }
+        let policy = HarvestPolicy { swap_to_base, auto_compound, max_slippage_bps };
+        env.storage().instance().set(&LendingKey::HarvestPolicy(position_id), &policy);
+        storage::extend_instance(&env);
+    }
+
+    /// Get what `harvest` does with a lending position's rewards
+    pub fn get_harvest_policy(env: Env, position_id: u32) -> HarvestPolicy {
+        harvest_policy(&env, position_id)
+    }
+
+    /// Get a lending position
+    pub fn get_yield_position(env: Env, position_id: u32) -> Option<YieldPosition> {
+        env.storage().instance().get(&LendingKey::YieldPosition(position_id))
+    }
+
+    /// Set the length of the yield epochs interest is reported over; applies
+    /// from the next checkpoint
+    pub fn set_yield_epoch(env: Env, yield_epoch_secs: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if yield_epoch_secs == 0 {
+            panic!("Epoch must be positive");
+        }
+        env.storage().instance().set(&LendingKey::YieldEpochSecs, &yield_epoch_secs);
+    }
+
+    /// Get every yield position's cost basis, value and interest earned,
+    /// overall and over the recent epochs
+    pub fn get_yield_report(env: Env) -> Vec<YieldReport> {
+        let epoch = current_epoch(&env);
+        let first_epoch = (epoch + 1).saturating_sub(MAX_REPORTED_EPOCHS);
+
+        let mut reports = Vec::new(&env);
+        for position in positions(&env).iter() {
+            let value = position_value(&env, &position);
+            let earned = earned(&position, value);
+
+            let mut epochs = Vec::new(&env);
+            for e in first_epoch..=epoch {
+                let mut interest = epoch_interest(&env, position.position_id, e);
+                if e == epoch {
+                    interest += earned - position.checkpointed;
+                }
+                if interest != 0 {
+                    epochs.push_back(EpochInterest { epoch: e, interest });
+                }
+            }
+            reports.push_back(YieldReport {
+                position_id: position.position_id,
+                pool: position.pool,
+                cost_basis: position.principal,
+                value,
+                earned,
+                harvested: position.harvested,
+                epochs,
+            });
+        }
+        reports
+    }
+
+    /// Get the current base-asset value of a lending position
+    pub fn get_yield_position_value(env: Env, position_id: u32) -> i128 {
+        Self::get_yield_position(env.clone(), position_id).map_or(0, |position| position_value(&env, &position))
+    }
+}
+
+fn active_position(env: &Env) -> Option<YieldPosition> {
+    let position_id: u32 = env.storage().instance().get(&LendingKey::ActivePosition)?;
+    env.storage().instance().get(&LendingKey::YieldPosition(position_id))
+}
+
+fn store_position(env: &Env, position: &YieldPosition) {
+    env.storage().instance().set(&LendingKey::YieldPosition(position.position_id), position);
+}
+
+/// Rewards are booked as they are claimed unless the admin sets a policy
+fn harvest_policy(env: &Env, position_id: u32) -> HarvestPolicy {
+    env.storage().instance()
+        .get(&LendingKey::HarvestPolicy(position_id))
+        .unwrap_or(HarvestPolicy { swap_to_base: false, auto_compound: false, max_slippage_bps: 0 })
+}
+
+/// Supply `amount` of base asset the vault holds to a position's pool
+fn supply(env: &Env, config: &VaultConfig, position: &mut YieldPosition, amount: i128) {
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    let vault = env.current_contract_address();
+    let pool = LendingPoolClient::new(env, &position.pool);
+    let b_tokens_before = pool.b_tokens(&vault);
+    token::Client::new(env, &base_token).transfer(&vault, &position.pool, &amount);
+    pool.supply(&vault, &amount);
+
+    position.b_tokens += pool.b_tokens(&vault) - b_tokens_before;
+    position.principal += amount;
+}
+
+/// Sell `amount` of `asset` for the base asset through the cheapest DEX;
+/// returns the base asset received
+pub(crate) fn swap_to_base(env: &Env, config: &VaultConfig, asset: &String, token_in: &Address, amount: i128, slippage_bps: u32) -> i128 {
+    let (router, fee_bps) = fees::cheapest_dex(env).expect("No DEX registered to swap through");
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    let bound_bps = 10000 - fee_bps as i128 - slippage_bps as i128;
+    let min_out = (portfolio::value_of(env, config, asset, amount) * bound_bps / 10000).max(0);
+
+    // Book what actually moved rather than what the router reports
+    let vault = env.current_contract_address();
+    let base_client = token::Client::new(env, &base_token);
+    let base_balance = base_client.balance(&vault);
+    token::Client::new(env, token_in).transfer(&vault, &router, &amount);
+    DexRouterClient::new(env, &router).swap_exact_in(&vault, token_in, &base_token, &amount, &min_out);
+    let received = base_client.balance(&vault) - base_balance;
+    if received < min_out {
+        panic!("Swap outside slippage bound");
+    }
+    received
+}
+
+fn positions(env: &Env) -> Vec<YieldPosition> {
+    let count: u32 = env.storage().instance().get(&LendingKey::PositionCount).unwrap_or(0);
+    let mut positions = Vec::new(env);
+    for position_id in 1..=count {
+        if let Some(position) = env.storage().instance().get(&LendingKey::YieldPosition(position_id)) {
+            positions.push_back(position);
+        }
+    }
+    positions
+}
+
+fn current_epoch(env: &Env) -> u64 {
+    let epoch_secs: u64 = env.storage().instance()
+        .get(&LendingKey::YieldEpochSecs)
+        .unwrap_or(DEFAULT_YIELD_EPOCH_SECS);
+    env.ledger().timestamp() / epoch_secs
+}
+
+fn epoch_interest(env: &Env, position_id: u32, epoch: u64) -> i128 {
+    env.storage().persistent()
+        .get(&LendingKey::EpochInterest(position_id, epoch))
+        .unwrap_or(0)
+}
+
+/// Interest a position has earned over its life, realized or not
+fn earned(position: &YieldPosition, value: i128) -> i128 {
+    value - position.principal + position.realized_interest
+}
+
+/// Book the interest earned since the last checkpoint to the current epoch
+fn checkpoint(env: &Env, position: &mut YieldPosition) {
+    let earned = earned(position, position_value(env, position));
+    let delta = earned - position.checkpointed;
+    if delta == 0 {
+        return;
+    }
+
+    let epoch = current_epoch(env);
+    let key = LendingKey::EpochInterest(position.position_id, epoch);
+    env.storage().persistent().set(&key, &(epoch_interest(env, position.position_id, epoch) + delta));
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+    position.checkpointed = earned;
+    store_position(env, position);
+}
+
+/// Checkpoint every open yield position (keeper snapshots)
+pub(crate) fn checkpoint_positions(env: &Env) {
+    for mut position in positions(env).iter() {
+        if position.b_tokens > 0 {
+            checkpoint(env, &mut position);
+        }
+    }
+}
+
+fn position_value(env: &Env, position: &YieldPosition) -> i128 {
+    if position.b_tokens == 0 {
+        return 0;
+    }
+    position.b_tokens * LendingPoolClient::new(env, &position.pool).b_rate() / B_RATE_SCALE
+}
+
+/// Base-asset value of every lending position
+pub(crate) fn lending_value(env: &Env) -> i128 {
+    let mut total = 0;
+    for position in positions(env).iter() {
+        total += position_value(env, &position);
+    }
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::contract;
+    use soroban_sdk::token::StellarAssetClient;
+
+    /// Pool paying interest by raising its bToken rate on demand
+    #[contract]
+    pub struct MockLendingPool;
+
+    #[contractimpl]
+    impl MockLendingPool {
+        pub fn init(env: Env, token: Address) {
+            env.storage().instance().set(&symbol_short!("token"), &token);
+            env.storage().instance().set(&symbol_short!("rate"), &B_RATE_SCALE);
+        }
+
+        pub fn set_b_rate(env: Env, rate: i128) {
+            env.storage().instance().set(&symbol_short!("rate"), &rate);
+        }
+
+        pub fn supply(env: Env, from: Address, amount: i128) -> i128 {
+            let minted = amount * B_RATE_SCALE / Self::b_rate(env.clone());
+            env.storage().instance().set(&from, &(Self::b_tokens(env.clone(), from.clone()) + minted));
+            minted
+        }
+
+        pub fn withdraw(env: Env, to: Address, amount: i128) -> i128 {
+            let burned = amount * B_RATE_SCALE / Self::b_rate(env.clone());
+            env.storage().instance().set(&to, &(Self::b_tokens(env.clone(), to.clone()) - burned));
+            let token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+            token::Client::new(&env, &token).transfer(&env.current_contract_address(), &to, &amount);
+            burned
+        }
+
+        pub fn b_tokens(env: Env, holder: Address) -> i128 {
+            env.storage().instance().get(&holder).unwrap_or(0)
+        }
+
+        pub fn b_rate(env: Env) -> i128 {
+            env.storage().instance().get(&symbol_short!("rate")).unwrap()
+        }
+
+        pub fn set_reward_token(env: Env, reward_token: Address) {
+            env.storage().instance().set(&symbol_short!("reward"), &reward_token);
+        }
+
+        pub fn reward_token(env: Env) -> Address {
+            env.storage().instance().get(&symbol_short!("reward")).unwrap()
+        }
+
+        /// Pays out every reward token the pool holds
+        pub fn claim(env: Env, _from: Address, to: Address) -> i128 {
+            let reward = token::Client::new(&env, &Self::reward_token(env.clone()));
+            let amount = reward.balance(&env.current_contract_address());
+            reward.transfer(&env.current_contract_address(), &to, &amount);
+            amount
+        }
+    }
+
+    /// Router selling any token for the base asset at half a unit each
+    #[contract]
+    pub struct MockRewardRouter;
+
+    #[contractimpl]
+    impl MockRewardRouter {
+        pub fn swap_exact_in(env: Env, to: Address, _token_in: Address, token_out: Address, amount_in: i128, _min_out: i128) -> i128 {
+            let out = amount_in / 2;
+            token::Client::new(&env, &token_out).transfer(&env.current_contract_address(), &to, &out);
+            out
+        }
+    }
+
+    #[test]
+    fn test_idle_capital_earns_interest_in_nav() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+
+        let pool = env.register_contract(None, MockLendingPool);
+        let pool_client = MockLendingPoolClient::new(&env, &pool);
+        pool_client.init(&token.address);
+        token.mint(&pool, &1000_0000000);  // liquidity to pay interest from
+        assert!(vault.client.try_deploy_idle(&400_0000000).is_err());
+        let position_id = vault.client.set_lending_pool(&pool);
+
+        vault.client.deploy_idle(&400_0000000);
+        assert_eq!(vault.client.get_position(&xlm), 600_0000000);
+        assert_eq!(vault.client.compute_nav(), 1000_0000000);
+
+        // 5% interest accrues into NAV
+        pool_client.set_b_rate(&(B_RATE_SCALE * 105 / 100));
+        assert_eq!(vault.client.get_yield_position_value(&position_id), 420_0000000);
+        assert_eq!(vault.client.compute_nav(), 1020_0000000);
+        assert!(vault.client.try_set_lending_pool(&Address::generate(&env)).is_err());
+
+        // Recalling half takes half the principal with it
+        vault.client.recall_idle(&210_0000000);
+        let position = vault.client.get_yield_position(&position_id).unwrap();
+        assert_eq!((position.b_tokens, position.principal), (200_0000000, 200_0000000));
+        assert_eq!(vault.client.get_position(&xlm), 810_0000000);
+        assert_eq!(vault.client.compute_nav(), 1020_0000000);
+        assert!(vault.client.try_recall_idle(&211_0000000).is_err());
+    }
+
+    #[test]
+    fn test_yield_report_by_epoch() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        let pool = env.register_contract(None, MockLendingPool);
+        let pool_client = MockLendingPoolClient::new(&env, &pool);
+        pool_client.init(&token.address);
+        token.mint(&pool, &1000_0000000);
+        let position_id = vault.client.set_lending_pool(&pool);
+        vault.client.deploy_idle(&400_0000000);
+
+        // 20 earned in the first epoch, half of it realized by the recall
+        pool_client.set_b_rate(&(B_RATE_SCALE * 105 / 100));
+        vault.client.recall_idle(&210_0000000);
+
+        // 10 more accrues in the next epoch without a checkpoint
+        env.ledger().with_mut(|l| l.timestamp = DEFAULT_YIELD_EPOCH_SECS);
+        pool_client.set_b_rate(&(B_RATE_SCALE * 110 / 100));
+        let report = vault.client.get_yield_report().get(0).unwrap();
+        assert_eq!(report.position_id, position_id);
+        assert_eq!((report.cost_basis, report.value, report.earned), (200_0000000, 220_0000000, 30_0000000));
+        assert_eq!(report.epochs.len(), 2);
+        assert_eq!(report.epochs.get(0).unwrap(), EpochInterest { epoch: 0, interest: 20_0000000 });
+        assert_eq!(report.epochs.get(1).unwrap(), EpochInterest { epoch: 1, interest: 10_0000000 });
+
+        // None of it shows up as trading P&L
+        let pnl = vault.client.get_pnl_breakdown();
+        assert_eq!((pnl.realized, pnl.unrealized), (0, 0));
+    }
+
+    #[test]
+    fn test_harvest_books_or_compounds_rewards() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let keeper = Address::generate(&env);
+
+        // Rewards paid in BLND, worth half a unit of base asset
+        let blnd = String::from_str(&env, "BLND");
+        let blnd_token = StellarAssetClient::new(&env, &env.register_stellar_asset_contract_v2(vault.admin.clone()).address());
+        vault.client.register_asset(&blnd, &blnd_token.address, &7, &blnd);
+        oracle.set_price(&blnd, &5000000);
+
+        let pool = env.register_contract(None, MockLendingPool);
+        let pool_client = MockLendingPoolClient::new(&env, &pool);
+        pool_client.init(&token.address);
+        pool_client.set_reward_token(&blnd_token.address);
+        let position_id = vault.client.set_lending_pool(&pool);
+        vault.client.deploy_idle(&400_0000000);
+        assert!(vault.client.try_harvest(&keeper, &position_id).is_err());
+
+        // By default rewards are booked as they come, as realized profit
+        blnd_token.mint(&pool, &100_0000000);
+        assert_eq!(vault.client.harvest(&keeper, &position_id), 50_0000000);
+        assert_eq!(vault.client.get_position(&blnd), 100_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, 50_0000000);
+
+        // Swapped to the base asset and supplied back as principal
+        let router = env.register_contract(None, MockRewardRouter);
+        vault.client.register_dex_fee_tier(&router, &30);
+        token.mint(&router, &100_0000000);
+        vault.client.set_harvest_policy(&position_id, &true, &true, &100);
+        blnd_token.mint(&pool, &100_0000000);
+        assert_eq!(vault.client.harvest(&keeper, &position_id), 50_0000000);
+        let position = vault.client.get_yield_position(&position_id).unwrap();
+        assert_eq!((position.b_tokens, position.principal, position.harvested), (450_0000000, 450_0000000, 100_0000000));
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 600_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, 50_0000000);
+    }
+}
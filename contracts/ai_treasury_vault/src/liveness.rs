@@ -0,0 +1,119 @@
+//! Admin dead man's switch.
+//!
+//! The admin proves liveness with `admin_heartbeat`. If no heartbeat arrives
+//! within `VaultConfig.admin_inactivity_secs`, trading stops and depositors
+//! may withdraw regardless of their lock-ups. Anyone can then call
+//! `trigger_dead_mans_switch` to record the halt in the config.
+
+use soroban_sdk::{contractimpl, symbol_short, Env};
+
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Prove the admin is still active
+    pub fn admin_heartbeat(env: Env) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        touch_admin(&env);
+    }
+
+    /// Set how long the admin may stay silent before the switch fires (0 = off)
+    pub fn set_admin_inactivity_window(env: Env, admin_inactivity_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.admin_inactivity_secs = admin_inactivity_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+        touch_admin(&env);
+    }
+
+    /// Halt trading once the admin has been inactive too long (anyone)
+    pub fn trigger_dead_mans_switch(env: Env) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        if !admin_inactive(&env, &config) {
+            panic!("Admin is active");
+        }
+
+        config.halted = true;
+        env.storage().instance().set(&DataKey::Config, &config);
+        env.events().publish((symbol_short!("deadman"),), last_heartbeat(&env, &config));
+    }
+
+    /// Get the timestamp of the admin's last heartbeat
+    pub fn get_admin_heartbeat(env: Env) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        last_heartbeat(&env, &config)
+    }
+}
+
+pub(crate) fn touch_admin(env: &Env) {
+    env.storage().instance().set(&DataKey::AdminHeartbeat, &env.ledger().timestamp());
+}
+
+fn last_heartbeat(env: &Env, config: &VaultConfig) -> u64 {
+    env.storage().instance()
+        .get(&DataKey::AdminHeartbeat)
+        .unwrap_or(config.created_at)
+}
+
+/// Whether the admin has been silent for longer than the inactivity window
+pub(crate) fn admin_inactive(env: &Env, config: &VaultConfig) -> bool {
+    if config.admin_inactivity_secs == 0 {
+        return false;
+    }
+    env.ledger().timestamp() > last_heartbeat(env, config) + config.admin_inactivity_secs
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::{Address, String};
+
+    #[test]
+    fn test_dead_mans_switch() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        vault.client.set_lockup_period(&(365 * 86400));
+        vault.client.set_admin_inactivity_window(&(30 * 86400));
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &100_0000000);
+        vault.client.deposit(&alice, &100_0000000);
+        assert!(vault.client.try_withdraw(&alice, &10_0000000).is_err());
+        assert!(vault.client.try_trigger_dead_mans_switch().is_err());
+
+        // A heartbeat keeps the switch from firing
+        env.ledger().with_mut(|l| l.timestamp += 20 * 86400);
+        vault.client.admin_heartbeat();
+        env.ledger().with_mut(|l| l.timestamp += 20 * 86400);
+        assert!(vault.client.is_operational());
+
+        // Then the admin goes silent
+        env.ledger().with_mut(|l| l.timestamp += 11 * 86400);
+        assert!(!vault.client.is_operational());
+        assert!(vault.client
+            .try_submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+            .is_err());
+        assert_eq!(vault.client.withdraw(&alice, &10_0000000), 10_0000000);
+
+        vault.client.trigger_dead_mans_switch();
+        assert!(vault.client.get_config().halted);
+    }
+}
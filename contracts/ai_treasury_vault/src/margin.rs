@@ -0,0 +1,233 @@
+//! Margin on short and leveraged exposure.
+//!
+//! Margined exposure is the oracle value of every short position plus the
+//! base asset borrowed from the lending market, and NAV is the equity
+//! covering it. Two ratios of NAV to that exposure apply: the initial
+//! margin (`VaultConfig.short_margin_bps`), which `approve_trade` requires
+//! of any sell that opens or grows a short, and the lower maintenance
+//! margin (`VaultConfig.maintenance_margin_bps`) the vault must never fall
+//! below. When it does, a keeper's `margin_call` force-reduces exposure back
+//! to the initial margin: shorts are bought back pro rata at their oracle
+//! price, realizing their P&L, and any shortfall left is repaid as debt out
+//! of the free base position.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env, String};
+
+use crate::{borrowing, collateral, fees, guard, portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct MarginStatus {
+    pub exposure: i128,  // shorts plus borrowed base asset
+    pub equity: i128,  // NAV
+    pub initial_required: i128,
+    pub maintenance_required: i128,
+    pub margin_bps: u32,  // equity per unit of exposure; u32::MAX without exposure
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the margin below which `margin_call` force-reduces exposure, in bps
+    pub fn set_maintenance_margin(env: Env, maintenance_margin_bps: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if maintenance_margin_bps < 10000 || maintenance_margin_bps > config.short_margin_bps {
+            panic!("Maintenance margin must be between 100% and the initial margin");
+        }
+        config.maintenance_margin_bps = maintenance_margin_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Get margined exposure, the equity covering it and both requirements
+    pub fn get_margin_status(env: Env) -> MarginStatus {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        margin_status(&env, &config)
+    }
+
+    /// Force-reduce exposure back to the initial margin once the
+    /// maintenance margin is breached; returns the exposure removed
+    ///
+    /// Anyone may call this; the caller is paid the keeper fee.
+    pub fn margin_call(env: Env, keeper: Address) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        keeper.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("margin"));
+
+        let status = margin_status(&env, &config);
+        if status.equity >= status.maintenance_required {
+            panic!("Maintenance margin met");
+        }
+
+        // NAV is unchanged by covering at the oracle price, so solve
+        // equity / (exposure - x) = initial margin
+        let needed = status.exposure - status.equity * 10000 / config.short_margin_bps as i128;
+        let shorts = portfolio::short_exposure(&env, &config);
+        let cover = needed.min(shorts);
+        let mut reduced = 0;
+        if cover > 0 {
+            for asset in portfolio::held_assets(&env).iter() {
+                let quantity = portfolio::position(&env, &asset);
+                if quantity >= 0 || asset == config.base_asset {
+                    continue;
+                }
+                // Round up so the shorts cover at least their share
+                let amount = ((-quantity * cover + shorts - 1) / shorts).min(-quantity);
+                let price = portfolio::price_of(&env, &config, &asset);
+                portfolio::apply_fill(&env, &config, &asset, TradeAction::Buy, amount, price);
+                reduced += portfolio::notional(&env, &asset, amount, price);
+                env.events().publish((symbol_short!("margin"), symbol_short!("cover"), asset), amount);
+            }
+        }
+
+        let repayment = (needed - reduced)
+            .min(borrowing::debt(&env))
+            .min(collateral::available(&env, &config.base_asset));
+        if repayment > 0 {
+            borrowing::repay_debt(&env, &config, repayment);
+            reduced += repayment;
+        }
+        if reduced == 0 {
+            panic!("Nothing to reduce");
+        }
+        fees::pay_keeper(&env, &config, &keeper);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("margin"), symbol_short!("call")), reduced);
+        reduced
+    }
+}
+
+fn margin_status(env: &Env, config: &VaultConfig) -> MarginStatus {
+    let exposure = margined_exposure(env, config);
+    let equity = portfolio::nav(env, config);
+    let margin_bps = if exposure > 0 {
+        (equity * 10000 / exposure).clamp(0, u32::MAX as i128 - 1) as u32
+    } else {
+        u32::MAX
+    };
+    MarginStatus {
+        exposure,
+        equity,
+        initial_required: exposure * config.short_margin_bps as i128 / 10000,
+        maintenance_required: exposure * config.maintenance_margin_bps as i128 / 10000,
+        margin_bps,
+    }
+}
+
+fn margined_exposure(env: &Env, config: &VaultConfig) -> i128 {
+    portfolio::short_exposure(env, config) + borrowing::debt(env)
+}
+
+/// Whether selling `amount` of `asset` keeps the vault within the initial margin
+pub(crate) fn initial_margin_ok(env: &Env, config: &VaultConfig, asset: &String, amount: i128) -> bool {
+    let held = portfolio::position(env, asset);
+    if held - amount >= 0 {
+        return true;
+    }
+    if !config.allow_shorting {
+        return false;
+    }
+
+    // Exposure after the trade: existing exposure plus the newly shorted quantity
+    let newly_short = if held > 0 { amount - held } else { amount };
+    let exposure = margined_exposure(env, config) + portfolio::value_of(env, config, asset, newly_short);
+    let required = exposure * config.short_margin_bps as i128 / 10000;
+
+    portfolio::nav(env, config) >= required
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::borrowing::test::{MockMarket, MockMarketClient};
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+    use crate::RiskMetrics;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_margin_call_covers_shorts() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.apply_change(&env, &ConfigChange::ShortSelling(true, 15000));
+        assert!(vault.client.try_set_maintenance_margin(&16000).is_err());
+        vault.client.set_maintenance_margin(&12000);
+
+        // 6 BTC short against 1000 of NAV is within the 150% initial margin
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Sell,
+            &6_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &-250,
+            &None,
+            &None,
+        );
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+        assert!(vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+        let keeper = Address::generate(&env);
+        assert!(vault.client.try_margin_call(&keeper).is_err());
+
+        // BTC at 140: NAV 760 against 840 of exposure breaches the 120% maintenance
+        oracle.set_price(&btc, &140_0000000);
+        let status = vault.client.get_margin_status();
+        assert_eq!((status.exposure, status.equity, status.margin_bps), (840_0000000, 760_0000000, 9047));
+
+        // Covering back to 150% leaves 760 / 1.5 of exposure
+        assert_eq!(vault.client.margin_call(&keeper), 333_3333360);
+        let status = vault.client.get_margin_status();
+        assert_eq!(status.equity, 760_0000000);
+        assert!(status.equity >= status.initial_required);
+        assert_eq!(vault.client.get_position(&btc), -3_6190476);
+    }
+
+    #[test]
+    fn test_margin_call_without_reduction_fails() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+
+        let market = env.register_contract(None, MockMarket);
+        let market_client = MockMarketClient::new(&env, &market);
+        market_client.init(&token.address);
+        market_client.set_borrow_limit(&200_0000000);
+        token.mint(&market, &1000_0000000);
+        vault.client.set_borrow_market(&market);
+        vault.client.borrow(&100_0000000);
+
+        // Debt alone breaches the maintenance margin, with no short to cover
+        // and every base unit locked
+        env.as_contract(&vault.client.address, || {
+            portfolio::adjust_position(&env, &xlm, -1000_0000000);
+            collateral::lock(&env, &xlm, &market, 100_0000000);
+        });
+        let status = vault.client.get_margin_status();
+        assert!(status.equity < status.maintenance_required);
+        let keeper = Address::generate(&env);
+        assert!(vault.client.try_margin_call(&keeper).is_err());
+        assert_eq!(vault.client.get_position(&xlm), 100_0000000);
+    }
+}
@@ -0,0 +1,390 @@
+//! Migration of V1 vault storage into the V2 layout.
+//!
+//! V1 used the same `DataKey` variants as V2 but smaller values under them:
+//! a configuration that named each agent, trade records with free-form
+//! string actions, and strategy and snapshot records without the fields V2
+//! added. Everything sat in instance storage. After upgrading the wasm, the
+//! V1 admin calls `migrate_from_v1` in pages of trade and snapshot ids,
+//! starting at 1. The first page converts the configuration, bumps its
+//! version and grants the V1 agents their roles; every page moves its
+//! trades (and the strategies they name) and snapshots into the V2
+//! encodings in persistent storage. Pages must follow on from each other so
+//! the trade log hash chain is rebuilt in order, and the migration finishes
+//! once a page reaches past the last trade and snapshot. Until then no
+//! signals, trades or snapshots are accepted, so nothing new lands in the
+//! strategy records, trade log or indexes ahead of the V1 history.
+
+use soroban_sdk::{
+    contractimpl, contracttype, symbol_short, Address, Env, Map, String, Symbol, TryFromVal, Val,
+};
+
+use crate::{benchmark, history, records, roles, storage};
+use crate::{
+    default_config, AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, PortfolioSnapshot,
+    StrategyPerformance, TradeAction, TradeRecord, VaultConfig,
+};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum MigrationKey {
+    MigrationCursor,  // next trade and snapshot id to migrate, while migrating
+}
+
+/// V1 vault configuration, stored under `DataKey::Config`
+#[derive(Clone)]
+#[contracttype]
+pub struct V1VaultConfig {
+    pub admin: Address,
+    pub trading_agent: Address,
+    pub risk_agent: Address,
+    pub payment_agent: Address,
+    pub max_single_trade: i128,
+    pub max_var_95: i32,
+    pub min_sharpe_ratio: i32,
+    pub dynamic_stop_loss: bool,
+    pub halted: bool,
+    pub created_at: u64,
+    pub version: u32,
+}
+
+/// V1 trade record, stored under `DataKey::Trade`
+#[derive(Clone)]
+#[contracttype]
+pub struct V1TradeRecord {
+    pub trade_id: u64,
+    pub signal_id: u64,
+    pub asset: String,
+    pub action: String,  // "BUY", "SELL", "HOLD"
+    pub amount: i128,
+    pub price: i128,
+    pub strategy: String,
+    pub executed_at: u64,
+    pub profit_loss: i128,
+}
+
+/// V1 strategy performance, stored under `DataKey::Strategy`
+#[derive(Clone)]
+#[contracttype]
+pub struct V1StrategyPerformance {
+    pub strategy_name: String,
+    pub total_trades: u32,
+    pub winning_trades: u32,
+    pub total_profit: i128,
+    pub avg_return: i32,
+    pub sharpe_ratio: i32,
+    pub last_updated: u64,
+}
+
+/// V1 snapshot, stored under `DataKey::Snapshot` and `DataKey::LatestSnapshot`
+#[derive(Clone)]
+#[contracttype]
+pub struct V1PortfolioSnapshot {
+    pub snapshot_id: u64,
+    pub timestamp: u64,
+    pub total_value: i128,
+    pub num_assets: u32,
+    pub total_trades: u64,
+    pub cumulative_return: i32,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Convert V1 trades and snapshots with ids in `[start_id, start_id +
+    /// limit)` to the V2 layout, converting the configuration on the first
+    /// page (V1 admin); returns the id the next page starts at
+    pub fn migrate_from_v1(env: Env, start_id: u64, limit: u32) -> u64 {
+        let cursor: u64 = match env.storage().instance().get(&MigrationKey::MigrationCursor) {
+            Some(cursor) => {
+                let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+                config.admin.require_auth();
+                cursor
+            }
+            None => {
+                migrate_config(&env);
+                1
+            }
+        };
+        if start_id != cursor {
+            panic!("Migration must continue from the last page");
+        }
+        if limit == 0 {
+            panic!("Limit must be positive");
+        }
+
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let end = start_id + limit as u64;
+        for id in start_id..end {
+            migrate_trade(&env, &config, id);
+            migrate_snapshot(&env, id);
+        }
+
+        let trade_count: u64 = env.storage().instance().get(&DataKey::TradeCounter).unwrap_or(0);
+        let snapshot_count: u64 = env.storage().instance().get(&DataKey::SnapshotCounter).unwrap_or(0);
+        if end > trade_count.max(snapshot_count) {
+            env.storage().instance().remove(&MigrationKey::MigrationCursor);
+            env.events().publish((symbol_short!("migrated"), 1u32, config.version), trade_count);
+        } else {
+            env.storage().instance().set(&MigrationKey::MigrationCursor, &end);
+        }
+        storage::extend_instance(&env);
+        end
+    }
+
+    /// Get the id the next `migrate_from_v1` page must start at, while a
+    /// migration is under way
+    pub fn get_migration_cursor(env: Env) -> Option<u64> {
+        env.storage().instance().get(&MigrationKey::MigrationCursor)
+    }
+}
+
+/// Refuse new signals, trades and snapshots while a migration is under way
+pub(crate) fn check_not_migrating(env: &Env) {
+    if env.storage().instance().has(&MigrationKey::MigrationCursor) {
+        panic!("Migration in progress");
+    }
+}
+
+/// Replace the V1 configuration and latest snapshot with their V2 forms
+fn migrate_config(env: &Env) {
+    let value: Val = env.storage().instance().get(&DataKey::Config).expect("No V1 state to migrate");
+    if !has_field(env, &value, "trading_agent") {
+        panic!("Already migrated");
+    }
+    let legacy = V1VaultConfig::try_from_val(env, &value).unwrap();
+    legacy.admin.require_auth();
+
+    let mut config = default_config(env, legacy.admin, legacy.max_single_trade);
+    config.max_var_95 = legacy.max_var_95;
+    config.min_sharpe_ratio = legacy.min_sharpe_ratio;
+    config.dynamic_stop_loss = legacy.dynamic_stop_loss;
+    config.halted = legacy.halted;
+    config.created_at = legacy.created_at;
+    env.storage().instance().set(&DataKey::Config, &config);
+
+    roles::grant(env, &roles::TRADING_AGENT, &legacy.trading_agent);
+    roles::grant(env, &roles::RISK_AGENT, &legacy.risk_agent);
+    roles::grant(env, &roles::PAYMENT_AGENT, &legacy.payment_agent);
+
+    let latest: Option<V1PortfolioSnapshot> = env.storage().instance().get(&DataKey::LatestSnapshot);
+    if let Some(latest) = latest {
+        env.storage().instance().set(&DataKey::LatestSnapshot, &convert_snapshot(env, latest));
+    }
+}
+
+/// Move a V1 trade record to persistent storage in the V2 encoding
+fn migrate_trade(env: &Env, config: &VaultConfig, trade_id: u64) {
+    let key = DataKey::Trade(trade_id);
+    let legacy: V1TradeRecord = match env.storage().instance().get(&key) {
+        Some(legacy) => legacy,
+        None => return,
+    };
+    migrate_strategy(env, &legacy.strategy);
+
+    let trade = TradeRecord {
+        trade_id: legacy.trade_id,
+        signal_id: legacy.signal_id,
+        asset: legacy.asset,
+        action: legacy_action(env, &legacy.action),
+        amount: legacy.amount,
+        price: legacy.price,
+        strategy: legacy.strategy,
+        executed_at: legacy.executed_at,
+        profit_loss: legacy.profit_loss,
+        realized_return_bps: 0,
+        venue: None,
+    };
+    env.storage().instance().remove(&key);
+    records::store_trade(env, config, &trade);
+    history::append_to_log(env, &trade);
+    history::index_trade(env, &trade);
+}
+
+/// Convert a strategy's V1 performance record and list the strategy
+fn migrate_strategy(env: &Env, strategy_name: &String) {
+    let key = DataKey::Strategy(strategy_name.clone());
+    let value: Val = match env.storage().instance().get(&key) {
+        Some(value) => value,
+        None => return,
+    };
+    if has_field(env, &value, "hold_signals") {
+        return;
+    }
+    let legacy = V1StrategyPerformance::try_from_val(env, &value).unwrap();
+
+    let perf = StrategyPerformance {
+        strategy_name: legacy.strategy_name,
+        total_trades: legacy.total_trades,
+        winning_trades: legacy.winning_trades,
+        total_profit: legacy.total_profit,
+        avg_return: legacy.avg_return,
+        sharpe_ratio: legacy.sharpe_ratio,
+        last_updated: legacy.last_updated,
+        hold_signals: 0,
+        predictions: 0,
+        avg_prediction_error: 0,
+    };
+    env.storage().instance().set(&key, &perf);
+
+    let mut strategies = AITreasuryVaultV2::get_all_strategies(env.clone());
+    if !strategies.contains(strategy_name) {
+        strategies.push_back(strategy_name.clone());
+        env.storage().instance().set(&DataKey::StrategyList, &strategies);
+    }
+}
+
+/// Move a V1 snapshot to persistent storage in the V2 encoding
+fn migrate_snapshot(env: &Env, snapshot_id: u64) {
+    let key = DataKey::Snapshot(snapshot_id);
+    let legacy: V1PortfolioSnapshot = match env.storage().instance().get(&key) {
+        Some(legacy) => legacy,
+        None => return,
+    };
+    env.storage().instance().remove(&key);
+    records::store_snapshot(env, None, &convert_snapshot(env, legacy));
+}
+
+/// V1 had no shares or benchmark, so snapshots get the initial share price
+fn convert_snapshot(env: &Env, legacy: V1PortfolioSnapshot) -> PortfolioSnapshot {
+    PortfolioSnapshot {
+        snapshot_id: legacy.snapshot_id,
+        timestamp: legacy.timestamp,
+        total_value: legacy.total_value,
+        num_assets: legacy.num_assets,
+        total_trades: legacy.total_trades,
+        cumulative_return: legacy.cumulative_return,
+        share_price: benchmark::share_price(env, legacy.total_value),
+        benchmark_price: 0,
+    }
+}
+
+/// Whether a stored struct has `field`; decoding checks the field count,
+/// so the layout is told apart by its fields first
+fn has_field(env: &Env, value: &Val, field: &str) -> bool {
+    Map::<Symbol, Val>::try_from_val(env, value)
+        .is_ok_and(|fields| fields.contains_key(Symbol::new(env, field)))
+}
+
+/// V1 stored actions as free-form strings
+fn legacy_action(env: &Env, action: &String) -> TradeAction {
+    if *action == String::from_str(env, "BUY") {
+        TradeAction::Buy
+    } else if *action == String::from_str(env, "SELL") {
+        TradeAction::Sell
+    } else {
+        TradeAction::Hold
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_migrate_from_v1() {
+        let env = Env::default();
+        let contract_id = env.register_contract(None, AITreasuryVaultV2);
+        let client = AITreasuryVaultV2Client::new(&env, &contract_id);
+        env.mock_all_auths();
+
+        let admin = Address::generate(&env);
+        let trading_agent = Address::generate(&env);
+        let lstm = String::from_str(&env, "LSTM");
+        env.as_contract(&contract_id, || {
+            let legacy = V1VaultConfig {
+                admin: admin.clone(),
+                trading_agent: trading_agent.clone(),
+                risk_agent: Address::generate(&env),
+                payment_agent: Address::generate(&env),
+                max_single_trade: 5000,
+                max_var_95: 300,
+                min_sharpe_ratio: 120,
+                dynamic_stop_loss: true,
+                halted: false,
+                created_at: 10,
+                version: 2,
+            };
+            env.storage().instance().set(&DataKey::Config, &legacy);
+            for id in 1..=3u64 {
+                let trade = V1TradeRecord {
+                    trade_id: id,
+                    signal_id: id,
+                    asset: String::from_str(&env, "XLM"),
+                    action: String::from_str(&env, if id == 2 { "SELL" } else { "BUY" }),
+                    amount: 1000,
+                    price: 1_2000000,
+                    strategy: lstm.clone(),
+                    executed_at: 40 + id,
+                    profit_loss: 0,
+                };
+                env.storage().instance().set(&DataKey::Trade(id), &trade);
+            }
+            let perf = V1StrategyPerformance {
+                strategy_name: lstm.clone(),
+                total_trades: 3,
+                winning_trades: 1,
+                total_profit: 0,
+                avg_return: 0,
+                sharpe_ratio: 0,
+                last_updated: 43,
+            };
+            env.storage().instance().set(&DataKey::Strategy(lstm.clone()), &perf);
+            let snapshot = V1PortfolioSnapshot {
+                snapshot_id: 1,
+                timestamp: 50,
+                total_value: 1000_0000000,
+                num_assets: 1,
+                total_trades: 3,
+                cumulative_return: 0,
+            };
+            env.storage().instance().set(&DataKey::Snapshot(1), &snapshot);
+            env.storage().instance().set(&DataKey::LatestSnapshot, &snapshot);
+            env.storage().instance().set(&DataKey::TradeCounter, &3u64);
+            env.storage().instance().set(&DataKey::SnapshotCounter, &1u64);
+        });
+
+        // Pages must follow on from each other
+        assert!(client.try_migrate_from_v1(&2, &2).is_err());
+        assert_eq!(client.migrate_from_v1(&1, &2), 3);
+        assert_eq!(client.get_migration_cursor(), Some(3));
+        let config = client.get_config();
+        assert_eq!((config.version, config.admin, config.max_var_95), (3, admin, 300));
+        assert!(client.has_role(&roles::TRADING_AGENT, &trading_agent));
+        assert!(client.try_migrate_from_v1(&1, &2).is_err());
+
+        // No new signals until the history is migrated
+        let submit = || {
+            client.try_submit_trading_signal(
+                &trading_agent,
+                &String::from_str(&env, "XLM"),
+                &TradeAction::Buy,
+                &1000,
+                &lstm,
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        assert!(submit().is_err());
+
+        assert_eq!(client.migrate_from_v1(&3, &2), 5);
+        assert!(submit().is_ok());
+        assert_eq!(client.get_migration_cursor(), None);
+        assert_eq!(client.get_total_trades(), 3);
+
+        let trade = client.get_trade(&2);
+        assert_eq!(trade.price, 1_2000000);
+        assert_eq!(trade.executed_at, 42);
+        assert_eq!(trade.strategy, lstm);
+        assert_eq!(trade.action, TradeAction::Sell);
+        assert_eq!(client.get_strategy_performance(&lstm).total_trades, 3);
+        assert_eq!(client.get_all_strategies().len(), 1);
+        assert_eq!(client.get_snapshot(&1).share_price, 1_0000000);
+        assert_eq!(client.get_latest_snapshot().total_value, 1000_0000000);
+
+        // Exactly once
+        assert!(client.try_migrate_from_v1(&1, &10).is_err());
+    }
+}
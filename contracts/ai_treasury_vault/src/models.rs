@@ -0,0 +1,174 @@
+//! AI model registry.
+//!
+//! The admin registers each model version with the hash of its weights, so
+//! an auditor can check that the artifact behind a trade is the one pinned
+//! on-chain. Signals name the model version that produced them in their
+//! `Provenance`; an unregistered version is refused, and once the admin
+//! turns on `set_require_models` every signal has to name one. The model of
+//! each executed trade is kept after its signal expires.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, BytesN, Env, Symbol};
+
+use crate::inference::InferenceSignature;
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ModelKey {
+    Model(Symbol, u32),  // (model_id, version) -> registered artifact
+    LatestVersion(Symbol),  // model_id -> highest registered version
+    RequireModels,
+    TradeModel(u64),  // trade_id -> model version behind it (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct ModelRef {
+    pub model_id: Symbol,
+    pub version: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct ModelVersion {
+    pub weights_hash: BytesN<32>,  // hash of the model's weight artifact
+    pub registered_at: u64,
+}
+
+/// Where a signal came from
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Provenance {
+    pub model: ModelRef,  // registered model version that produced the signal
+    pub run_id: Option<u64>,  // anchored inference run it came from
+    pub signature: InferenceSignature,  // inference service signature over the signal
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Register a model version and the hash of its weights
+    ///
+    /// Versions of a model must be registered in increasing order.
+    pub fn register_model(env: Env, model_id: Symbol, version: u32, weights_hash: BytesN<32>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let latest: u32 = env.storage().instance()
+            .get(&ModelKey::LatestVersion(model_id.clone()))
+            .unwrap_or(0);
+        if version <= latest {
+            panic!("Model version must increase");
+        }
+
+        let model = ModelVersion { weights_hash, registered_at: env.ledger().timestamp() };
+        env.storage().instance().set(&ModelKey::Model(model_id.clone(), version), &model);
+        env.storage().instance().set(&ModelKey::LatestVersion(model_id.clone()), &version);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("model"), symbol_short!("register"), model_id, version), model.weights_hash);
+    }
+
+    /// Get a registered model version
+    pub fn get_model(env: Env, model_id: Symbol, version: u32) -> Option<ModelVersion> {
+        env.storage().instance().get(&ModelKey::Model(model_id, version))
+    }
+
+    /// Get the highest registered version of a model (0 if none)
+    pub fn get_latest_model_version(env: Env, model_id: Symbol) -> u32 {
+        env.storage().instance().get(&ModelKey::LatestVersion(model_id)).unwrap_or(0)
+    }
+
+    /// Require (or stop requiring) every signal to name a registered model version
+    pub fn set_require_models(env: Env, required: bool) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().set(&ModelKey::RequireModels, &required);
+        storage::extend_instance(&env);
+    }
+
+    /// Get the model version behind an executed trade
+    pub fn get_trade_model(env: Env, trade_id: u64) -> Option<ModelRef> {
+        env.storage().persistent().get(&ModelKey::TradeModel(trade_id))
+    }
+}
+
+/// Check a new signal's provenance; returns the model and version it names
+pub(crate) fn check_provenance(env: &Env, provenance: &Option<Provenance>) -> (Option<Symbol>, u32) {
+    let model = match provenance {
+        Some(provenance) => provenance.model.clone(),
+        None => {
+            if env.storage().instance().get(&ModelKey::RequireModels).unwrap_or(false) {
+                panic!("Signal must name a model version");
+            }
+            return (None, 0);
+        }
+    };
+
+    if !env.storage().instance().has(&ModelKey::Model(model.model_id.clone(), model.version)) {
+        panic!("Unregistered model version");
+    }
+    (Some(model.model_id), model.version)
+}
+
+/// Keep the model behind an executed trade
+pub(crate) fn record_trade_model(env: &Env, trade_id: u64, model_id: &Option<Symbol>, version: u32) {
+    if let Some(model_id) = model_id {
+        let key = ModelKey::TradeModel(trade_id);
+        env.storage().persistent().set(&key, &ModelRef { model_id: model_id.clone(), version });
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_model_registry() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let lstm = Symbol::new(&env, "lstm_v");
+        let weights = BytesN::from_array(&env, &[7; 32]);
+
+        vault.client.register_model(&lstm, &2, &weights);
+        assert!(vault.client.try_register_model(&lstm, &2, &weights).is_err());
+        assert!(vault.client.try_register_model(&lstm, &1, &weights).is_err());
+        assert_eq!(vault.client.get_model(&lstm, &2).unwrap().weights_hash, weights);
+        assert_eq!(vault.client.get_latest_model_version(&lstm), 2);
+
+        let submit = |provenance: Option<Provenance>| {
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &provenance,
+            )
+        };
+        let named = |version: u32| {
+            let model = ModelRef { model_id: lstm.clone(), version };
+            Some(Provenance { model, run_id: None, signature: InferenceSignature::Unsigned })
+        };
+
+        // Only registered versions, and a model is needed once required
+        assert!(submit(named(1)).is_err());
+        assert!(submit(None).is_ok());
+        vault.client.set_require_models(&true);
+        assert!(submit(None).is_err());
+        let signal_id = submit(named(2)).unwrap().unwrap();
+
+        // The executed trade stays attributable to the model
+        let trade_id = vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        assert_eq!(vault.client.get_trade_model(&trade_id), Some(ModelRef { model_id: lstm, version: 2 }));
+    }
+}
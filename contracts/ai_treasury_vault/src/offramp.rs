@@ -0,0 +1,189 @@
+//! Fiat off-ramp intents.
+//!
+//! The payment agent requests an off-ramp of some amount of an asset with
+//! the hash of the memo the anchor expects. The funds leave the book into
+//! escrow at once and the request is published as an event carrying the
+//! full `OfframpRequest`, for an SEP-24/SEP-31 anchor bridge to pick up.
+//! The registered anchor then either confirms it, receiving the escrowed
+//! tokens to pay out in fiat, or refunds it, returning them to the book;
+//! the admin can refund too if the anchor never answers.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, token, Address, BytesN, Env, String};
+
+use crate::{assets, compliance, fees, guard, portfolio, roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum OfframpKey {
+    OfframpAnchor,  // anchor bridge that settles off-ramps
+    OfframpCount,
+    Offramp(u64),  // request_id -> off-ramp request (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum OfframpStatus {
+    Pending,
+    Confirmed(u64),  // confirmed at
+    Refunded(u64),  // refunded at
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct OfframpRequest {
+    pub request_id: u64,
+    pub asset: String,
+    pub token: Address,
+    pub amount: i128,
+    pub memo_hash: BytesN<32>,  // hash of the memo the anchor matches the payout by
+    pub requested_at: u64,
+    pub status: OfframpStatus,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the anchor bridge that settles off-ramps
+    pub fn set_offramp_anchor(env: Env, anchor: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().set(&OfframpKey::OfframpAnchor, &anchor);
+    }
+
+    /// Get the anchor bridge that settles off-ramps
+    pub fn get_offramp_anchor(env: Env) -> Option<Address> {
+        env.storage().instance().get(&OfframpKey::OfframpAnchor)
+    }
+
+    /// Escrow `amount` of `asset` for the anchor to pay out in fiat (payment agent)
+    pub fn request_offramp(env: Env, caller: Address, amount: i128, asset: String, memo_hash: BytesN<32>) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::PAYMENT_AGENT);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if Self::get_offramp_anchor(env.clone()).is_none() {
+            panic!("Off-ramp anchor not configured");
+        }
+        let token = assets::token_address(&env, &config, &asset).expect("Asset has no token");
+        if portfolio::position(&env, &asset) < amount {
+            panic!("Insufficient position");
+        }
+
+        let base_before = portfolio::position(&env, &config.base_asset);
+        portfolio::adjust_position(&env, &asset, -amount);
+        fees::check_reserve(&env, &config, base_before);
+
+        let request_id: u64 = env.storage().instance().get(&OfframpKey::OfframpCount).unwrap_or(0) + 1;
+        let request = OfframpRequest {
+            request_id,
+            asset,
+            token,
+            amount,
+            memo_hash,
+            requested_at: env.ledger().timestamp(),
+            status: OfframpStatus::Pending,
+        };
+        store_request(&env, &request);
+        env.storage().instance().set(&OfframpKey::OfframpCount, &request_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("offramp"), symbol_short!("request"), request_id), request);
+        request_id
+    }
+
+    /// Release an escrowed off-ramp to the anchor (anchor)
+    pub fn confirm_offramp(env: Env, request_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let anchor = Self::get_offramp_anchor(env.clone()).expect("Off-ramp anchor not configured");
+        anchor.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("offramp"));
+
+        let mut request = pending_request(&env, request_id);
+        compliance::check_recipient(&env, &config, &anchor);
+        request.status = OfframpStatus::Confirmed(env.ledger().timestamp());
+        store_request(&env, &request);
+
+        token::Client::new(&env, &request.token)
+            .transfer(&env.current_contract_address(), &anchor, &request.amount);
+        env.events().publish((symbol_short!("offramp"), symbol_short!("confirm"), request_id), request.amount);
+    }
+
+    /// Return an escrowed off-ramp to the book (anchor or admin)
+    pub fn refund_offramp(env: Env, caller: Address, request_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        caller.require_auth();
+        if caller != config.admin && Self::get_offramp_anchor(env.clone()) != Some(caller) {
+            panic!("Only the anchor or admin can refund");
+        }
+
+        let mut request = pending_request(&env, request_id);
+        request.status = OfframpStatus::Refunded(env.ledger().timestamp());
+        store_request(&env, &request);
+        portfolio::adjust_position(&env, &request.asset, request.amount);
+
+        env.events().publish((symbol_short!("offramp"), symbol_short!("refund"), request_id), request.amount);
+    }
+
+    /// Get an off-ramp request
+    pub fn get_offramp(env: Env, request_id: u64) -> Option<OfframpRequest> {
+        env.storage().persistent().get(&OfframpKey::Offramp(request_id))
+    }
+}
+
+fn pending_request(env: &Env, request_id: u64) -> OfframpRequest {
+    let request: OfframpRequest = env.storage().persistent()
+        .get(&OfframpKey::Offramp(request_id))
+        .expect("No such off-ramp");
+    if request.status != OfframpStatus::Pending {
+        panic!("Off-ramp already settled");
+    }
+    request
+}
+
+fn store_request(env: &Env, request: &OfframpRequest) {
+    let key = OfframpKey::Offramp(request.request_id);
+    env.storage().persistent().set(&key, request);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_offramp_escrow() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+        let memo = BytesN::from_array(&env, &[9; 32]);
+
+        let anchor = Address::generate(&env);
+        assert!(vault.client.try_request_offramp(&vault.payment_agent, &100_0000000, &xlm, &memo).is_err());
+        vault.client.set_offramp_anchor(&anchor);
+        assert!(vault.client.try_request_offramp(&vault.trading_agent, &100_0000000, &xlm, &memo).is_err());
+
+        // Escrowed funds leave the book until settled
+        let first = vault.client.request_offramp(&vault.payment_agent, &100_0000000, &xlm, &memo);
+        let second = vault.client.request_offramp(&vault.payment_agent, &50_0000000, &xlm, &memo);
+        assert_eq!(vault.client.get_position(&xlm), 850_0000000);
+
+        vault.client.confirm_offramp(&first);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&anchor), 100_0000000);
+        assert!(vault.client.try_refund_offramp(&anchor, &first).is_err());
+
+        assert!(vault.client.try_refund_offramp(&Address::generate(&env), &second).is_err());
+        vault.client.refund_offramp(&anchor, &second);
+        assert_eq!(vault.client.get_position(&xlm), 900_0000000);
+        assert_eq!(vault.client.get_offramp(&second).unwrap().status, OfframpStatus::Refunded(0));
+    }
+}
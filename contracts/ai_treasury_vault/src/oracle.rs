@@ -0,0 +1,387 @@
+//! Price oracle interface used for on-chain valuation.
+//!
+//! Assets are priced by `VaultConfig.price_oracle` unless up to
+//! `MAX_ORACLE_SOURCES` oracle contracts are registered for them, in which
+//! case the median of their quotes is used. Executions are refused when
+//! those sources disagree by more than `VaultConfig.max_oracle_spread_bps`
+//! of the median, so a single corrupted feed cannot set the trade price.
+//!
+//! Each source is a `PriceSource`: an oracle contract, the protocol it
+//! speaks, the feed id it knows the asset by and the decimals its prices
+//! carry. Protocols are read through an `OracleAdapter`, and quotes are
+//! rescaled to base-asset units here, so supporting another oracle only
+//! means adding an adapter (see `reflector`).
+//!
+//! An asset can also be given a maximum quote age. Its prices are then read
+//! with their publication time through `lastprice`, and any valuation or
+//! execution that would use an older quote fails with
+//! `OracleError::StaleOracle` instead of using it.
+
+use soroban_sdk::{
+    contractclient, contracterror, contractimpl, contracttype, panic_with_error, Address, Env, String,
+    Vec,
+};
+
+use crate::{assets, reflector};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Smallest units per whole unit for assets without registered decimals.
+pub const PRICE_SCALE: i128 = 10_000_000;
+
+/// Most oracle contracts that can be registered for one asset
+pub const MAX_ORACLE_SOURCES: u32 = 3;
+
+/// Price feed the vault queries when valuing its positions.
+#[allow(dead_code)]
+#[contractclient(name = "PriceOracleClient")]
+pub trait PriceOracle {
+    /// Price of one whole unit of the `asset` feed, in base-asset smallest units
+    fn price(env: Env, asset: String) -> i128;
+
+    /// Latest price of the `asset` feed with the time it was published
+    fn lastprice(env: Env, asset: String) -> Option<PriceData>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PriceData {
+    pub price: i128,
+    pub timestamp: u64,
+}
+
+/// Oracle protocol a price source speaks
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[contracttype]
+pub enum OracleKind {
+    Vault,  // the `PriceOracle` interface above
+    Reflector,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PriceSource {
+    pub kind: OracleKind,
+    pub contract: Address,
+    pub feed_id: String,
+    pub decimals: u32,  // prices are quoted scaled by 10^decimals
+}
+
+/// Reads quotes from one oracle protocol
+pub(crate) trait OracleAdapter {
+    /// Latest quote for the source's feed with its publication time
+    fn lastprice(env: &Env, source: &PriceSource) -> Option<PriceData>;
+
+    /// Latest quote where its age does not matter
+    fn price(env: &Env, source: &PriceSource) -> i128 {
+        Self::lastprice(env, source).expect("No price for feed").price
+    }
+}
+
+struct VaultOracle;
+
+impl OracleAdapter for VaultOracle {
+    fn lastprice(env: &Env, source: &PriceSource) -> Option<PriceData> {
+        PriceOracleClient::new(env, &source.contract).lastprice(&source.feed_id)
+    }
+
+    fn price(env: &Env, source: &PriceSource) -> i128 {
+        PriceOracleClient::new(env, &source.contract).price(&source.feed_id)
+    }
+}
+
+#[contracterror]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OracleError {
+    StaleOracle = 1,
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum OracleKey {
+    AssetOracles(String),  // asset -> price sources to take the median of
+    FeedMaxAge(String),  // asset -> oldest acceptable quote in seconds
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Price an asset from the median of several `PriceOracle` contracts
+    /// (empty list removes them)
+    pub fn set_asset_oracles(env: Env, asset: String, oracles: Vec<Address>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let mut sources = Vec::new(&env);
+        for oracle in oracles.iter() {
+            sources.push_back(PriceSource {
+                kind: OracleKind::Vault,
+                contract: oracle,
+                feed_id: assets::feed_id(&env, &asset),
+                decimals: assets::decimals(&env, &config.base_asset),
+            });
+        }
+        Self::set_price_sources(env, asset, sources);
+    }
+
+    /// Price an asset from the median of several price sources of any kind
+    /// (empty list removes them)
+    pub fn set_price_sources(env: Env, asset: String, sources: Vec<PriceSource>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if sources.len() > MAX_ORACLE_SOURCES {
+            panic!("Too many oracle sources");
+        }
+        for source in sources.iter() {
+            if source.decimals > assets::MAX_ASSET_DECIMALS {
+                panic!("Too many decimals");
+            }
+        }
+
+        let key = OracleKey::AssetOracles(asset);
+        if sources.is_empty() {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &sources);
+        }
+    }
+
+    /// Get the price sources registered for an asset
+    pub fn get_price_sources(env: Env, asset: String) -> Vec<PriceSource> {
+        price_sources(&env, &asset)
+    }
+
+    /// Set how far apart (bps of the median) an asset's oracles may quote
+    /// before executions are refused (0 = no limit)
+    pub fn set_max_oracle_spread(env: Env, max_oracle_spread_bps: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.max_oracle_spread_bps = max_oracle_spread_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Refuse quotes for an asset older than `max_age_secs` (0 = no limit)
+    pub fn set_feed_max_age(env: Env, asset: String, max_age_secs: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let key = OracleKey::FeedMaxAge(asset);
+        if max_age_secs == 0 {
+            env.storage().instance().remove(&key);
+        } else {
+            env.storage().instance().set(&key, &max_age_secs);
+        }
+    }
+
+    /// Get the maximum quote age set for an asset
+    pub fn get_feed_max_age(env: Env, asset: String) -> Option<u64> {
+        env.storage().instance().get(&OracleKey::FeedMaxAge(asset))
+    }
+}
+
+fn price_sources(env: &Env, asset: &String) -> Vec<PriceSource> {
+    env.storage().instance()
+        .get(&OracleKey::AssetOracles(asset.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+/// Quote from one source in base-asset units per whole unit of the asset
+fn quote(env: &Env, config: &VaultConfig, source: &PriceSource, max_age: Option<u64>) -> i128 {
+    let price = match max_age {
+        Some(max_age) => {
+            let data = match source.kind {
+                OracleKind::Vault => VaultOracle::lastprice(env, source),
+                OracleKind::Reflector => reflector::ReflectorAdapter::lastprice(env, source),
+            };
+            match data {
+                Some(data) if env.ledger().timestamp() <= data.timestamp + max_age => data.price,
+                _ => panic_with_error!(env, OracleError::StaleOracle),
+            }
+        }
+        None => match source.kind {
+            OracleKind::Vault => VaultOracle::price(env, source),
+            OracleKind::Reflector => reflector::ReflectorAdapter::price(env, source),
+        },
+    };
+    price * assets::unit_scale(env, &config.base_asset) / 10i128.pow(source.decimals)
+}
+
+/// Oracle quotes for `asset`, sorted ascending
+fn quotes(env: &Env, config: &VaultConfig, asset: &String) -> Vec<i128> {
+    let mut sources = price_sources(env, asset);
+    if sources.is_empty() {
+        match &config.price_oracle {
+            Some(oracle) => sources.push_back(PriceSource {
+                kind: OracleKind::Vault,
+                contract: oracle.clone(),
+                feed_id: assets::feed_id(env, asset),
+                decimals: assets::decimals(env, &config.base_asset),
+            }),
+            None => panic!("Price oracle not configured"),
+        }
+    }
+
+    let max_age: Option<u64> = env.storage().instance().get(&OracleKey::FeedMaxAge(asset.clone()));
+    let mut sorted: Vec<i128> = Vec::new(env);
+    for source in sources.iter() {
+        let price = quote(env, config, &source, max_age);
+        let at = sorted.iter().position(|quote| quote > price).unwrap_or(sorted.len() as usize);
+        sorted.insert(at as u32, price);
+    }
+    sorted
+}
+
+/// Median oracle price of one whole unit of `asset`
+pub(crate) fn price(env: &Env, config: &VaultConfig, asset: &String) -> i128 {
+    median(&quotes(env, config, asset))
+}
+
+fn median(sorted: &Vec<i128>) -> i128 {
+    let mid = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted.get(mid - 1).unwrap() + sorted.get(mid).unwrap()) / 2
+    } else {
+        sorted.get(mid).unwrap()
+    }
+}
+
+/// Refuse to trade `asset` on a quote older than its maximum age
+pub(crate) fn check_fresh(env: &Env, config: &VaultConfig, asset: &String) {
+    if *asset != config.base_asset && env.storage().instance().has(&OracleKey::FeedMaxAge(asset.clone())) {
+        quotes(env, config, asset);
+    }
+}
+
+/// Refuse to trade `asset` while its oracles disagree beyond the limit
+pub(crate) fn check_spread(env: &Env, config: &VaultConfig, asset: &String) {
+    if config.max_oracle_spread_bps == 0 || *asset == config.base_asset {
+        return;
+    }
+    if price_sources(env, asset).len() < 2 {
+        return;
+    }
+
+    let sorted = quotes(env, config, asset);
+    let median = median(&sorted);
+    let spread = sorted.last().unwrap() - sorted.first().unwrap();
+    if median <= 0 || spread * 10000 > median * config.max_oracle_spread_bps as i128 {
+        panic!("Oracle sources disagree");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::{setup, MockOracle, MockOracleClient};
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_oracle_median_and_spread() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+
+        let mut sources = Vec::new(&env);
+        let mut feeds = Vec::new(&env);
+        for _ in 0..MAX_ORACLE_SOURCES {
+            let oracle = env.register_contract(None, MockOracle);
+            sources.push_back(oracle.clone());
+            feeds.push_back(oracle);
+        }
+        let set_prices = |prices: [i128; 3]| {
+            for (source, price) in feeds.iter().zip(prices) {
+                MockOracleClient::new(&env, &source).set_price(&btc, &price);
+            }
+        };
+        vault.client.set_asset_oracles(&btc, &sources);
+        vault.client.set_max_oracle_spread(&500);
+
+        let mut too_many = sources.clone();
+        too_many.push_back(sources.get(0).unwrap());
+        assert!(vault.client.try_set_asset_oracles(&btc, &too_many).is_err());
+
+        let submit = || {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        let execute = |signal_id: u64| {
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None)
+        };
+
+        let median_price = || {
+            let config = vault.client.get_config();
+            env.as_contract(&vault.client.address, || price(&env, &config, &btc))
+        };
+
+        set_prices([100_0000000, 101_0000000, 100_5000000]);
+        assert_eq!(median_price(), 100_5000000);
+        assert!(execute(submit()).is_ok());
+
+        // One corrupted feed leaves the median alone but blocks execution
+        set_prices([100_0000000, 500_0000000, 100_5000000]);
+        assert_eq!(median_price(), 100_5000000);
+        assert!(execute(submit()).is_err());
+
+        // Two sources average
+        sources.pop_back();
+        vault.client.set_asset_oracles(&btc, &sources);
+        assert_eq!(median_price(), 300_0000000);
+    }
+
+    #[test]
+    fn test_stale_oracle() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let btc = String::from_str(&env, "BTC");
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        oracle.set_price(&btc, &100_0000000);
+        vault.client.set_feed_max_age(&btc, &300);
+        assert_eq!(vault.client.get_feed_max_age(&btc), Some(300));
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &1_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+        assert_eq!(vault.client.get_position(&btc), 1_0000000);
+        assert!(vault.client.try_compute_nav().is_ok());
+
+        // Past the limit both valuation and execution refuse the quote
+        env.ledger().with_mut(|l| l.timestamp += 301);
+        let stale = Some(Ok(soroban_sdk::Error::from_contract_error(OracleError::StaleOracle as u32)));
+        assert_eq!(vault.client.try_compute_nav().err(), stale);
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Sell,
+            &1_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &-250,
+            &None,
+            &None,
+        );
+        assert_eq!(vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None).err(), stale);
+        assert_eq!(vault.client.try_execute_best(&vault.payment_agent, &signal_id, &None).err(), stale);
+
+        oracle.set_price(&btc, &100_0000000);
+        assert!(vault.client.try_compute_nav().is_ok());
+    }
+}
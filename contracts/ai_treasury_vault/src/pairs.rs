@@ -0,0 +1,261 @@
+//! Atomic pair trades (sell one asset, buy another).
+//!
+//! A pair signal shares the signal id space with ordinary signals, so it is
+//! approved through `approve_trade` like any other signal. Execution books
+//! both legs in a single invocation; if either leg fails the whole call is
+//! rolled back and the portfolio is never left half-rotated.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env, String, Symbol, Vec};
+
+use crate::inference::SignalLeg;
+use crate::models::Provenance;
+use crate::{agents, assets, attestations, fees, guard, inference, models, oracle, portfolio, roles, staking, storage};
+use crate::{
+    check_executable, check_signal, record_trades, AITreasuryVaultV2, AITreasuryVaultV2Client,
+    DataKey, Fill, TradeAction, VaultConfig,
+};
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PairSignal {
+    pub signal_id: u64,
+    pub sell_asset: String,
+    pub sell_amount: i128,
+    pub buy_asset: String,
+    pub buy_amount: i128,
+    pub strategy: String,
+    pub confidence: u32,  // 0-100
+    pub expected_return: i32,  // basis points
+    pub timestamp: u64,
+    pub model_id: Option<Symbol>,  // registered model that produced it
+    pub model_version: u32,  // version of that model (0 without one)
+    pub run_id: Option<u64>,  // anchored inference run it came from
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Submit a signal to rotate `sell_amount` of one asset into another
+    pub fn submit_pair_trade(
+        env: Env,
+        caller: Address,
+        sell_asset: String,
+        sell_amount: i128,
+        buy_asset: String,
+        buy_amount: i128,
+        strategy: String,
+        confidence: u32,
+        expected_return: i32,
+        nonce: Option<u64>,
+        provenance: Option<Provenance>,
+    ) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::TRADING_AGENT);
+        agents::throttle(&env, &config, &caller);
+        staking::require_bond(&env, &caller);
+
+        if let Some(nonce) = nonce {
+            if let Some(signal_id) = env.storage().temporary().get(&DataKey::SignalNonce(nonce)) {
+                return signal_id;
+            }
+        }
+
+        if sell_asset == buy_asset {
+            panic!("Pair legs must differ");
+        }
+        if sell_amount <= 0 || buy_amount <= 0 {
+            panic!("Leg amounts must be positive");
+        }
+        check_signal(&env, &config, sell_amount.max(buy_amount), confidence, expected_return);
+        assets::check_trade_limit(&env, &sell_asset, sell_amount);
+        assets::check_trade_limit(&env, &buy_asset, buy_amount);
+        let (model_id, model_version) = models::check_provenance(&env, &provenance);
+        let run_id = attestations::check_attestation(&env, &provenance);
+        let legs = Vec::from_array(&env, [
+            SignalLeg { asset: sell_asset.clone(), action: TradeAction::Sell, amount: sell_amount },
+            SignalLeg { asset: buy_asset.clone(), action: TradeAction::Buy, amount: buy_amount },
+        ]);
+        inference::check_signature(&env, &provenance, legs, &strategy, confidence, expected_return);
+
+        let signal_id: u64 = env.storage().instance()
+            .get(&DataKey::SignalCounter).unwrap_or(0) + 1;
+        let signal = PairSignal {
+            signal_id,
+            sell_asset,
+            sell_amount,
+            buy_asset,
+            buy_amount,
+            strategy,
+            confidence,
+            expected_return,
+            timestamp: env.ledger().timestamp(),
+            model_id,
+            model_version,
+            run_id,
+        };
+
+        env.storage().instance().set(&DataKey::SignalCounter, &signal_id);
+        storage::set_temporary(&env, &DataKey::PairSignal(signal_id), &signal);
+        if let Some(nonce) = nonce {
+            storage::set_temporary(&env, &DataKey::SignalNonce(nonce), &signal_id);
+        }
+        agents::record_signal(&env, &caller);
+        storage::extend_instance(&env);
+
+        signal_id
+    }
+
+    /// Execute both legs of an approved pair signal as one unit
+    ///
+    /// Returns the (sell, buy) trade ids; like `execute_trade`, a repeated
+    /// call returns the ids recorded the first time.
+    pub fn execute_pair_trade(
+        env: Env,
+        caller: Address,
+        signal_id: u64,
+        sell_price: i128,
+        buy_price: i128,
+        nonce: Option<u64>,
+    ) -> (u64, u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::PAYMENT_AGENT);
+        let _lock = guard::lock(&env, symbol_short!("pair"));
+        agents::throttle(&env, &config, &caller);
+        staking::require_bond(&env, &caller);
+
+        if let Some(nonce) = nonce {
+            if let Some(trade_id) = env.storage().temporary().get::<_, u64>(&DataKey::ExecutionNonce(nonce)) {
+                return (trade_id, trade_id + 1);
+            }
+        }
+
+        // Both legs are recorded back to back
+        if let Some(trade_id) = storage::get_persistent::<u64>(&env, &DataKey::Executed(signal_id)) {
+            return (trade_id, trade_id + 1);
+        }
+
+        let signal = pair_signal(&env, signal_id).expect("No such pair signal");
+        check_executable(&env, &config, signal_id, &signal.strategy, signal.sell_amount.max(signal.buy_amount));
+        assets::check_trade_limit(&env, &signal.sell_asset, signal.sell_amount);
+        assets::check_trade_limit(&env, &signal.buy_asset, signal.buy_amount);
+        oracle::check_spread(&env, &config, &signal.sell_asset);
+        oracle::check_spread(&env, &config, &signal.buy_asset);
+
+        let sell = Fill {
+            asset: signal.sell_asset,
+            action: TradeAction::Sell,
+            amount: signal.sell_amount,
+            price: sell_price,
+            venue: None,
+        };
+        let buy = Fill {
+            asset: signal.buy_asset,
+            action: TradeAction::Buy,
+            amount: signal.buy_amount,
+            price: buy_price,
+            venue: None,
+        };
+        let fills = [sell, buy];
+        let base_before = portfolio::position(&env, &config.base_asset);
+        let sell_id = record_trades(&env, &config, signal_id, &signal.strategy, signal.confidence, signal.expected_return, &fills);
+        fees::check_reserve(&env, &config, base_before);
+        agents::record_execution(&env, &config, &caller, &fills);
+        let buy_id = sell_id + 1;
+        models::record_trade_model(&env, sell_id, &signal.model_id, signal.model_version);
+        models::record_trade_model(&env, buy_id, &signal.model_id, signal.model_version);
+        attestations::record_trade_run(&env, sell_id, signal.run_id);
+        attestations::record_trade_run(&env, buy_id, signal.run_id);
+
+        storage::set_persistent(&env, &DataKey::Executed(signal_id), &sell_id);
+        if let Some(nonce) = nonce {
+            storage::set_temporary(&env, &DataKey::ExecutionNonce(nonce), &sell_id);
+        }
+        storage::extend_instance(&env);
+
+        (sell_id, buy_id)
+    }
+
+    /// Get a pending pair signal
+    pub fn get_pair_signal(env: Env, signal_id: u64) -> Option<PairSignal> {
+        pair_signal(&env, signal_id)
+    }
+}
+
+pub(crate) fn pair_signal(env: &Env, signal_id: u64) -> Option<PairSignal> {
+    env.storage().temporary().get(&DataKey::PairSignal(signal_id))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::RiskMetrics;
+
+    #[test]
+    fn test_pair_trade_is_atomic() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.register_oracle(&env);
+        let btc = String::from_str(&env, "BTC");
+        let eth = String::from_str(&env, "ETH");
+        let xlm = String::from_str(&env, "XLM");
+
+        let buy_btc = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &1_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &buy_btc, &40000_0000000, &None);
+
+        let rotate = |sell_amount: i128| {
+            vault.client.submit_pair_trade(
+                &vault.trading_agent,
+                &btc,
+                &sell_amount,
+                &eth,
+                &20_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &300,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+
+        // Selling more BTC than held fails the sell leg, so ETH is not bought either
+        let too_big = rotate(2_0000000);
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &too_big, &metrics));
+        assert!(vault.client
+            .try_execute_pair_trade(&vault.payment_agent, &too_big, &40000_0000000, &2000_0000000, &None)
+            .is_err());
+        assert_eq!(vault.client.get_position(&eth), 0);
+        assert_eq!(vault.client.get_position(&btc), 1_0000000);
+
+        let signal_id = rotate(1_0000000);
+        assert!(vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        let (sell_id, buy_id) = vault.client
+            .execute_pair_trade(&vault.payment_agent, &signal_id, &40000_0000000, &2000_0000000, &None);
+        assert_eq!((sell_id, buy_id), (2, 3));
+        assert_eq!(vault.client.get_position(&btc), 0);
+        assert_eq!(vault.client.get_position(&eth), 20_0000000);
+        assert_eq!(vault.client.get_position(&xlm), -40000_0000000);
+
+        // Retries return the same legs
+        assert_eq!(
+            vault.client.execute_pair_trade(&vault.payment_agent, &signal_id, &1, &1, &None),
+            (2, 3)
+        );
+    }
+}
@@ -0,0 +1,469 @@
+//! Scheduled beneficiary payouts.
+//!
+//! The admin schedules a payment of a token to a beneficiary with a release
+//! time; the payment agent executes it at or after that time, and the admin
+//! can cancel it until then. Each payment settles a registered invoice and
+//! names a budget category, whose envelope it is charged against when
+//! executed. Paying out the base token or a registered asset's token draws
+//! down the matching position, must respect the operating reserve and is
+//! charged to the payment agent's notional allowance. The payment agent can
+//! also pay a batch of invoices at once, all or nothing. Every payment stays
+//! on record with its outcome, so `get_payments` doubles as the payment
+//! history.
+//!
+//! A payment in a registered asset the vault holds too little of first buys
+//! the shortfall with the base asset through the DEX with the cheapest fee
+//! tier. The swap may cost no more than the oracle value grossed up by that
+//! fee and the payment slippage bound, and both legs are booked from the
+//! balances actually moved.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, token, Address, Env, Symbol, Vec};
+
+use crate::{agents, assets, budgets, compliance, fees, guard, invoices, portfolio, roles, storage};
+use crate::venues::DexRouterClient;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Most payments `get_payments` returns in one call
+pub const MAX_PAYMENTS_PER_PAGE: u32 = 100;
+
+/// Most payments `pay_batch` makes in one call
+pub const MAX_BATCH_PAYMENTS: u32 = 20;
+
+/// Slippage allowed on payment swaps unless the admin sets another bound
+pub const DEFAULT_PAYMENT_SLIPPAGE_BPS: u32 = 100;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum PayoutKey {
+    PaymentCount,
+    PaymentSlippage,  // bps over the oracle price payment swaps may pay
+    Payment(u64),  // payment_id -> scheduled payment and its outcome (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum PaymentStatus {
+    Scheduled,
+    Paid(u64),  // paid at
+    Cancelled(u64),  // cancelled at
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct ScheduledPayment {
+    pub payment_id: u64,
+    pub beneficiary: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub release_at: u64,
+    pub category: Symbol,  // budget category the payment is charged to
+    pub invoice_id: u64,  // invoice the payment settles
+    pub status: PaymentStatus,
+}
+
+/// One leg of `pay_batch`, settling a registered invoice
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PaymentInput {
+    pub beneficiary: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub category: Symbol,
+    pub invoice_id: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Schedule a payment of invoice `invoice_id` the payment agent may make
+    /// from `release_at` on, charged to budget `category`
+    pub fn schedule_payment(
+        env: Env,
+        beneficiary: Address,
+        token: Address,
+        amount: i128,
+        release_at: u64,
+        category: Symbol,
+        invoice_id: u64,
+    ) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let input = PaymentInput { beneficiary, token, amount, category, invoice_id };
+        let payment = new_payment(&env, input, release_at);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("payout"), symbol_short!("schedule"), payment.payment_id), amount);
+        payment.payment_id
+    }
+
+    /// Make a released payment (payment agent)
+    pub fn execute_payment(env: Env, caller: Address, payment_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::PAYMENT_AGENT);
+        let _lock = guard::lock(&env, symbol_short!("payout"));
+
+        let mut payment = payment(&env, payment_id);
+        if payment.status != PaymentStatus::Scheduled {
+            panic!("Payment is not scheduled");
+        }
+        if env.ledger().timestamp() < payment.release_at {
+            panic!("Payment is not released yet");
+        }
+        let value = execute(&env, &config, &mut payment);
+        agents::spend_allowance(&env, &config, &caller, value);
+        storage::extend_instance(&env);
+    }
+
+    /// Pay a batch of invoices at once (payment agent); fails as a whole if
+    /// any leg fails or the batch exceeds the agent's allowance
+    pub fn pay_batch(env: Env, caller: Address, payments: Vec<PaymentInput>) -> Vec<u64> {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::PAYMENT_AGENT);
+        let _lock = guard::lock(&env, symbol_short!("payout"));
+
+        if payments.is_empty() || payments.len() > MAX_BATCH_PAYMENTS {
+            panic!("Invalid batch size");
+        }
+
+        let now = env.ledger().timestamp();
+        let mut payment_ids = Vec::new(&env);
+        let mut value = 0;
+        for input in payments.iter() {
+            let mut payment = new_payment(&env, input, now);
+            value += execute(&env, &config, &mut payment);
+            payment_ids.push_back(payment.payment_id);
+        }
+        agents::spend_allowance(&env, &config, &caller, value);
+        storage::extend_instance(&env);
+        payment_ids
+    }
+
+    /// Cancel a payment before its release time (admin)
+    pub fn cancel_payment(env: Env, payment_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let mut payment = payment(&env, payment_id);
+        if payment.status != PaymentStatus::Scheduled {
+            panic!("Payment is not scheduled");
+        }
+        let now = env.ledger().timestamp();
+        if now >= payment.release_at {
+            panic!("Payment already released");
+        }
+        payment.status = PaymentStatus::Cancelled(now);
+        invoices::reopen(&env, payment.invoice_id);
+        store_payment(&env, &payment);
+
+        env.events().publish((symbol_short!("payout"), symbol_short!("cancel"), payment_id), payment.amount);
+    }
+
+    /// Bound what payment swaps may pay over the oracle price, in bps
+    pub fn set_payment_slippage(env: Env, slippage_bps: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if slippage_bps > 10000 {
+            panic!("Slippage exceeds 100%");
+        }
+        env.storage().instance().set(&PayoutKey::PaymentSlippage, &slippage_bps);
+    }
+
+    /// Get the slippage bound on payment swaps, in bps
+    pub fn get_payment_slippage(env: Env) -> u32 {
+        payment_slippage(&env)
+    }
+
+    /// Get a scheduled payment
+    pub fn get_payment(env: Env, payment_id: u64) -> Option<ScheduledPayment> {
+        env.storage().persistent().get(&PayoutKey::Payment(payment_id))
+    }
+
+    /// Get up to `limit` payments from `start`, oldest first
+    pub fn get_payments(env: Env, start: u64, limit: u32) -> Vec<ScheduledPayment> {
+        let count: u64 = env.storage().instance().get(&PayoutKey::PaymentCount).unwrap_or(0);
+        let end = (start + limit.min(MAX_PAYMENTS_PER_PAGE) as u64).min(count + 1);
+
+        let mut payments = Vec::new(&env);
+        for payment_id in start.max(1)..end {
+            if let Some(payment) = Self::get_payment(env.clone(), payment_id) {
+                payments.push_back(payment);
+            }
+        }
+        payments
+    }
+}
+
+/// Record a payment settling `input.invoice_id`, payable from `release_at`
+fn new_payment(env: &Env, input: PaymentInput, release_at: u64) -> ScheduledPayment {
+    if input.amount <= 0 {
+        panic!("Amount must be positive");
+    }
+    budgets::check_category(env, &input.category, &input.token);
+
+    let payment_id: u64 = env.storage().instance().get(&PayoutKey::PaymentCount).unwrap_or(0) + 1;
+    invoices::schedule(env, input.invoice_id, payment_id, &input.beneficiary, input.amount);
+    let payment = ScheduledPayment {
+        payment_id,
+        beneficiary: input.beneficiary,
+        token: input.token,
+        amount: input.amount,
+        release_at,
+        category: input.category,
+        invoice_id: input.invoice_id,
+        status: PaymentStatus::Scheduled,
+    };
+    store_payment(env, &payment);
+    env.storage().instance().set(&PayoutKey::PaymentCount, &payment_id);
+    payment
+}
+
+/// Make a released payment against its budget and invoice; returns its
+/// base-asset value
+fn execute(env: &Env, config: &VaultConfig, payment: &mut ScheduledPayment) -> i128 {
+    budgets::charge(env, &payment.category, &payment.token, payment.amount);
+    invoices::settle(env, payment.invoice_id, payment.payment_id);
+    payment.status = PaymentStatus::Paid(env.ledger().timestamp());
+    store_payment(env, payment);
+
+    swap_for_payment(env, config, &payment.token, payment.amount);
+    let value = pay_out(env, config, &payment.token, &payment.beneficiary, payment.amount);
+    env.events().publish((symbol_short!("payout"), symbol_short!("paid"), payment.payment_id), payment.amount);
+    value
+}
+
+fn payment_slippage(env: &Env) -> u32 {
+    env.storage().instance()
+        .get(&PayoutKey::PaymentSlippage)
+        .unwrap_or(DEFAULT_PAYMENT_SLIPPAGE_BPS)
+}
+
+/// Buy with the base asset whatever a payment of `amount` of a registered
+/// asset's token needs beyond the vault's position in it
+fn swap_for_payment(env: &Env, config: &VaultConfig, token: &Address, amount: i128) {
+    let asset = match assets::asset_for_token(env, config, token) {
+        Some(asset) if asset != config.base_asset => asset,
+        _ => return,
+    };
+    let shortfall = amount - portfolio::position(env, &asset).max(0);
+    if shortfall <= 0 {
+        return;
+    }
+
+    let (router, fee_bps) = fees::cheapest_dex(env).expect("No DEX registered to swap through");
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    let bound_bps = 10000 + fee_bps as i128 + payment_slippage(env) as i128;
+    let max_in = portfolio::value_of(env, config, &asset, shortfall) * bound_bps / 10000;
+
+    // Book what actually moved rather than what the router reports
+    let vault = env.current_contract_address();
+    let base_client = token::Client::new(env, &base_token);
+    let out_client = token::Client::new(env, token);
+    let (base_balance, out_balance) = (base_client.balance(&vault), out_client.balance(&vault));
+    base_client.transfer(&vault, &router, &max_in);
+    DexRouterClient::new(env, &router).swap_exact_out(&vault, &base_token, token, &shortfall, &max_in);
+    let spent = base_balance - base_client.balance(&vault);
+    let received = out_client.balance(&vault) - out_balance;
+    if spent > max_in || received < shortfall {
+        panic!("Swap outside slippage bound");
+    }
+
+    let base_before = portfolio::position(env, &config.base_asset);
+    portfolio::adjust_position(env, &config.base_asset, -spent);
+    portfolio::adjust_position(env, &asset, received);
+    fees::check_reserve(env, config, base_before);
+    env.events().publish((symbol_short!("payout"), symbol_short!("swap"), asset), (spent, received));
+}
+
+/// Whether the vault holds enough of `token`, and of the position it backs
+/// above the operating reserve, to pay out `amount`
+pub(crate) fn can_pay(env: &Env, config: &VaultConfig, token: &Address, amount: i128) -> bool {
+    if token::Client::new(env, token).balance(&env.current_contract_address()) < amount {
+        return false;
+    }
+    match assets::asset_for_token(env, config, token) {
+        Some(asset) if asset == config.base_asset => {
+            portfolio::position(env, &asset) - amount >= fees::operating_reserve(env)
+        }
+        Some(asset) => portfolio::position(env, &asset) >= amount,
+        None => true,
+    }
+}
+
+/// Send `amount` of `token` to `to`, drawing down the position a managed
+/// token backs; returns the base-asset value paid out (0 for tokens the
+/// vault does not manage). Callers record their own state first.
+pub(crate) fn pay_out(env: &Env, config: &VaultConfig, token: &Address, to: &Address, amount: i128) -> i128 {
+    compliance::check_recipient(env, config, to);
+
+    // Managed tokens leave the book as well as the balance
+    let mut value = 0;
+    if let Some(asset) = assets::asset_for_token(env, config, token) {
+        let base_before = portfolio::position(env, &config.base_asset);
+        portfolio::adjust_position(env, &asset, -amount);
+        fees::check_reserve(env, config, base_before);
+        value = portfolio::value_of(env, config, &asset, amount);
+    }
+    token::Client::new(env, token).transfer(&env.current_contract_address(), to, &amount);
+    value
+}
+
+fn payment(env: &Env, payment_id: u64) -> ScheduledPayment {
+    env.storage().persistent()
+        .get(&PayoutKey::Payment(payment_id))
+        .expect("No such payment")
+}
+
+fn store_payment(env: &Env, payment: &ScheduledPayment) {
+    let key = PayoutKey::Payment(payment.payment_id);
+    env.storage().persistent().set(&key, payment);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::{contract, symbol_short, BytesN, String};
+
+    #[test]
+    fn test_scheduled_payouts() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        let grants = symbol_short!("grants");
+        vault.client.set_budget(&grants, &token.address, &1000_0000000);
+
+        let grantee = Address::generate(&env);
+        let invoice = vault.client.register_invoice(&BytesN::from_array(&env, &[1; 32]), &grantee, &100_0000000, &2000);
+        let first = vault.client.schedule_payment(&grantee, &token.address, &100_0000000, &2000, &grants, &invoice);
+        let invoice = vault.client.register_invoice(&BytesN::from_array(&env, &[2; 32]), &grantee, &50_0000000, &3000);
+        let second = vault.client.schedule_payment(&grantee, &token.address, &50_0000000, &3000, &grants, &invoice);
+
+        // Only the payment agent, only once released
+        assert!(vault.client.try_execute_payment(&vault.payment_agent, &first).is_err());
+        env.ledger().with_mut(|l| l.timestamp = 2000);
+        assert!(vault.client.try_execute_payment(&vault.trading_agent, &first).is_err());
+        vault.client.execute_payment(&vault.payment_agent, &first);
+        assert!(vault.client.try_execute_payment(&vault.payment_agent, &first).is_err());
+        assert_eq!(token::Client::new(&env, &token.address).balance(&grantee), 100_0000000);
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 900_0000000);
+
+        // Cancellable until released
+        vault.client.cancel_payment(&second);
+        assert!(vault.client.try_execute_payment(&vault.payment_agent, &second).is_err());
+
+        let history = vault.client.get_payments(&1, &10);
+        assert_eq!(history.len(), 2);
+        assert_eq!(history.get(0).unwrap().status, PaymentStatus::Paid(2000));
+        assert_eq!(history.get(1).unwrap().status, PaymentStatus::Cancelled(2000));
+    }
+
+    #[test]
+    fn test_pay_batch_is_atomic() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let ops = symbol_short!("ops");
+        vault.client.set_budget(&ops, &token.address, &1000_0000000);
+        vault.client.set_agent_allowance(&vault.payment_agent, &100_0000000);
+
+        let vendors = [Address::generate(&env), Address::generate(&env)];
+        let mut batch = Vec::new(&env);
+        for (n, vendor) in vendors.iter().enumerate() {
+            let hash = BytesN::from_array(&env, &[n as u8 + 1; 32]);
+            let invoice_id = vault.client.register_invoice(&hash, vendor, &40_0000000, &0);
+            batch.push_back(PaymentInput {
+                beneficiary: vendor.clone(),
+                token: token.address.clone(),
+                amount: 40_0000000,
+                category: ops.clone(),
+                invoice_id,
+            });
+        }
+
+        // A third leg takes the batch past the allowance, so nothing is paid
+        let hash = BytesN::from_array(&env, &[3; 32]);
+        let invoice_id = vault.client.register_invoice(&hash, &vendors[0], &40_0000000, &0);
+        let mut over = batch.clone();
+        over.push_back(PaymentInput { invoice_id, ..batch.get(0).unwrap() });
+        assert!(vault.client.try_pay_batch(&vault.payment_agent, &over).is_err());
+        assert_eq!(token::Client::new(&env, &token.address).balance(&vendors[0]), 0);
+
+        let payment_ids = vault.client.pay_batch(&vault.payment_agent, &batch);
+        assert_eq!(payment_ids.len(), 2);
+        for vendor in vendors.iter() {
+            assert_eq!(token::Client::new(&env, &token.address).balance(vendor), 40_0000000);
+        }
+        assert_eq!(vault.client.get_remaining_allowance(&vault.payment_agent), Some(20_0000000));
+        assert!(vault.client.try_pay_batch(&vault.payment_agent, &batch).is_err());
+    }
+
+    /// Router filling swaps at a fixed rate of base units per whole output unit
+    #[contract]
+    pub struct MockRouter;
+
+    #[contractimpl]
+    impl MockRouter {
+        pub fn set_rate(env: Env, rate: i128) {
+            env.storage().instance().set(&symbol_short!("rate"), &rate);
+        }
+
+        pub fn swap_exact_out(env: Env, to: Address, token_in: Address, token_out: Address, amount_out: i128, max_in: i128) -> i128 {
+            let rate: i128 = env.storage().instance().get(&symbol_short!("rate")).unwrap();
+            let used = amount_out * rate / 1_0000000;
+            let router = env.current_contract_address();
+            token::Client::new(&env, &token_out).transfer(&router, &to, &amount_out);
+            token::Client::new(&env, &token_in).transfer(&router, &to, &(max_in - used));
+            used
+        }
+    }
+
+    #[test]
+    fn test_payment_swaps_shortfall() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        let usdc = String::from_str(&env, "USDC");
+        let usdc_token = StellarAssetClient::new(&env, &env.register_stellar_asset_contract_v2(vault.admin.clone()).address());
+        vault.client.register_asset(&usdc, &usdc_token.address, &7, &usdc);
+        oracle.set_price(&usdc, &2_0000000);
+        let router = env.register_contract(None, MockRouter);
+        usdc_token.mint(&router, &1000_0000000);
+        vault.client.register_dex_fee_tier(&router, &30);
+
+        let ops = symbol_short!("ops");
+        vault.client.set_budget(&ops, &usdc_token.address, &1000_0000000);
+        let vendor = Address::generate(&env);
+        let invoice_id = vault.client.register_invoice(&BytesN::from_array(&env, &[1; 32]), &vendor, &50_0000000, &0);
+        let payment_id = vault.client.schedule_payment(&vendor, &usdc_token.address, &50_0000000, &0, &ops, &invoice_id);
+
+        // 2.1 per USDC is past the 0.3% fee and 1% slippage over the oracle's 2
+        let router = MockRouterClient::new(&env, &router);
+        router.set_rate(&2_1000000);
+        assert!(vault.client.try_execute_payment(&vault.payment_agent, &payment_id).is_err());
+
+        router.set_rate(&2_0200000);
+        vault.client.execute_payment(&vault.payment_agent, &payment_id);
+        assert_eq!(token::Client::new(&env, &usdc_token.address).balance(&vendor), 50_0000000);
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 899_0000000);
+        assert_eq!(vault.client.get_position(&usdc), 0);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&vault.client.address), 899_0000000);
+    }
+}
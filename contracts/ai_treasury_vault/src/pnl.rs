@@ -0,0 +1,189 @@
+//! Realized and unrealized P&L.
+//!
+//! Every fill updates the cost basis of its position on the average-cost
+//! method: adding to a position adds the fill's notional, reducing it
+//! releases cost pro rata and realizes the difference. Short positions carry
+//! a negative cost basis (the proceeds received). Unrealized P&L marks each
+//! position to the oracle against its remaining cost basis. The realized
+//! figure is what each trade record's `profit_loss` holds, so the payment
+//! agent no longer reports P&L.
+
+use soroban_sdk::{contractimpl, contracttype, Env, String};
+
+use crate::{assets, portfolio, reporting};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum PnlKey {
+    CostBasis(String),  // asset -> signed base-asset cost of the open position
+    RealizedPnl,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PnlBreakdown {
+    pub realized: i128,  // booked on-chain from closed quantity
+    pub unrealized: i128,  // open positions marked to the oracle
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Get realized and unrealized P&L in the base asset
+    pub fn get_pnl_breakdown(env: Env) -> PnlBreakdown {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        let mut unrealized = 0;
+        for asset in portfolio::held_assets(&env).iter() {
+            if asset == config.base_asset {
+                continue;
+            }
+            let quantity = portfolio::position(&env, &asset);
+            unrealized += portfolio::value_of(&env, &config, &asset, quantity) - cost_basis(&env, &asset);
+        }
+
+        let realized = env.storage().instance().get(&PnlKey::RealizedPnl).unwrap_or(0);
+        PnlBreakdown {
+            realized: reporting::to_reporting(&env, &config, realized),
+            unrealized: reporting::to_reporting(&env, &config, unrealized),
+        }
+    }
+
+    /// Average entry price of the open position per whole unit (0 when flat)
+    pub fn get_average_entry_price(env: Env, asset: String) -> i128 {
+        let quantity = portfolio::position(&env, &asset);
+        if quantity == 0 {
+            return 0;
+        }
+        (cost_basis(&env, &asset) * assets::unit_scale(&env, &asset) / quantity).abs()
+    }
+}
+
+pub(crate) fn cost_basis(env: &Env, asset: &String) -> i128 {
+    env.storage().instance()
+        .get(&PnlKey::CostBasis(asset.clone()))
+        .unwrap_or(0)
+}
+
+/// Update the cost basis for a fill on a position of `held` before the
+/// fill; returns the P&L it realizes
+pub(crate) fn book_fill(
+    env: &Env,
+    asset: &String,
+    held: i128,
+    action: TradeAction,
+    amount: i128,
+    notional: i128,
+) -> i128 {
+    let (delta, value) = match action {
+        TradeAction::Buy => (amount, notional),
+        TradeAction::Sell => (-amount, -notional),
+        TradeAction::Hold => return 0,
+    };
+    if delta == 0 {
+        return 0;
+    }
+
+    let mut cost = cost_basis(env, asset);
+    let mut realized = 0;
+
+    if held == 0 || (held > 0) == (delta > 0) {
+        cost += value;
+    } else {
+        // Close against the open position at its average cost first
+        let closing = delta.abs().min(held.abs());
+        let released = cost * closing / held.abs();
+        let closing_value = value * closing / delta.abs();
+        realized = -(closing_value + released);
+        cost += value - closing_value - released;
+    }
+
+    if held + delta == 0 {
+        cost = 0;
+    }
+    let key = PnlKey::CostBasis(asset.clone());
+    if cost == 0 {
+        env.storage().instance().remove(&key);
+    } else {
+        env.storage().instance().set(&key, &cost);
+    }
+
+    book_realized(env, realized);
+    realized
+}
+
+/// Add base-asset P&L realized outside a fill
+pub(crate) fn book_realized(env: &Env, realized: i128) {
+    if realized != 0 {
+        let total: i128 = env.storage().instance().get(&PnlKey::RealizedPnl).unwrap_or(0);
+        env.storage().instance().set(&PnlKey::RealizedPnl, &(total + realized));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+
+    #[test]
+    fn test_pnl_breakdown() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+        vault.apply_change(&env, &ConfigChange::ShortSelling(true, 15000));
+
+        let trade = |action: TradeAction, amount: i128, price: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &price, &None);
+        };
+
+        // Buy 2 at 100 and 2 at 120: average cost 110
+        trade(TradeAction::Buy, 2_0000000, 100_0000000);
+        trade(TradeAction::Buy, 2_0000000, 120_0000000);
+        assert_eq!(cost_basis_of(&env, &vault.client.address, &btc), 440_0000000);
+        assert_eq!(vault.client.get_average_entry_price(&btc), 110_0000000);
+
+        // Selling 1 at 130 realizes 20, recorded on the trade itself
+        trade(TradeAction::Sell, 1_0000000, 130_0000000);
+        assert_eq!(vault.client.get_trade(&3).profit_loss, 20_0000000);
+        assert_eq!(vault.client.get_trade(&3).realized_return_bps, 1538);  // 20 / 130
+
+        // Compared with the 2.5% forecast
+        let perf = vault.client.get_strategy_performance(&String::from_str(&env, "LSTM"));
+        assert_eq!((perf.predictions, perf.avg_prediction_error), (1, 1288));
+        oracle.set_price(&btc, &150_0000000);
+        let pnl = vault.client.get_pnl_breakdown();
+        assert_eq!(pnl.realized, 20_0000000);
+        assert_eq!(pnl.unrealized, 120_0000000);  // 3 * (150 - 110)
+
+        // Selling 5 closes the 3 held at 140 and opens a 2 short at 140
+        trade(TradeAction::Sell, 5_0000000, 140_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, 110_0000000);
+        assert_eq!(cost_basis_of(&env, &vault.client.address, &btc), -280_0000000);
+        assert_eq!(vault.client.get_average_entry_price(&btc), 140_0000000);
+
+        // Covering at 120 realizes 2 * 20
+        trade(TradeAction::Buy, 2_0000000, 120_0000000);
+        assert_eq!(vault.client.get_strategy_performance(&String::from_str(&env, "LSTM")).total_profit, 150_0000000);
+        let pnl = vault.client.get_pnl_breakdown();
+        assert_eq!((pnl.realized, pnl.unrealized), (150_0000000, 0));
+    }
+
+    fn cost_basis_of(env: &Env, vault: &soroban_sdk::Address, asset: &String) -> i128 {
+        env.as_contract(vault, || cost_basis(env, asset))
+    }
+}
@@ -0,0 +1,255 @@
+//! Liquidity provision in AMM pools.
+//!
+//! A pool is registered under the symbol of its LP share token, which must
+//! itself be a registered asset, together with the two assets it pairs.
+//! Capital provided to the pool leaves the two asset positions and the LP
+//! shares received are booked as a position in the share symbol, so idle
+//! pairs can earn fees while NAV still reconciles with what the vault holds.
+//! LP shares are valued from the pool's reserves: the oracle value of both
+//! reserves divided over the pool's outstanding shares. Both directions book
+//! the balances actually moved.
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, String};
+
+use crate::{assets, fees, guard, portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Interface of the AMM pools the treasury provides liquidity to
+#[allow(dead_code)]
+#[contractclient(name = "AmmPoolClient")]
+pub trait IAmmPool {
+    /// Called after up to `amount_a` and `amount_b` have been transferred to
+    /// the pool; mint shares to `to`, refund what the pool ratio did not use
+    /// and return the shares minted
+    fn deposit(env: Env, to: Address, amount_a: i128, amount_b: i128) -> i128;
+    /// Called after `shares` have been transferred to the pool; burn them and
+    /// send the underlying tokens to `to`
+    fn withdraw(env: Env, to: Address, shares: i128) -> (i128, i128);
+    /// Current reserves of the two pooled tokens
+    fn get_reserves(env: Env) -> (i128, i128);
+    /// Shares outstanding
+    fn total_shares(env: Env) -> i128;
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum PoolKey {
+    Pool(String),  // LP share symbol -> pool and the assets it pairs
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PoolInfo {
+    pub pool: Address,
+    pub asset_a: String,
+    pub asset_b: String,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Register the pool whose LP shares are the registered asset `lp_asset`
+    pub fn register_pool(env: Env, lp_asset: String, pool: Address, asset_a: String, asset_b: String) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if assets::asset_info(&env, &lp_asset).is_none() {
+            panic!("LP share asset not registered");
+        }
+        if asset_a == asset_b || lp_asset == asset_a || lp_asset == asset_b {
+            panic!("Invalid pool assets");
+        }
+        for asset in [&asset_a, &asset_b] {
+            if assets::token_address(&env, &config, asset).is_none() {
+                panic!("Asset has no token");
+            }
+        }
+
+        env.storage().instance().set(&PoolKey::Pool(lp_asset), &PoolInfo { pool, asset_a, asset_b });
+        storage::extend_instance(&env);
+    }
+
+    /// Get a registered pool
+    pub fn get_pool(env: Env, lp_asset: String) -> Option<PoolInfo> {
+        pool_info(&env, &lp_asset)
+    }
+
+    /// Provide up to `amount_a` and `amount_b` to a pool; returns the shares received
+    pub fn provide_liquidity(env: Env, lp_asset: String, amount_a: i128, amount_b: i128) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("pool"));
+
+        let info = pool_info(&env, &lp_asset).expect("Pool not registered");
+        if amount_a <= 0 || amount_b <= 0 {
+            panic!("Amount must be positive");
+        }
+        if portfolio::position(&env, &info.asset_a) < amount_a || portfolio::position(&env, &info.asset_b) < amount_b {
+            panic!("Insufficient position");
+        }
+
+        let vault = env.current_contract_address();
+        let clients = [&info.asset_a, &info.asset_b, &lp_asset]
+            .map(|asset| token::Client::new(&env, &assets::token_address(&env, &config, asset).unwrap()));
+        let before = clients.each_ref().map(|client| client.balance(&vault));
+        clients[0].transfer(&vault, &info.pool, &amount_a);
+        clients[1].transfer(&vault, &info.pool, &amount_b);
+        AmmPoolClient::new(&env, &info.pool).deposit(&vault, &amount_a, &amount_b);
+        let after = clients.each_ref().map(|client| client.balance(&vault));
+
+        let base_before = portfolio::position(&env, &config.base_asset);
+        portfolio::adjust_position(&env, &info.asset_a, after[0] - before[0]);
+        portfolio::adjust_position(&env, &info.asset_b, after[1] - before[1]);
+        let shares = after[2] - before[2];
+        portfolio::adjust_position(&env, &lp_asset, shares);
+        fees::check_reserve(&env, &config, base_before);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("pool"), symbol_short!("provide"), lp_asset), shares);
+        shares
+    }
+
+    /// Redeem LP shares for the pooled assets; returns the amounts received
+    pub fn remove_liquidity(env: Env, lp_asset: String, shares: i128) -> (i128, i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("pool"));
+
+        let info = pool_info(&env, &lp_asset).expect("Pool not registered");
+        if shares <= 0 || portfolio::position(&env, &lp_asset) < shares {
+            panic!("Insufficient LP shares");
+        }
+
+        let vault = env.current_contract_address();
+        let clients = [&info.asset_a, &info.asset_b, &lp_asset]
+            .map(|asset| token::Client::new(&env, &assets::token_address(&env, &config, asset).unwrap()));
+        let before = clients.each_ref().map(|client| client.balance(&vault));
+        clients[2].transfer(&vault, &info.pool, &shares);
+        AmmPoolClient::new(&env, &info.pool).withdraw(&vault, &shares);
+        let after = clients.each_ref().map(|client| client.balance(&vault));
+
+        let received = (after[0] - before[0], after[1] - before[1]);
+        portfolio::adjust_position(&env, &info.asset_a, received.0);
+        portfolio::adjust_position(&env, &info.asset_b, received.1);
+        portfolio::adjust_position(&env, &lp_asset, after[2] - before[2]);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("pool"), symbol_short!("remove"), lp_asset), shares);
+        received
+    }
+}
+
+fn pool_info(env: &Env, lp_asset: &String) -> Option<PoolInfo> {
+    env.storage().instance().get(&PoolKey::Pool(lp_asset.clone()))
+}
+
+/// Base-asset value of one whole LP share of `lp_asset` from its pool's
+/// reserves, if `lp_asset` is a registered pool's share
+pub(crate) fn share_price(env: &Env, config: &VaultConfig, lp_asset: &String) -> Option<i128> {
+    let info = pool_info(env, lp_asset)?;
+    let pool = AmmPoolClient::new(env, &info.pool);
+    let total_shares = pool.total_shares();
+    if total_shares <= 0 {
+        return Some(0);
+    }
+
+    let (reserve_a, reserve_b) = pool.get_reserves();
+    let reserves_value = portfolio::value_of(env, config, &info.asset_a, reserve_a)
+        + portfolio::value_of(env, config, &info.asset_b, reserve_b);
+    Some(reserves_value * assets::unit_scale(env, lp_asset) / total_shares)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::contract;
+
+    /// Constant-ratio pool minting one share per unit of token A deposited
+    #[contract]
+    pub struct MockPool;
+
+    #[contractimpl]
+    impl MockPool {
+        pub fn init(env: Env, token_a: Address, token_b: Address, share_token: Address) {
+            env.storage().instance().set(&symbol_short!("tokens"), &(token_a, token_b, share_token));
+        }
+
+        pub fn deposit(env: Env, to: Address, amount_a: i128, amount_b: i128) -> i128 {
+            let (_, token_b, share_token): (Address, Address, Address) =
+                env.storage().instance().get(&symbol_short!("tokens")).unwrap();
+            // Pool ratio is 1 A : 2 B; refund the excess B
+            let used_b = amount_a * 2;
+            let pool = env.current_contract_address();
+            token::Client::new(&env, &token_b).transfer(&pool, &to, &(amount_b - used_b));
+            StellarAssetClient::new(&env, &share_token).mint(&to, &amount_a);
+            amount_a
+        }
+
+        pub fn withdraw(env: Env, to: Address, shares: i128) -> (i128, i128) {
+            let (token_a, token_b, share_token): (Address, Address, Address) =
+                env.storage().instance().get(&symbol_short!("tokens")).unwrap();
+            let pool = env.current_contract_address();
+            token::Client::new(&env, &share_token).burn(&pool, &shares);
+            token::Client::new(&env, &token_a).transfer(&pool, &to, &shares);
+            token::Client::new(&env, &token_b).transfer(&pool, &to, &(shares * 2));
+            (shares, shares * 2)
+        }
+
+        pub fn get_reserves(env: Env) -> (i128, i128) {
+            let (token_a, token_b, _): (Address, Address, Address) =
+                env.storage().instance().get(&symbol_short!("tokens")).unwrap();
+            let pool = env.current_contract_address();
+            (token::Client::new(&env, &token_a).balance(&pool), token::Client::new(&env, &token_b).balance(&pool))
+        }
+
+        pub fn total_shares(env: Env) -> i128 {
+            let (token_a, _, _): (Address, Address, Address) =
+                env.storage().instance().get(&symbol_short!("tokens")).unwrap();
+            token::Client::new(&env, &token_a).balance(&env.current_contract_address())
+        }
+    }
+
+    #[test]
+    fn test_lp_position_reconciles_nav() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        // USDC at 2 XLM, held through a deposit into the book
+        let xlm = String::from_str(&env, "XLM");
+        let usdc = String::from_str(&env, "USDC");
+        let usdc_token = StellarAssetClient::new(&env, &env.register_stellar_asset_contract_v2(vault.admin.clone()).address());
+        vault.client.register_asset(&usdc, &usdc_token.address, &7, &usdc);
+        oracle.set_price(&usdc, &2_0000000);
+        usdc_token.mint(&vault.client.address, &300_0000000);
+        env.as_contract(&vault.client.address, || portfolio::adjust_position(&env, &usdc, 300_0000000));
+        let nav = vault.client.compute_nav();
+
+        let pool = env.register_contract(None, MockPool);
+        let lp = String::from_str(&env, "LP-USDC-XLM");
+        let lp_token = env.register_stellar_asset_contract_v2(pool.clone()).address();
+        MockPoolClient::new(&env, &pool).init(&usdc_token.address, &token.address, &lp_token);
+        assert!(vault.client.try_register_pool(&lp, &pool, &usdc, &xlm).is_err());
+        vault.client.register_asset(&lp, &lp_token, &7, &lp);
+        vault.client.register_pool(&lp, &pool, &usdc, &xlm);
+
+        // 100 USDC pairs with 200 XLM; the 50 XLM over the ratio comes back
+        assert_eq!(vault.client.provide_liquidity(&lp, &100_0000000, &250_0000000), 100_0000000);
+        assert_eq!(vault.client.get_position(&usdc), 200_0000000);
+        assert_eq!(vault.client.get_position(&xlm), 800_0000000);
+        assert_eq!(vault.client.get_position(&lp), 100_0000000);
+        assert_eq!(vault.client.compute_nav(), nav);
+
+        assert_eq!(vault.client.remove_liquidity(&lp, &40_0000000), (40_0000000, 80_0000000));
+        assert_eq!(vault.client.get_position(&lp), 60_0000000);
+        assert_eq!(vault.client.get_position(&xlm), 880_0000000);
+        assert_eq!(vault.client.compute_nav(), nav);
+    }
+}
@@ -0,0 +1,402 @@
+//! Position tracking and net asset value (NAV) calculation.
+//!
+//! Positions are a book kept by the contract: every executed BUY adds the
+//! asset and spends the base asset at the executed price, every SELL does the
+//! reverse. NAV is the base-asset position plus every other position marked
+//! to the configured price oracle, or to the median of the asset's oracles
+//! when it has several. Quantities are in each asset's smallest units (see
+//! `assets`), prices are base-asset units per whole asset.
+//!
+//! Positions are signed: when shorting is enabled a SELL beyond the held
+//! quantity leaves a negative position, and the total short exposure must
+//! stay covered by NAV at the margin requirements (see `margin`).
+//!
+//! Portfolio composition weighs each position by its share of gross
+//! exposure, the sum of absolute position values, so shorts and a negative
+//! base balance show up as exposure rather than cancelling out.
+//!
+//! Positions worth less than a threshold can be swept into the base asset
+//! at their oracle price in one batch, realizing their P&L, so leftover dust
+//! stops cluttering the position map and every NAV computation.
+
+use soroban_sdk::{contractimpl, symbol_short, Address, Env, Map, String, Vec};
+
+use crate::{assets, borrowing, collateral, fees, guard, lending, oracle, pnl, pools, reporting, strategies, subvaults};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Set the price oracle used for NAV calculation
+    pub fn set_price_oracle(env: Env, oracle: Address) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.price_oracle = Some(oracle);
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Get the held quantity of an asset
+    pub fn get_position(env: Env, asset: String) -> i128 {
+        position(&env, &asset)
+    }
+
+    /// Get all non-zero positions
+    pub fn get_positions(env: Env) -> Map<String, i128> {
+        let mut positions = Map::new(&env);
+        for asset in held_assets(&env).iter() {
+            let quantity = position(&env, &asset);
+            positions.set(asset, quantity);
+        }
+        positions
+    }
+
+    /// Get each held asset's share of gross exposure in basis points
+    ///
+    /// Weights are rounded down, so they may sum to slightly under 10000.
+    pub fn get_portfolio_composition(env: Env) -> Map<String, u32> {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let mut values = Map::new(&env);
+        let mut gross = 0;
+        for asset in held_assets(&env).iter() {
+            let value = value_of(&env, &config, &asset, position(&env, &asset)).abs();
+            gross += value;
+            values.set(asset, value);
+        }
+
+        let mut weights = Map::new(&env);
+        for (asset, value) in values.iter() {
+            let weight = if gross > 0 { value * 10000 / gross } else { 0 };
+            weights.set(asset, weight as u32);
+        }
+        weights
+    }
+
+    /// Get the oracle value of all short positions (positive number)
+    pub fn get_short_exposure(env: Env) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        short_exposure(&env, &config)
+    }
+
+    /// Close the shared book's free quantity of every asset whose oracle
+    /// value is below `min_value` into the base asset (admin); returns the
+    /// number swept
+    ///
+    /// Quantities locked as collateral or held by a sub-vault are left
+    /// alone. Token-backed longs are sold through the cheapest registered
+    /// DEX, at most `max_slippage_bps` below the oracle value on top of its
+    /// fee, and booked at what the swap returned; anything without tokens to
+    /// move is closed on the book at the oracle price.
+    pub fn sweep_dust(env: Env, min_value: i128, max_slippage_bps: u32) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("dust"));
+
+        if min_value <= 0 {
+            panic!("Threshold must be positive");
+        }
+
+        let base_before = position(&env, &config.base_asset);
+        let mut swept = 0;
+        for asset in held_assets(&env).iter() {
+            if asset == config.base_asset {
+                continue;
+            }
+            let quantity = position(&env, &asset)
+                - collateral::locked(&env, &asset)
+                - subvaults::held_by_sub_vaults(&env, &asset);
+            if quantity == 0 {
+                continue;
+            }
+            let price = price_of(&env, &config, &asset);
+            let value = notional(&env, &asset, quantity, price);
+            if value.abs() >= min_value {
+                continue;
+            }
+
+            // Longs are sold and shorts bought back
+            let (action, price) = match assets::token_address(&env, &config, &asset) {
+                Some(token) if quantity > 0 => {
+                    let received = lending::swap_to_base(&env, &config, &asset, &token, quantity, max_slippage_bps);
+                    (TradeAction::Sell, received * assets::unit_scale(&env, &asset) / quantity)
+                }
+                _ if quantity > 0 => (TradeAction::Sell, price),
+                _ => (TradeAction::Buy, price),
+            };
+            apply_fill(&env, &config, &asset, action, quantity.abs(), price);
+            env.events().publish((symbol_short!("dust"), symbol_short!("swept"), asset), value);
+            swept += 1;
+        }
+        collateral::check_unlocked(&env, &config.base_asset);
+        fees::check_reserve(&env, &config, base_before);
+        swept
+    }
+
+    /// Compute the vault's net asset value in the base asset
+    pub fn compute_nav(env: Env) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        reporting::to_reporting(&env, &config, nav(&env, &config))
+    }
+}
+
+pub(crate) fn position(env: &Env, asset: &String) -> i128 {
+    env.storage().instance()
+        .get(&DataKey::Position(asset.clone()))
+        .unwrap_or(0)
+}
+
+pub(crate) fn held_assets(env: &Env) -> Vec<String> {
+    env.storage().instance()
+        .get(&DataKey::PositionAssets)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Add `delta` to a position, keeping the held-asset list in sync
+pub(crate) fn adjust_position(env: &Env, asset: &String, delta: i128) {
+    if delta == 0 {
+        return;
+    }
+
+    let key = DataKey::Position(asset.clone());
+    let old = position(env, asset);
+    let new = old + delta;
+
+    if new == 0 {
+        env.storage().instance().remove(&key);
+        let mut assets = held_assets(env);
+        if let Some(index) = assets.first_index_of(asset) {
+            assets.remove(index);
+        }
+        env.storage().instance().set(&DataKey::PositionAssets, &assets);
+    } else {
+        env.storage().instance().set(&key, &new);
+        if old == 0 {
+            let mut assets = held_assets(env);
+            assets.push_back(asset.clone());
+            env.storage().instance().set(&DataKey::PositionAssets, &assets);
+        }
+    }
+}
+
+/// Base-asset value of a fill at its executed price
+pub(crate) fn notional(env: &Env, asset: &String, amount: i128, price: i128) -> i128 {
+    amount * price / assets::unit_scale(env, asset)
+}
+
+/// Book an executed trade against the positions; returns the realized P&L
+pub(crate) fn apply_fill(
+    env: &Env,
+    config: &VaultConfig,
+    asset: &String,
+    action: TradeAction,
+    amount: i128,
+    price: i128,
+) -> i128 {
+    if *asset == config.base_asset {
+        return 0;
+    }
+
+    let notional = notional(env, asset, amount, price);
+    let realized = pnl::book_fill(env, asset, position(env, asset), action, amount, notional);
+
+    match action {
+        TradeAction::Buy => {
+            adjust_position(env, asset, amount);
+            adjust_position(env, &config.base_asset, -notional);
+        }
+        TradeAction::Sell => {
+            if !config.allow_shorting && position(env, asset) < amount {
+                panic!("Insufficient position");
+            }
+            adjust_position(env, asset, -amount);
+            adjust_position(env, &config.base_asset, notional);
+        }
+        TradeAction::Hold => {}
+    }
+
+    realized
+}
+
+/// Base-asset position, every other position marked to the oracle, capital
+/// allocated to strategy adapters and idle capital lent out
+pub(crate) fn nav(env: &Env, config: &VaultConfig) -> i128 {
+    let mut total: i128 = 0;
+
+    for asset in held_assets(env).iter() {
+        let quantity = position(env, &asset);
+        if asset == config.base_asset {
+            total += quantity;
+            continue;
+        }
+
+        total += value_of(env, config, &asset, quantity);
+    }
+
+    total + strategies::adapters_value(env) + lending::lending_value(env) - borrowing::debt(env)
+}
+
+/// Oracle price of one whole unit of an asset, in base-asset units; LP
+/// shares are priced from their pool's reserves
+pub(crate) fn price_of(env: &Env, config: &VaultConfig, asset: &String) -> i128 {
+    if *asset == config.base_asset {
+        return assets::unit_scale(env, asset);
+    }
+    if let Some(price) = pools::share_price(env, config, asset) {
+        return price;
+    }
+
+    oracle::price(env, config, asset)
+}
+
+/// Oracle value of `quantity` smallest units of `asset` in the base asset
+pub(crate) fn value_of(env: &Env, config: &VaultConfig, asset: &String, quantity: i128) -> i128 {
+    quantity * price_of(env, config, asset) / assets::unit_scale(env, asset)
+}
+
+pub(crate) fn short_exposure(env: &Env, config: &VaultConfig) -> i128 {
+    let mut exposure = 0;
+    for asset in held_assets(env).iter() {
+        let quantity = position(env, &asset);
+        if quantity < 0 && asset != config.base_asset {
+            exposure += value_of(env, config, &asset, -quantity);
+        }
+    }
+    exposure
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+    use crate::RiskMetrics;
+    use soroban_sdk::testutils::Address as _;
+
+    #[test]
+    fn test_compute_nav() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &50000_0000000);
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &2_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+
+        // Bought 2 BTC for 90k, now marked at 50k each
+        assert_eq!(vault.client.get_position(&btc), 2_0000000);
+        assert_eq!(
+            vault.client.get_position(&String::from_str(&env, "XLM")),
+            -90000_0000000
+        );
+        assert_eq!(vault.client.compute_nav(), 10000_0000000);
+
+        // 100k long BTC against 90k owed in XLM
+        let composition = vault.client.get_portfolio_composition();
+        assert_eq!(composition.get(btc.clone()), Some(5263));
+        assert_eq!(composition.get(String::from_str(&env, "XLM")), Some(4736));
+
+        // Snapshots record the on-chain NAV instead of the reported value
+        vault.client.create_snapshot(&vault.trading_agent, &1, &1, &0);
+        assert_eq!(vault.client.get_latest_snapshot().total_value, 10000_0000000);
+    }
+
+    #[test]
+    fn test_sweep_dust() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let btc = String::from_str(&env, "BTC");
+        let eth = String::from_str(&env, "ETH");
+        oracle.set_price(&btc, &50000_0000000);
+        oracle.set_price(&eth, &2000_0000000);
+
+        for (asset, amount) in [(&btc, 1_0000000), (&eth, 10000)] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                asset,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &2000_0000000, &None);
+        }
+        oracle.set_price(&eth, &2500_0000000);
+        let nav = vault.client.compute_nav();
+        let market = Address::generate(&env);
+        env.as_contract(&vault.client.address, || collateral::lock(&env, &eth, &market, 4000));
+
+        // 0.0006 free ETH is worth 1.5 base; the locked ETH and BTC stay
+        assert_eq!(vault.client.sweep_dust(&10_0000000, &100), 1);
+        assert_eq!(vault.client.get_position(&eth), 4000);
+        assert_eq!(vault.client.get_position(&btc), 1_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, 3000000);
+        assert_eq!(vault.client.compute_nav(), nav);
+        assert!(vault.client.try_sweep_dust(&0, &100).is_err());
+    }
+
+    #[test]
+    fn test_short_position_margin() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        let sell = |amount: i128| {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Sell,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &-250,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+
+        // Shorting is off by default
+        let signal_id = sell(1_0000000);
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        assert!(vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None).is_err());
+
+        vault.apply_change(&env, &ConfigChange::ShortSelling(true, 15000));
+        assert!(vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+        assert_eq!(vault.client.get_position(&btc), -1_0000000);
+        assert_eq!(vault.client.get_short_exposure(), 100_0000000);
+        assert_eq!(vault.client.compute_nav(), 1000_0000000);
+
+        // 10 BTC more would need 1650 of margin against a 1000 NAV
+        let signal_id = sell(10_0000000);
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+    }
+}
@@ -0,0 +1,143 @@
+//! Locking in realized profits.
+//!
+//! With a stable asset configured, every execution that realizes a profit
+//! earmarks it for conversion. The next keeper run (`maybe_snapshot`) books
+//! the conversion of the earmarked base asset into the stable asset at its
+//! oracle price plus the cheapest registered DEX swap fee, before the
+//! snapshot is taken, so gains stop riding on volatile assets. Earmarks
+//! above the available base position wait for the following run.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Env, String};
+
+use crate::{assets, fees, portfolio};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ProfitKey {
+    StableAsset,  // asset realized profits are converted into
+    Earmarked,  // base-asset profit awaiting conversion
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Convert realized profits into `stable_asset` at keeper runs (None turns it off)
+    pub fn set_profit_lock(env: Env, stable_asset: Option<String>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        match stable_asset {
+            Some(asset) => {
+                if asset == config.base_asset {
+                    panic!("Stable asset must differ from the base asset");
+                }
+                env.storage().instance().set(&ProfitKey::StableAsset, &asset);
+            }
+            None => {
+                env.storage().instance().remove(&ProfitKey::StableAsset);
+                env.storage().instance().remove(&ProfitKey::Earmarked);
+            }
+        }
+    }
+
+    /// Get the asset realized profits are converted into
+    pub fn get_profit_lock(env: Env) -> Option<String> {
+        env.storage().instance().get(&ProfitKey::StableAsset)
+    }
+
+    /// Get the realized profit awaiting conversion
+    pub fn get_earmarked_profit(env: Env) -> i128 {
+        earmarked(&env)
+    }
+}
+
+fn earmarked(env: &Env) -> i128 {
+    env.storage().instance().get(&ProfitKey::Earmarked).unwrap_or(0)
+}
+
+/// Earmark the profit an execution realized, if profits are being locked in
+pub(crate) fn record_profit(env: &Env, profit_loss: i128) {
+    if profit_loss <= 0 || !env.storage().instance().has(&ProfitKey::StableAsset) {
+        return;
+    }
+    env.storage().instance().set(&ProfitKey::Earmarked, &(earmarked(env) + profit_loss));
+}
+
+/// Book the conversion of earmarked profit into the stable asset; returns
+/// the base amount converted
+pub(crate) fn convert_earmarked(env: &Env, config: &VaultConfig) -> i128 {
+    let stable: String = match env.storage().instance().get(&ProfitKey::StableAsset) {
+        Some(stable) => stable,
+        None => return 0,
+    };
+    let pending = earmarked(env);
+    let amount = pending.min(portfolio::position(env, &config.base_asset));
+    if amount <= 0 {
+        return 0;
+    }
+
+    // Buying at the oracle price grossed up by the swap fee spends `amount`
+    let oracle_price = portfolio::price_of(env, config, &stable);
+    let price = oracle_price * (10000 + fees::swap_fee_bps(env, config) as i128) / 10000;
+    let quantity = amount * assets::unit_scale(env, &stable) / price;
+    if quantity <= 0 {
+        return 0;
+    }
+    portfolio::apply_fill(env, config, &stable, TradeAction::Buy, quantity, price);
+    let converted = portfolio::notional(env, &stable, quantity, price);
+    env.storage().instance().set(&ProfitKey::Earmarked, &(pending - converted));
+
+    env.events().publish((symbol_short!("profit"), symbol_short!("locked"), stable), converted);
+    converted
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::Address;
+
+    #[test]
+    fn test_profit_lock() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        let usdc = String::from_str(&env, "USDC");
+        oracle.set_price(&btc, &100_0000000);
+        oracle.set_price(&usdc, &2_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.set_profit_lock(&Some(usdc.clone()));
+        vault.client.set_snapshot_interval(&3600);
+
+        // Buy 1 BTC at 100 and sell it at 120: 20 profit earmarked
+        for (action, price) in [(TradeAction::Buy, 100_0000000), (TradeAction::Sell, 120_0000000)] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.execute_trade(&vault.payment_agent, &signal_id, &price, &None);
+        }
+        assert_eq!(vault.client.get_earmarked_profit(), 20_0000000);
+
+        // The keeper run converts it at 2 per USDC
+        env.ledger().with_mut(|l| l.timestamp += 3600);
+        vault.client.maybe_snapshot(&Address::generate(&env));
+        assert_eq!(vault.client.get_position(&usdc), 10_0000000);
+        assert_eq!(vault.client.get_earmarked_profit(), 0);
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 1000_0000000);
+    }
+}
@@ -0,0 +1,194 @@
+//! Rebalance previews.
+//!
+//! `simulate_rebalance` works out the trades that would move the book to a
+//! set of target weights, what they would cost at the vault's swap fee
+//! and how concentrated the portfolio would be afterwards. Nothing is
+//! written; the dashboard calls it as a view before submitting signals.
+
+use soroban_sdk::{contractimpl, contracttype, Env, Map, String, Vec};
+
+use crate::{assets, fees, portfolio};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, VaultConfig};
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct ProjectedTrade {
+    pub asset: String,
+    pub action: TradeAction,
+    pub amount: i128,  // asset smallest units
+    pub value: i128,  // base-asset value at oracle prices
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct RebalancePreview {
+    pub trades: Vec<ProjectedTrade>,  // sells first, then buys
+    pub estimated_fees: i128,  // base-asset units
+    pub weights: Map<String, u32>,  // post-trade share of NAV per asset (bps)
+    pub max_weight_bps: u32,  // largest post-trade weight outside the base asset
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Preview the trades that would move the book to `target_weights` (bps of NAV)
+    ///
+    /// Whatever the weights leave unallocated stays in the base asset.
+    pub fn simulate_rebalance(env: Env, target_weights: Map<String, u32>) -> RebalancePreview {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+
+        let mut total_weight: u32 = 0;
+        for (_, weight) in target_weights.iter() {
+            total_weight += weight;
+        }
+        if total_weight > 10000 {
+            panic!("Target weights exceed 100%");
+        }
+
+        let nav = portfolio::nav(&env, &config);
+        if nav <= 0 {
+            panic!("Nothing to rebalance");
+        }
+
+        // Every asset that is either held or targeted
+        let mut universe = portfolio::held_assets(&env);
+        for (asset, _) in target_weights.iter() {
+            if !universe.contains(&asset) {
+                universe.push_back(asset);
+            }
+        }
+
+        let mut sells = Vec::new(&env);
+        let mut buys = Vec::new(&env);
+        let mut post_values: Map<String, i128> = Map::new(&env);
+        let mut base_value = portfolio::position(&env, &config.base_asset);
+        let mut traded: i128 = 0;
+
+        for asset in universe.iter() {
+            if asset == config.base_asset {
+                continue;
+            }
+
+            let held = portfolio::position(&env, &asset);
+            let target_value = nav * target_weights.get(asset.clone()).unwrap_or(0) as i128 / 10000;
+            let price = portfolio::price_of(&env, &config, &asset);
+            let delta = (target_value - portfolio::value_of(&env, &config, &asset, held))
+                * assets::unit_scale(&env, &asset)
+                / price;
+
+            if delta != 0 {
+                let value = portfolio::value_of(&env, &config, &asset, delta.abs());
+                let trade = ProjectedTrade {
+                    asset: asset.clone(),
+                    action: if delta > 0 { TradeAction::Buy } else { TradeAction::Sell },
+                    amount: delta.abs(),
+                    value,
+                };
+                if delta > 0 {
+                    base_value -= value;
+                    buys.push_back(trade);
+                } else {
+                    base_value += value;
+                    sells.push_back(trade);
+                }
+                traded += value;
+            }
+
+            let post_value = portfolio::value_of(&env, &config, &asset, held + delta);
+            if post_value != 0 {
+                post_values.set(asset, post_value);
+            }
+        }
+
+        let estimated_fees = traded * fees::swap_fee_bps(&env, &config) as i128 / 10000;
+        base_value -= estimated_fees;
+        let post_nav = nav - estimated_fees;
+
+        let mut weights = Map::new(&env);
+        let mut max_weight_bps = 0;
+        if post_nav > 0 {
+            for (asset, value) in post_values.iter() {
+                let weight = (value.max(0) * 10000 / post_nav) as u32;
+                max_weight_bps = max_weight_bps.max(weight);
+                weights.set(asset, weight);
+            }
+            weights.set(config.base_asset.clone(), (base_value.max(0) * 10000 / post_nav) as u32);
+        }
+
+        sells.append(&buys);
+        RebalancePreview { trades: sells, estimated_fees, weights, max_weight_bps }
+    }
+}
+
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::Address;
+
+    #[test]
+    fn test_simulate_rebalance() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        let eth = String::from_str(&env, "ETH");
+        let xlm = String::from_str(&env, "XLM");
+        oracle.set_price(&btc, &100_0000000);
+        oracle.set_price(&eth, &10_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &8_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None);
+        vault.client.set_swap_fee(&30);
+
+        // 80% BTC -> 50% BTC, 30% ETH
+        let mut targets = Map::new(&env);
+        targets.set(btc.clone(), 5000);
+        targets.set(eth.clone(), 3000);
+        let preview = vault.client.simulate_rebalance(&targets);
+
+        let mut expected = Vec::new(&env);
+        expected.push_back(ProjectedTrade {
+            asset: btc.clone(),
+            action: TradeAction::Sell,
+            amount: 3_0000000,
+            value: 300_0000000,
+        });
+        expected.push_back(ProjectedTrade {
+            asset: eth.clone(),
+            action: TradeAction::Buy,
+            amount: 30_0000000,
+            value: 300_0000000,
+        });
+        assert_eq!(preview.trades, expected);
+        assert_eq!(preview.estimated_fees, 1_8000000);
+
+        // Fees come out of the base asset, so NAV is 998.2 afterwards
+        assert_eq!(preview.weights.get(btc.clone()), Some(5009));
+        assert_eq!(preview.weights.get(eth), Some(3005));
+        assert_eq!(preview.weights.get(xlm.clone()), Some(1985));
+        assert_eq!(preview.max_weight_bps, 5009);
+
+        // Nothing was traded
+        assert_eq!(vault.client.get_position(&btc), 8_0000000);
+        assert_eq!(vault.client.get_position(&xlm), 200_0000000);
+
+        targets.set(xlm, 3000);
+        assert!(vault.client.try_simulate_rebalance(&targets).is_err());
+    }
+}
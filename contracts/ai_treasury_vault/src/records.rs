@@ -0,0 +1,420 @@
+//! Compact trade record and snapshot encoding.
+//!
+//! Trade records are the one entry type that grows with every execution, so
+//! they are stored packed: asset and strategy names become indices into a
+//! symbol table kept in instance storage, the timestamp becomes a `u32`
+//! offset from the vault's creation and the trade id is left to the key.
+//! The venue of a routed trade goes into the same symbol table as its
+//! strkey. `TradeRecordV4` is a tuple struct so it encodes as a vector
+//! rather than a map of field names. Readers always get a full
+//! `TradeRecord` back; records written before packing (or that cannot be
+//! packed) are read as they are, records from before `realized_return_bps`
+//! existed read it as 0 and records from before `venue` read it as `None`.
+//!
+//! Snapshots are delta-encoded: every `SNAPSHOT_KEYFRAME_INTERVAL`th
+//! snapshot is stored in full and the ones in between only as the change
+//! from their predecessor. Reading one walks back to the nearest full
+//! snapshot and replays the deltas.
+
+use soroban_sdk::{contracttype, Address, Env, Map, String, Symbol, TryFromVal, Val, Vec};
+
+use crate::storage;
+use crate::{DataKey, PortfolioSnapshot, TradeAction, TradeRecord, VaultConfig};
+
+/// Snapshots stored in full are `1, 1 + N, 1 + 2N, ...`
+pub const SNAPSHOT_KEYFRAME_INTERVAL: u64 = 24;
+
+#[derive(Clone)]
+#[contracttype]
+pub enum RecordKey {
+    Symbols,  // symbol table: index -> asset, strategy or venue name
+}
+
+/// Packed trade record as written before `realized_return_bps`
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct TradeRecordV2(pub u64, pub u32, pub TradeAction, pub i128, pub i128, pub u32, pub u32, pub i128);
+
+/// Packed trade record as written before `venue`
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct TradeRecordV3(
+    pub u64,
+    pub u32,
+    pub TradeAction,
+    pub i128,
+    pub i128,
+    pub u32,
+    pub u32,
+    pub i128,
+    pub i32,
+);
+
+/// Packed trade record: (signal_id, asset index, action, amount, price,
+/// strategy index, seconds since vault creation, profit_loss,
+/// realized_return_bps, venue index)
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct TradeRecordV4(
+    pub u64,
+    pub u32,
+    pub TradeAction,
+    pub i128,
+    pub i128,
+    pub u32,
+    pub u32,
+    pub i128,
+    pub i32,
+    pub Option<u32>,
+);
+
+/// Unpacked trade record as written before `realized_return_bps`
+#[derive(Clone)]
+#[contracttype]
+pub struct TradeRecordV1 {
+    pub trade_id: u64,
+    pub signal_id: u64,
+    pub asset: String,
+    pub action: TradeAction,
+    pub amount: i128,
+    pub price: i128,
+    pub strategy: String,
+    pub executed_at: u64,
+    pub profit_loss: i128,
+}
+
+/// Unpacked trade record as written before `venue`
+#[derive(Clone)]
+#[contracttype]
+pub struct UnroutedTradeRecord {
+    pub trade_id: u64,
+    pub signal_id: u64,
+    pub asset: String,
+    pub action: TradeAction,
+    pub amount: i128,
+    pub price: i128,
+    pub strategy: String,
+    pub executed_at: u64,
+    pub profit_loss: i128,
+    pub realized_return_bps: i32,
+}
+
+/// Change from the previous snapshot: (seconds elapsed, total_value delta,
+/// num_assets, trades since, cumulative_return, share_price delta,
+/// benchmark_price delta)
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SnapshotDelta(pub u32, pub i128, pub u32, pub u64, pub i32, pub i128, pub i128);
+
+fn symbols(env: &Env) -> Vec<String> {
+    env.storage().instance()
+        .get(&RecordKey::Symbols)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Index of `name` in the symbol table, adding it if new
+fn intern(env: &Env, table: &mut Vec<String>, name: &String) -> u32 {
+    match table.first_index_of(name) {
+        Some(index) => index,
+        None => {
+            table.push_back(name.clone());
+            env.storage().instance().set(&RecordKey::Symbols, table);
+            table.len() - 1
+        }
+    }
+}
+
+/// Write a trade record, packed when its timestamp fits the offset
+pub(crate) fn store_trade(env: &Env, config: &VaultConfig, trade: &TradeRecord) {
+    let key = DataKey::Trade(trade.trade_id);
+    let offset = trade.executed_at.checked_sub(config.created_at)
+        .and_then(|offset| u32::try_from(offset).ok());
+
+    match offset {
+        Some(offset) => {
+            let mut table = symbols(env);
+            let packed = TradeRecordV4(
+                trade.signal_id,
+                intern(env, &mut table, &trade.asset),
+                trade.action,
+                trade.amount,
+                trade.price,
+                intern(env, &mut table, &trade.strategy),
+                offset,
+                trade.profit_loss,
+                trade.realized_return_bps,
+                trade.venue.as_ref().map(|venue| intern(env, &mut table, &venue.to_string())),
+            );
+            storage::set_persistent(env, &key, &packed);
+        }
+        None => storage::set_persistent(env, &key, trade),
+    }
+}
+
+/// Read a trade record in any encoding, falling back to pre-migration
+/// instance storage
+pub(crate) fn load_trade(env: &Env, config: &VaultConfig, trade_id: u64) -> Option<TradeRecord> {
+    let key = DataKey::Trade(trade_id);
+    let value: Val = match storage::get_persistent(env, &key) {
+        Some(value) => value,
+        None => env.storage().instance().get(&key)?,
+    };
+
+    // Decoding checks the field count, so pick the encoding by it first
+    if let Ok(fields) = Vec::<Val>::try_from_val(env, &value) {
+        let packed = match fields.len() {
+            8 => {
+                let old = TradeRecordV2::try_from_val(env, &value).ok()?;
+                TradeRecordV4(old.0, old.1, old.2, old.3, old.4, old.5, old.6, old.7, 0, None)
+            }
+            9 => {
+                let old = TradeRecordV3::try_from_val(env, &value).ok()?;
+                TradeRecordV4(old.0, old.1, old.2, old.3, old.4, old.5, old.6, old.7, old.8, None)
+            }
+            _ => TradeRecordV4::try_from_val(env, &value).ok()?,
+        };
+        let table = symbols(env);
+        return Some(TradeRecord {
+            trade_id,
+            signal_id: packed.0,
+            asset: table.get(packed.1).unwrap(),
+            action: packed.2,
+            amount: packed.3,
+            price: packed.4,
+            strategy: table.get(packed.5).unwrap(),
+            executed_at: config.created_at + packed.6 as u64,
+            profit_loss: packed.7,
+            realized_return_bps: packed.8,
+            venue: packed.9.map(|index| Address::from_string(&table.get(index).unwrap())),
+        });
+    }
+
+    let fields = Map::<Symbol, Val>::try_from_val(env, &value).ok()?;
+    if fields.contains_key(Symbol::new(env, "venue")) {
+        return TradeRecord::try_from_val(env, &value).ok();
+    }
+    if fields.contains_key(Symbol::new(env, "realized_return_bps")) {
+        let old = UnroutedTradeRecord::try_from_val(env, &value).ok()?;
+        return Some(TradeRecord {
+            trade_id: old.trade_id,
+            signal_id: old.signal_id,
+            asset: old.asset,
+            action: old.action,
+            amount: old.amount,
+            price: old.price,
+            strategy: old.strategy,
+            executed_at: old.executed_at,
+            profit_loss: old.profit_loss,
+            realized_return_bps: old.realized_return_bps,
+            venue: None,
+        });
+    }
+    let old = TradeRecordV1::try_from_val(env, &value).ok()?;
+    Some(TradeRecord {
+        trade_id: old.trade_id,
+        signal_id: old.signal_id,
+        asset: old.asset,
+        action: old.action,
+        amount: old.amount,
+        price: old.price,
+        strategy: old.strategy,
+        executed_at: old.executed_at,
+        profit_loss: old.profit_loss,
+        realized_return_bps: 0,
+        venue: None,
+    })
+}
+
+/// Write a snapshot, as a delta from `previous` unless it is a keyframe
+pub(crate) fn store_snapshot(env: &Env, previous: Option<&PortfolioSnapshot>, snapshot: &PortfolioSnapshot) {
+    let key = DataKey::Snapshot(snapshot.snapshot_id);
+    let elapsed = previous
+        .filter(|previous| previous.snapshot_id + 1 == snapshot.snapshot_id)
+        .filter(|_| !(snapshot.snapshot_id - 1).is_multiple_of(SNAPSHOT_KEYFRAME_INTERVAL))
+        .and_then(|previous| {
+            let elapsed = snapshot.timestamp.checked_sub(previous.timestamp)?;
+            Some((previous, u32::try_from(elapsed).ok()?))
+        });
+
+    match elapsed {
+        Some((previous, elapsed)) => {
+            let delta = SnapshotDelta(
+                elapsed,
+                snapshot.total_value - previous.total_value,
+                snapshot.num_assets,
+                snapshot.total_trades - previous.total_trades,
+                snapshot.cumulative_return,
+                snapshot.share_price - previous.share_price,
+                snapshot.benchmark_price - previous.benchmark_price,
+            );
+            storage::set_persistent(env, &key, &delta);
+        }
+        None => storage::set_persistent(env, &key, snapshot),
+    }
+}
+
+/// Rebuild a snapshot from the nearest full one before it, falling back to
+/// pre-migration instance storage
+pub(crate) fn load_snapshot(env: &Env, snapshot_id: u64) -> Option<PortfolioSnapshot> {
+    let mut deltas: Vec<SnapshotDelta> = Vec::new(env);
+    let mut id = snapshot_id;
+    let mut snapshot = loop {
+        let key = DataKey::Snapshot(id);
+        let value: Val = match storage::get_persistent(env, &key) {
+            Some(value) => value,
+            None => return env.storage().instance().get(&key).filter(|_| deltas.is_empty()),
+        };
+        if let Ok(delta) = SnapshotDelta::try_from_val(env, &value) {
+            deltas.push_front(delta);
+            id -= 1;
+            continue;
+        }
+        break PortfolioSnapshot::try_from_val(env, &value).ok()?;
+    };
+
+    for delta in deltas.iter() {
+        snapshot = apply_delta(snapshot, &delta);
+    }
+    Some(snapshot)
+}
+
+/// Rebuild snapshots `from..=to` in one pass, replaying deltas forward
+pub(crate) fn load_snapshots(env: &Env, from: u64, to: u64) -> Vec<PortfolioSnapshot> {
+    let mut snapshots = Vec::new(env);
+    let mut current = match load_snapshot(env, from) {
+        Some(snapshot) => snapshot,
+        None => return snapshots,
+    };
+    snapshots.push_back(current.clone());
+
+    for id in from + 1..=to {
+        let key = DataKey::Snapshot(id);
+        let value: Val = match storage::get_persistent(env, &key) {
+            Some(value) => value,
+            None => match env.storage().instance().get(&key) {
+                Some(snapshot) => {
+                    current = snapshot;
+                    snapshots.push_back(current.clone());
+                    continue;
+                }
+                None => break,
+            },
+        };
+        current = match SnapshotDelta::try_from_val(env, &value) {
+            Ok(delta) => apply_delta(current, &delta),
+            Err(_) => PortfolioSnapshot::try_from_val(env, &value).unwrap(),
+        };
+        snapshots.push_back(current.clone());
+    }
+    snapshots
+}
+
+fn apply_delta(snapshot: PortfolioSnapshot, delta: &SnapshotDelta) -> PortfolioSnapshot {
+    let mut snapshot = snapshot;
+    snapshot.snapshot_id += 1;
+    snapshot.timestamp += delta.0 as u64;
+    snapshot.total_value += delta.1;
+    snapshot.num_assets = delta.2;
+    snapshot.total_trades += delta.3;
+    snapshot.cumulative_return = delta.4;
+    snapshot.share_price += delta.5;
+    snapshot.benchmark_price += delta.6;
+    snapshot
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_trades_are_packed() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &100000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+
+        env.as_contract(&vault.client.address, || {
+            let packed: TradeRecordV4 = env.storage().persistent().get(&DataKey::Trade(1)).unwrap();
+            assert_eq!(packed, TradeRecordV4(1, 0, TradeAction::Buy, 100000, 45000_0000000, 1, 0, 0, 0, None));
+
+            // Records packed before realized returns or venues were kept still read
+            let older = TradeRecordV2(1, 0, TradeAction::Buy, 100000, 45000_0000000, 1, 0, 0);
+            env.storage().persistent().set(&DataKey::Trade(2), &older);
+            let unrouted = TradeRecordV3(1, 0, TradeAction::Buy, 100000, 45000_0000000, 1, 0, 0, 25);
+            env.storage().persistent().set(&DataKey::Trade(4), &unrouted);
+            let unpacked = TradeRecordV1 {
+                trade_id: 3,
+                signal_id: 1,
+                asset: btc.clone(),
+                action: TradeAction::Buy,
+                amount: 100000,
+                price: 45000_0000000,
+                strategy: String::from_str(&env, "LSTM"),
+                executed_at: 0,
+                profit_loss: 0,
+            };
+            env.storage().persistent().set(&DataKey::Trade(3), &unpacked);
+        });
+        assert_eq!(vault.client.get_trade(&2).realized_return_bps, 0);
+        assert_eq!(vault.client.get_trade(&3).asset, btc);
+        assert_eq!(vault.client.get_trade(&4).realized_return_bps, 25);
+        assert_eq!(vault.client.get_trade(&4).venue, None);
+
+        // Getters see the full record
+        let trade = vault.client.get_trade(&1);
+        assert_eq!(trade.trade_id, 1);
+        assert_eq!(trade.asset, btc);
+        assert_eq!(trade.strategy, String::from_str(&env, "LSTM"));
+        assert_eq!(trade.executed_at, vault.client.get_config().created_at);
+    }
+
+    #[test]
+    fn test_snapshots_are_delta_encoded() {
+        let env = Env::default();
+        let vault = setup(&env);
+
+        for i in 1..=SNAPSHOT_KEYFRAME_INTERVAL + 2 {
+            env.budget().reset_default();
+            env.ledger().with_mut(|l| l.timestamp += 3600);
+            vault.client.create_snapshot(&vault.trading_agent, &(1000 + i as i128), &2, &(i as i32));
+        }
+
+        env.as_contract(&vault.client.address, || {
+            let stored = |id: u64| -> Val {
+                env.storage().persistent().get(&DataKey::Snapshot(id)).unwrap()
+            };
+            assert!(PortfolioSnapshot::try_from_val(&env, &stored(1)).is_ok());
+            assert!(SnapshotDelta::try_from_val(&env, &stored(2)).is_ok());
+            assert!(SnapshotDelta::try_from_val(&env, &stored(SNAPSHOT_KEYFRAME_INTERVAL)).is_ok());
+            assert!(PortfolioSnapshot::try_from_val(&env, &stored(SNAPSHOT_KEYFRAME_INTERVAL + 1)).is_ok());
+        });
+
+        // Rebuilt snapshots match what was taken
+        env.budget().reset_default();
+        let snapshot = vault.client.get_snapshot(&SNAPSHOT_KEYFRAME_INTERVAL);
+        assert_eq!(snapshot.snapshot_id, SNAPSHOT_KEYFRAME_INTERVAL);
+        assert_eq!(snapshot.timestamp, SNAPSHOT_KEYFRAME_INTERVAL * 3600);
+        assert_eq!(snapshot.total_value, 1000 + SNAPSHOT_KEYFRAME_INTERVAL as i128);
+        assert_eq!(snapshot.cumulative_return, SNAPSHOT_KEYFRAME_INTERVAL as i32);
+        assert_eq!(snapshot.share_price, 1_0000000);
+
+        let latest = vault.client.get_latest_snapshot();
+        let rebuilt = vault.client.get_snapshot(&(SNAPSHOT_KEYFRAME_INTERVAL + 2));
+        assert_eq!(rebuilt.timestamp, latest.timestamp);
+        assert_eq!(rebuilt.total_value, latest.total_value);
+    }
+}
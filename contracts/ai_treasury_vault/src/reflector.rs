@@ -0,0 +1,121 @@
+//! Reflector oracle adapter.
+//!
+//! Reflector implements SEP-40: feeds are addressed by an `Asset` enum and
+//! prices are quoted in the oracle's base currency scaled by its own
+//! `decimals()`. A `PriceSource` of kind `Reflector` names the feed by its
+//! ticker (`Asset::Other`) and carries those decimals; the oracle's base
+//! currency must match the vault's base asset.
+
+use soroban_sdk::{contractclient, contracttype, Address, Env, String, Symbol};
+
+use crate::oracle::{OracleAdapter, PriceData, PriceSource};
+
+/// Longest ticker Reflector accepts as a feed id
+const MAX_TICKER_LEN: usize = 32;
+
+/// SEP-40 asset identifier
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum ReflectorAsset {
+    Stellar(Address),
+    Other(Symbol),
+}
+
+#[allow(dead_code)]
+#[contractclient(name = "ReflectorClient")]
+pub trait Reflector {
+    fn lastprice(env: Env, asset: ReflectorAsset) -> Option<PriceData>;
+    fn decimals(env: Env) -> u32;
+}
+
+pub(crate) struct ReflectorAdapter;
+
+impl OracleAdapter for ReflectorAdapter {
+    fn lastprice(env: &Env, source: &PriceSource) -> Option<PriceData> {
+        let asset = ReflectorAsset::Other(ticker(env, &source.feed_id));
+        ReflectorClient::new(env, &source.contract).lastprice(&asset)
+    }
+}
+
+fn ticker(env: &Env, feed_id: &String) -> Symbol {
+    let len = feed_id.len() as usize;
+    if len > MAX_TICKER_LEN {
+        panic!("Feed id too long");
+    }
+    let mut buf = [0u8; MAX_TICKER_LEN];
+    feed_id.copy_into_slice(&mut buf[..len]);
+    Symbol::new(env, core::str::from_utf8(&buf[..len]).expect("Feed id is not UTF-8"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::oracle::OracleKind;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+    use soroban_sdk::{contract, contractimpl, Vec};
+
+    /// Reflector stand-in quoting with 14 decimals
+    #[contract]
+    pub struct MockReflector;
+
+    #[contractimpl]
+    impl MockReflector {
+        pub fn set_price(env: Env, asset: ReflectorAsset, price: i128) {
+            let data = PriceData { price, timestamp: env.ledger().timestamp() };
+            env.storage().instance().set(&asset, &data);
+        }
+
+        pub fn lastprice(env: Env, asset: ReflectorAsset) -> Option<PriceData> {
+            env.storage().instance().get(&asset)
+        }
+
+        pub fn decimals(_env: Env) -> u32 {
+            14
+        }
+    }
+
+    #[test]
+    fn test_reflector_price_source() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+
+        let contract = env.register_contract(None, MockReflector);
+        let reflector = MockReflectorClient::new(&env, &contract);
+        reflector.set_price(&ReflectorAsset::Other(Symbol::new(&env, "BTC")), &100_00000000000000);
+
+        let mut sources = Vec::new(&env);
+        sources.push_back(PriceSource {
+            kind: OracleKind::Reflector,
+            contract,
+            feed_id: btc.clone(),
+            decimals: reflector.decimals(),
+        });
+        vault.client.set_price_sources(&btc, &sources);
+        assert_eq!(vault.client.get_price_sources(&btc), sources);
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &btc,
+            &TradeAction::Buy,
+            &2_0000000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &90_0000000, &None);
+
+        // Valued at 100 in base units: 200 held against 180 spent
+        assert_eq!(vault.client.compute_nav(), 20_0000000);
+
+        // Staleness limits apply to Reflector quotes too
+        vault.client.set_feed_max_age(&btc, &60);
+        env.ledger().with_mut(|l| l.timestamp += 61);
+        assert!(vault.client.try_compute_nav().is_err());
+    }
+}
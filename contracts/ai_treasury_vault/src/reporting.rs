@@ -0,0 +1,120 @@
+//! Stable-settlement accounting.
+//!
+//! Positions are always booked in the base asset, but when a reporting asset
+//! (typically a stablecoin) is chosen, the figures the vault reports are
+//! converted into it through the oracle at the moment they are recorded:
+//! `compute_nav`, snapshot values and share prices, external flows, the P&L
+//! on trade records and strategy performance, and the P&L breakdown. Treasury
+//! reports then show what the vault is worth in dollars rather than moving
+//! with the base asset's own price. Returns measured in bps are unaffected
+//! by the conversion of the P&L they come from.
+//!
+//! The reporting asset can only be chosen before the first trade and
+//! snapshot, so no series mixes denominations.
+
+use soroban_sdk::{contractimpl, contracttype, Env, String};
+
+use crate::{assets, portfolio};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ReportingKey {
+    ReportingAsset,  // asset reported figures are denominated in
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Denominate reported figures in `asset` (None reports in the base asset)
+    pub fn set_reporting_asset(env: Env, asset: Option<String>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let trades: u64 = env.storage().instance().get(&DataKey::TradeCounter).unwrap_or(0);
+        if trades > 0 || env.storage().instance().has(&DataKey::LatestSnapshot) {
+            panic!("Reporting asset is fixed once trading has started");
+        }
+
+        match asset {
+            Some(asset) => {
+                if config.price_oracle.is_none() {
+                    panic!("Price oracle not configured");
+                }
+                if asset == config.base_asset {
+                    panic!("Reporting asset must differ from the base asset");
+                }
+                env.storage().instance().set(&ReportingKey::ReportingAsset, &asset);
+            }
+            None => env.storage().instance().remove(&ReportingKey::ReportingAsset),
+        }
+    }
+
+    /// Get the asset reported figures are denominated in (None for the base asset)
+    pub fn get_reporting_asset(env: Env) -> Option<String> {
+        env.storage().instance().get(&ReportingKey::ReportingAsset)
+    }
+}
+
+/// Convert a base-asset amount into the reporting asset at the oracle price
+pub(crate) fn to_reporting(env: &Env, config: &VaultConfig, amount: i128) -> i128 {
+    let asset: String = match env.storage().instance().get(&ReportingKey::ReportingAsset) {
+        Some(asset) => asset,
+        None => return amount,
+    };
+    amount * assets::unit_scale(env, &asset) / portfolio::price_of(env, config, &asset)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::Address;
+
+    #[test]
+    fn test_stable_reporting() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        let usdc = String::from_str(&env, "USDC");
+        oracle.set_price(&btc, &100_0000000);
+        oracle.set_price(&usdc, &10_0000000);  // 10 base per USDC
+        vault.client.set_reporting_asset(&Some(usdc.clone()));
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        assert_eq!(vault.client.compute_nav(), 100_0000000);
+
+        // Buy 1 BTC at 100 and sell it at 120: 20 base, 2 USDC of profit
+        let mut trade_id = 0;
+        for (action, price) in [(TradeAction::Buy, 100_0000000), (TradeAction::Sell, 120_0000000)] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            trade_id = vault.client.execute_trade(&vault.payment_agent, &signal_id, &price, &None);
+        }
+        assert_eq!(vault.client.get_trade(&trade_id).profit_loss, 2_0000000);
+        assert_eq!(vault.client.get_pnl_breakdown().realized, 2_0000000);
+        assert!(vault.client.try_set_reporting_asset(&None).is_err());
+
+        // The base asset halving against USDC halves the reported value
+        vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        oracle.set_price(&usdc, &20_0000000);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        assert_eq!(vault.client.get_snapshot(&1).total_value, 102_0000000);
+        assert_eq!(vault.client.get_snapshot(&2).total_value, 51_0000000);
+    }
+}
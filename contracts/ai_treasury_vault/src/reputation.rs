@@ -0,0 +1,181 @@
+//! Strategy reputation.
+//!
+//! Every execution that realizes P&L is an outcome for its strategy. Three
+//! running averages (weight `REPUTATION_ALPHA_BPS` per outcome) are kept:
+//! how often outcomes were profitable, how far the realized return landed
+//! from the signal's `expected_return`, and how much of NAV each loss took.
+//! They combine into a 0-100 score, weighted 50/30/20, which decays back
+//! towards a neutral 50 with a half-life of `REPUTATION_HALF_LIFE_SECS`
+//! while the strategy produces no outcomes.
+//!
+//! The auto-approve tier can require a minimum reputation, so a strategy
+//! that has gone cold or keeps missing its own forecasts loses its way
+//! around the risk agent.
+
+use soroban_sdk::{contractimpl, contracttype, Env, String};
+
+use crate::portfolio;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, VaultConfig};
+
+/// Weight of each new outcome in the running averages (basis points)
+pub const REPUTATION_ALPHA_BPS: u32 = 2000;
+/// Time for a score's distance from neutral to halve without new outcomes
+pub const REPUTATION_HALF_LIFE_SECS: u64 = 30 * 86400;
+/// Score of a strategy without outcomes
+pub const NEUTRAL_REPUTATION: u32 = 50;
+/// Prediction error at or above which the error component scores zero
+const MAX_ERROR_BPS: u32 = 1000;
+/// Loss per outcome, as a share of NAV, at which the drawdown component scores zero
+const MAX_DRAWDOWN_BPS: u32 = 500;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum ReputationKey {
+    Reputation(String),  // strategy_name -> running outcome averages
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct ReputationState {
+    pub win_rate_bps: u32,  // profitable outcomes
+    pub error_bps: u32,  // |realized - expected| return
+    pub drawdown_bps: u32,  // losses as a share of NAV
+    pub outcomes: u32,
+    pub updated_at: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Get a strategy's 0-100 reputation, decayed to now
+    pub fn get_reputation(env: Env, strategy: String) -> u32 {
+        reputation(&env, &strategy)
+    }
+}
+
+pub(crate) fn reputation(env: &Env, strategy: &String) -> u32 {
+    let state: ReputationState = match env.storage().instance().get(&ReputationKey::Reputation(strategy.clone())) {
+        Some(state) => state,
+        None => return NEUTRAL_REPUTATION,
+    };
+
+    let error_score = MAX_ERROR_BPS - state.error_bps.min(MAX_ERROR_BPS);
+    let drawdown_score = MAX_DRAWDOWN_BPS - state.drawdown_bps.min(MAX_DRAWDOWN_BPS);
+    let raw = (state.win_rate_bps * 50 / 10000
+        + error_score * 30 / MAX_ERROR_BPS
+        + drawdown_score * 20 / MAX_DRAWDOWN_BPS) as i64;
+
+    // Halve the distance from neutral per half-life, linearly in between
+    let elapsed = env.ledger().timestamp().saturating_sub(state.updated_at);
+    let halvings = elapsed / REPUTATION_HALF_LIFE_SECS;
+    if halvings >= 8 {
+        return NEUTRAL_REPUTATION;
+    }
+    let remainder = (elapsed % REPUTATION_HALF_LIFE_SECS) as i64;
+    let mut deviation = (raw - NEUTRAL_REPUTATION as i64) / (1 << halvings);
+    deviation -= deviation * remainder / (2 * REPUTATION_HALF_LIFE_SECS as i64);
+    (NEUTRAL_REPUTATION as i64 + deviation) as u32
+}
+
+/// Fold an execution's realized P&L and its distance from the forecast
+/// into its strategy's reputation
+pub(crate) fn record_outcome(
+    env: &Env,
+    config: &VaultConfig,
+    strategy: &String,
+    prediction_error: Option<u32>,
+    profit_loss: i128,
+) {
+    let error_bps = match prediction_error {
+        Some(error) => error.min(10000),
+        None => return,
+    };
+    let drawdown_bps = if profit_loss < 0 && config.price_oracle.is_some() {
+        let nav = portfolio::nav(env, config);
+        if nav > 0 { (-profit_loss * 10000 / nav).min(10000) as u32 } else { 0 }
+    } else {
+        0
+    };
+    let win_bps = if profit_loss > 0 { 10000 } else { 0 };
+
+    let key = ReputationKey::Reputation(strategy.clone());
+    let state = match env.storage().instance().get::<_, ReputationState>(&key) {
+        Some(state) => ReputationState {
+            win_rate_bps: blend(state.win_rate_bps, win_bps),
+            error_bps: blend(state.error_bps, error_bps),
+            drawdown_bps: blend(state.drawdown_bps, drawdown_bps),
+            outcomes: state.outcomes + 1,
+            updated_at: env.ledger().timestamp(),
+        },
+        None => ReputationState {
+            win_rate_bps: win_bps,
+            error_bps,
+            drawdown_bps,
+            outcomes: 1,
+            updated_at: env.ledger().timestamp(),
+        },
+    };
+    env.storage().instance().set(&key, &state);
+}
+
+fn blend(average: u32, sample: u32) -> u32 {
+    (average * (10000 - REPUTATION_ALPHA_BPS) + sample * REPUTATION_ALPHA_BPS) / 10000
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_reputation() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let btc = String::from_str(&env, "BTC");
+        let lstm = String::from_str(&env, "LSTM");
+        assert_eq!(vault.client.get_reputation(&lstm), NEUTRAL_REPUTATION);
+
+        let trade = |action: TradeAction, price: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &1_0000000,
+                &lstm,
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &price, &None)
+        };
+
+        // Buying at 100 and selling at 102.5 lands close to the 2.5% forecast
+        assert!(trade(TradeAction::Buy, 100_0000000).is_ok());
+        assert_eq!(vault.client.get_reputation(&lstm), NEUTRAL_REPUTATION);
+        assert!(trade(TradeAction::Sell, 102_5000000).is_ok());
+        let perfect = vault.client.get_reputation(&lstm);
+        assert_eq!(perfect, 99);
+
+        // A loss that misses the forecast by far drags the score down
+        assert!(trade(TradeAction::Buy, 100_0000000).is_ok());
+        assert!(trade(TradeAction::Sell, 80_0000000).is_ok());
+        let after_loss = vault.client.get_reputation(&lstm);
+        assert!(after_loss < perfect);
+
+        // Without new outcomes the score halves its distance from neutral
+        env.ledger().with_mut(|l| l.timestamp += REPUTATION_HALF_LIFE_SECS);
+        let decayed = vault.client.get_reputation(&lstm);
+        assert_eq!(decayed - NEUTRAL_REPUTATION, (after_loss - NEUTRAL_REPUTATION) / 2);
+
+        // Below the required reputation small trades need the risk agent again
+        vault.apply_change(&env, &ConfigChange::ApprovalTiers(10_0000000, 0));
+        vault.client.set_auto_approve_reputation(&(decayed + 1));
+        assert!(trade(TradeAction::Buy, 100_0000000).is_err());
+        vault.client.set_auto_approve_reputation(&decayed);
+        assert!(trade(TradeAction::Buy, 100_0000000).is_ok());
+    }
+}
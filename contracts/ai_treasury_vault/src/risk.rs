@@ -0,0 +1,690 @@
+//! On-chain risk estimates.
+//!
+//! Every snapshot feeds the period return of the vault's NAV per share into
+//! an exponentially weighted variance (RiskMetrics-style, lambda = 0.94), so
+//! `approve_trade` can rely on a volatility figure the contract computed
+//! itself rather than the one reported by the risk agent.
+//!
+//! Snapshots also record their drawdown: how far NAV per share sits below
+//! its running peak, in basis points. The series is kept in pages of
+//! `DRAWDOWN_PAGE_SIZE` entries so underwater charts read it directly.
+//!
+//! Each `approve_trade` call also serves as the risk agent's heartbeat; once
+//! it is older than `VaultConfig.max_risk_staleness_secs`, trades are refused
+//! until the risk agent reports again.
+//!
+//! Approvals are recorded per signal and per risk agent. A signal counts as
+//! approved once `VaultConfig.risk_quorum` distinct risk agents have signed
+//! off (e.g. 2-of-3); with a quorum of 0 execution does not wait for them.
+//!
+//! Approval can also be tiered by trade size. Signals below
+//! `VaultConfig.auto_approve_below` execute without risk approval, larger
+//! ones need at least one risk approval (or the quorum, if higher), and
+//! those of `VaultConfig.cosign_above` or more also need the admin to
+//! `cosign_trade`. Pair signals are sized by their larger leg. The
+//! auto-approve tier can further be limited to strategies whose reputation
+//! is at least a configured minimum. The quorum and the tiers are changed
+//! through the timelocked config-change queue (see `changes`).
+//!
+//! `evaluate` is the single place the approval limits are checked; both
+//! `approve_trade` and the read-only `simulate_approval` go through it.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, String, Vec};
+
+use crate::{margin, pairs, portfolio, reputation, roles, storage};
+use crate::{
+    AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, RiskMetrics, TradeAction, TradingSignal,
+    VaultConfig,
+};
+
+/// Weight of the previous variance in each update (basis points)
+pub const EWMA_LAMBDA_BPS: i128 = 9400;
+
+/// Drawdowns per storage page
+pub const DRAWDOWN_PAGE_SIZE: u64 = 100;
+
+/// Most drawdowns `get_drawdown_series` returns in one call
+pub const MAX_DRAWDOWN_SERIES: u32 = 200;
+
+#[derive(Clone)]
+#[contracttype]
+pub struct VolatilityState {
+    pub last_share_price: i128,
+    pub variance: i128,  // EWMA of squared period returns (bps^2)
+    pub samples: u32,  // period returns folded in so far
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum RiskKey {
+    Cosigned(u64),  // signal_id -> admin co-signed (temporary)
+    MinAutoReputation,  // reputation a strategy needs for the auto-approve tier
+    PeakSharePrice,  // highest snapshot NAV per share so far
+    DrawdownStart,  // first snapshot with a recorded drawdown
+    DrawdownPage(u64),  // page -> drawdowns (bps) of consecutive snapshots (persistent)
+}
+
+/// Result of checking a signal against the risk limits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[contracttype]
+pub enum ApprovalOutcome {
+    Approved,
+    Volatility,
+    ValueAtRisk,
+    Sharpe,
+    Drawdown,
+    StopLoss,
+    Concentration,
+    ShortMargin,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Check a signal against the risk limits without approving it
+    ///
+    /// Returns the first limit that would reject the trade, or `Approved`.
+    pub fn simulate_approval(env: Env, signal_id: u64, risk_metrics: RiskMetrics) -> ApprovalOutcome {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let risk_metrics = with_onchain_volatility(&env, risk_metrics);
+        evaluate(&env, &config, signal_id, &risk_metrics)
+    }
+
+    /// Set the largest share of NAV a buy may leave in one asset (0 = no limit)
+    pub fn set_max_concentration(env: Env, max_concentration_bps: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if max_concentration_bps > 10000 {
+            panic!("Concentration exceeds 100%");
+        }
+
+        config.max_concentration_bps = max_concentration_bps;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Get the EWMA volatility of snapshot-to-snapshot returns (bps)
+    pub fn get_volatility(env: Env) -> u32 {
+        volatility(&env).unwrap_or(0)
+    }
+
+    /// Get the drawdowns (bps below the running peak) of up to `limit`
+    /// snapshots from `start`, oldest first
+    pub fn get_drawdown_series(env: Env, start: u64, limit: u32) -> Vec<u32> {
+        let first: u64 = env.storage().instance()
+            .get(&RiskKey::DrawdownStart)
+            .expect("No drawdown history");
+        if start < first {
+            panic!("No drawdown history for snapshot");
+        }
+        let latest: u64 = env.storage().instance().get(&DataKey::SnapshotCounter).unwrap_or(0);
+        let end = (start + limit.min(MAX_DRAWDOWN_SERIES) as u64).min(latest + 1);
+
+        let mut series = Vec::new(&env);
+        let mut page_index = None;
+        let mut page: Vec<u32> = Vec::new(&env);
+        for snapshot_id in start..end {
+            let offset = snapshot_id - first;
+            if page_index != Some(offset / DRAWDOWN_PAGE_SIZE) {
+                page_index = Some(offset / DRAWDOWN_PAGE_SIZE);
+                page = env.storage().persistent()
+                    .get(&RiskKey::DrawdownPage(offset / DRAWDOWN_PAGE_SIZE))
+                    .unwrap_or(Vec::new(&env));
+            }
+            match page.get((offset % DRAWDOWN_PAGE_SIZE) as u32) {
+                Some(drawdown) => series.push_back(drawdown),
+                None => break,
+            }
+        }
+        series
+    }
+
+    /// Set the highest on-chain volatility at which trades are approved (0 = no limit)
+    pub fn set_max_volatility(env: Env, max_volatility: u32) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.max_volatility = max_volatility;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Set how old the last risk update may be when a trade executes (0 = no limit)
+    pub fn set_max_risk_staleness(env: Env, max_risk_staleness_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.max_risk_staleness_secs = max_risk_staleness_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Limit the auto-approve tier to strategies with at least this reputation (0-100)
+    pub fn set_auto_approve_reputation(env: Env, min_reputation: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if min_reputation > 100 {
+            panic!("Reputation must be between 0 and 100");
+        }
+        env.storage().instance().set(&RiskKey::MinAutoReputation, &min_reputation);
+        storage::extend_instance(&env);
+    }
+
+    /// Admin co-signature for a signal in the top approval tier
+    pub fn cosign_trade(env: Env, signal_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let known = env.storage().temporary().has(&DataKey::Signal(signal_id))
+            || pairs::pair_signal(&env, signal_id).is_some();
+        if !known {
+            panic!("No such signal");
+        }
+        // Lives as long as the signal itself
+        let key = RiskKey::Cosigned(signal_id);
+        env.storage().temporary().set(&key, &true);
+        env.storage().temporary().extend_ttl(&key, storage::DAY_IN_LEDGERS, storage::DAY_IN_LEDGERS);
+    }
+
+    /// Get the risk agents that have approved a signal
+    pub fn get_trade_approvals(env: Env, signal_id: u64) -> Vec<Address> {
+        approvals(&env, signal_id)
+    }
+
+    /// Get the timestamp of the risk agent's last update
+    pub fn get_last_risk_update(env: Env) -> u64 {
+        env.storage().instance().get(&DataKey::LastRiskUpdate).unwrap_or(0)
+    }
+}
+
+/// Record a risk-agent heartbeat
+pub(crate) fn touch_risk_update(env: &Env) {
+    env.storage().instance().set(&DataKey::LastRiskUpdate, &env.ledger().timestamp());
+}
+
+pub(crate) fn approvals(env: &Env, signal_id: u64) -> Vec<Address> {
+    env.storage().temporary()
+        .get(&DataKey::Approvals(signal_id))
+        .unwrap_or(Vec::new(env))
+}
+
+/// Record a risk agent's approval; returns whether the quorum is now met
+pub(crate) fn record_approval(env: &Env, config: &VaultConfig, signal_id: u64, agent: &Address) -> bool {
+    let mut agents = approvals(env, signal_id);
+    if !agents.contains(agent) {
+        agents.push_back(agent.clone());
+        storage::set_temporary(env, &DataKey::Approvals(signal_id), &agents);
+    }
+    valid_approvals(env, signal_id) >= config.risk_quorum.max(1)
+}
+
+/// Approvals from accounts that can still act as risk agents; revoked or
+/// blacklisted approvers no longer count
+fn valid_approvals(env: &Env, signal_id: u64) -> u32 {
+    approvals(env, signal_id).iter()
+        .filter(|agent| roles::acts_as(env, agent, &roles::RISK_AGENT))
+        .count() as u32
+}
+
+/// Risk approvals a signal of `amount` needs under the approval tiers
+fn required_approvals(env: &Env, config: &VaultConfig, strategy: &String, amount: i128) -> u32 {
+    let min_reputation: u32 = env.storage().instance().get(&RiskKey::MinAutoReputation).unwrap_or(0);
+    if amount < config.auto_approve_below && reputation::reputation(env, strategy) >= min_reputation {
+        0
+    } else if config.auto_approve_below > 0 || config.cosign_above > 0 {
+        config.risk_quorum.max(1)
+    } else {
+        config.risk_quorum
+    }
+}
+
+/// Whether a signal of `amount` has collected the approvals execution requires
+pub(crate) fn quorum_met(env: &Env, config: &VaultConfig, signal_id: u64, strategy: &String, amount: i128) -> bool {
+    let required = required_approvals(env, config, strategy, amount);
+    required == 0 || valid_approvals(env, signal_id) >= required
+}
+
+/// Whether a signal of `amount` is missing a required admin co-signature
+pub(crate) fn needs_cosign(env: &Env, config: &VaultConfig, signal_id: u64, amount: i128) -> bool {
+    config.cosign_above > 0
+        && amount >= config.cosign_above
+        && !env.storage().temporary().has(&RiskKey::Cosigned(signal_id))
+}
+
+/// Whether the last risk update is too old to trade on
+pub(crate) fn risk_is_stale(env: &Env, config: &VaultConfig) -> bool {
+    if config.max_risk_staleness_secs == 0 {
+        return false;
+    }
+    let last: u64 = env.storage().instance().get(&DataKey::LastRiskUpdate).unwrap_or(0);
+    env.ledger().timestamp() > last + config.max_risk_staleness_secs
+}
+
+/// Fold the return since the previous snapshot into the variance estimate
+pub(crate) fn record_share_price(env: &Env, share_price: i128) {
+    let state: Option<VolatilityState> = env.storage().instance().get(&DataKey::Volatility);
+    let state = match state {
+        Some(state) if state.last_share_price > 0 => {
+            let period_return = (share_price - state.last_share_price) * 10000 / state.last_share_price;
+            let squared = period_return * period_return;
+            let variance = if state.samples == 0 {
+                squared
+            } else {
+                (EWMA_LAMBDA_BPS * state.variance + (10000 - EWMA_LAMBDA_BPS) * squared) / 10000
+            };
+            VolatilityState {
+                last_share_price: share_price,
+                variance,
+                samples: state.samples + 1,
+            }
+        }
+        _ => VolatilityState {
+            last_share_price: share_price,
+            variance: 0,
+            samples: 0,
+        },
+    };
+    env.storage().instance().set(&DataKey::Volatility, &state);
+}
+
+/// Append a snapshot's drawdown from the running peak to the series
+pub(crate) fn record_drawdown(env: &Env, snapshot_id: u64, share_price: i128) {
+    let first: u64 = match env.storage().instance().get(&RiskKey::DrawdownStart) {
+        Some(first) => first,
+        None => {
+            env.storage().instance().set(&RiskKey::DrawdownStart, &snapshot_id);
+            snapshot_id
+        }
+    };
+    let peak: i128 = env.storage().instance().get(&RiskKey::PeakSharePrice).unwrap_or(0).max(share_price);
+    env.storage().instance().set(&RiskKey::PeakSharePrice, &peak);
+    let drawdown = if peak > 0 { ((peak - share_price) * 10000 / peak) as u32 } else { 0 };
+
+    let key = RiskKey::DrawdownPage((snapshot_id - first) / DRAWDOWN_PAGE_SIZE);
+    let mut page: Vec<u32> = env.storage().persistent().get(&key).unwrap_or(Vec::new(env));
+    page.push_back(drawdown);
+    env.storage().persistent().set(&key, &page);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+/// Replace the reported volatility with the on-chain estimate when there is one
+pub(crate) fn with_onchain_volatility(env: &Env, risk_metrics: RiskMetrics) -> RiskMetrics {
+    let mut risk_metrics = risk_metrics;
+    if let Some(volatility) = volatility(env) {
+        risk_metrics.portfolio_volatility = volatility;
+    }
+    risk_metrics
+}
+
+/// Check a signal against every approval limit; reads storage only
+pub(crate) fn evaluate(
+    env: &Env,
+    config: &VaultConfig,
+    signal_id: u64,
+    risk_metrics: &RiskMetrics,
+) -> ApprovalOutcome {
+    if config.max_volatility > 0 && risk_metrics.portfolio_volatility > config.max_volatility {
+        return ApprovalOutcome::Volatility;
+    }
+
+    if risk_metrics.var_95 > config.max_var_95 {
+        return ApprovalOutcome::ValueAtRisk;
+    }
+
+    if risk_metrics.sharpe_ratio < config.min_sharpe_ratio {
+        return ApprovalOutcome::Sharpe;
+    }
+
+    if risk_metrics.max_drawdown < -2000 {  // -20%
+        return ApprovalOutcome::Drawdown;
+    }
+
+    if config.dynamic_stop_loss && risk_metrics.stop_loss_level < -1500 {
+        return ApprovalOutcome::StopLoss;  // Stop-loss triggered at -15%
+    }
+
+    // Legs the signal would buy and sell
+    let signal: Option<TradingSignal> = env.storage().temporary().get(&DataKey::Signal(signal_id));
+    let (buy, sell) = match (signal, pairs::pair_signal(env, signal_id)) {
+        (Some(signal), _) => match signal.action {
+            TradeAction::Buy => (Some((signal.asset, signal.amount)), None),
+            TradeAction::Sell => (None, Some((signal.asset, signal.amount))),
+            TradeAction::Hold => (None, None),
+        },
+        (None, Some(pair)) => (
+            Some((pair.buy_asset, pair.buy_amount)),
+            Some((pair.sell_asset, pair.sell_amount)),
+        ),
+        (None, None) => (None, None),
+    };
+
+    if let Some((asset, amount)) = buy {
+        if !concentration_ok(env, config, &asset, amount) {
+            return ApprovalOutcome::Concentration;
+        }
+    }
+
+    // Shorts must stay covered by the vault's margin requirement
+    if let Some((asset, amount)) = sell {
+        if !margin::initial_margin_ok(env, config, &asset, amount) {
+            return ApprovalOutcome::ShortMargin;
+        }
+    }
+
+    ApprovalOutcome::Approved
+}
+
+/// Whether buying `amount` of `asset` keeps it within the concentration limit
+fn concentration_ok(env: &Env, config: &VaultConfig, asset: &soroban_sdk::String, amount: i128) -> bool {
+    if config.max_concentration_bps == 0 || *asset == config.base_asset {
+        return true;
+    }
+
+    let nav = portfolio::nav(env, config);
+    if nav <= 0 {
+        return false;
+    }
+    let value = portfolio::value_of(env, config, asset, portfolio::position(env, asset) + amount);
+    value * 10000 <= nav * config.max_concentration_bps as i128
+}
+
+/// Current volatility estimate, once at least one period return is known
+pub(crate) fn volatility(env: &Env) -> Option<u32> {
+    let state: VolatilityState = env.storage().instance().get(&DataKey::Volatility)?;
+    if state.samples == 0 {
+        return None;
+    }
+    Some(isqrt(state.variance) as u32)
+}
+
+fn isqrt(value: i128) -> i128 {
+    if value < 2 {
+        return value;
+    }
+    let mut x = value;
+    let mut y = (x + 1) / 2;
+    while y < x {
+        x = y;
+        y = (x + value / x) / 2;
+    }
+    x
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::changes::ConfigChange;
+    use crate::test::setup;
+    use crate::RiskMetrics;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::{Address, String};
+
+    #[test]
+    fn test_ewma_volatility() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &2000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        assert_eq!(vault.client.get_volatility(), 0);
+
+        // A 10% gain (donated to the vault) is the first period return
+        let xlm = String::from_str(&env, "XLM");
+        let gain = |amount: i128| {
+            env.as_contract(&vault.client.address, || {
+                crate::portfolio::adjust_position(&env, &xlm, amount);
+            });
+        };
+        gain(100_0000000);
+        vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        assert_eq!(vault.client.get_volatility(), 1000);
+
+        // A flat period decays the estimate: sqrt(0.94) * 10%
+        vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        assert_eq!(vault.client.get_volatility(), 969);
+
+        // On-chain volatility overrides the reported one in risk decisions
+        vault.client.set_max_volatility(&500);
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &1, &metrics));
+        vault.client.set_max_volatility(&1000);
+        assert!(vault.client.approve_trade(&vault.risk_agent, &1, &metrics));
+        assert_eq!(vault.client.get_risk_metrics().portfolio_volatility, 969);
+    }
+
+    #[test]
+    fn test_drawdown_series() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        assert!(vault.client.try_get_drawdown_series(&1, &10).is_err());
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        let xlm = String::from_str(&env, "XLM");
+        let snapshot_after = |change: i128| {
+            env.as_contract(&vault.client.address, || {
+                crate::portfolio::adjust_position(&env, &xlm, change);
+            });
+            vault.client.create_snapshot(&vault.trading_agent, &0, &1, &0);
+        };
+
+        // 1000 -> 1100 (peak) -> 990 -> 1045 -> 1200 (new peak)
+        for change in [0, 100_0000000, -110_0000000, 55_0000000, 155_0000000] {
+            snapshot_after(change);
+        }
+        assert_eq!(vault.client.get_drawdown_series(&1, &10), Vec::from_array(&env, [0, 0, 1000, 500, 0]));
+        assert_eq!(vault.client.get_drawdown_series(&3, &2), Vec::from_array(&env, [1000, 500]));
+    }
+
+    #[test]
+    fn test_stale_risk_blocks_execution() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.client.set_max_risk_staleness(&3600);
+        env.ledger().with_mut(|l| l.timestamp = 10_000);
+
+        let submit = || {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+
+        // No risk update yet
+        let signal_id = submit();
+        assert!(vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None).is_err());
+
+        vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics);
+        assert_eq!(vault.client.get_last_risk_update(), 10_000);
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+
+        // The heartbeat lapses after an hour
+        env.ledger().with_mut(|l| l.timestamp = 13_601);
+        let signal_id = submit();
+        assert!(vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None).is_err());
+    }
+
+    #[test]
+    fn test_risk_quorum() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let second = Address::generate(&env);
+        let third = Address::generate(&env);
+        vault.client.grant_role(&roles::RISK_AGENT, &second);
+        vault.client.grant_role(&roles::RISK_AGENT, &third);
+        vault.apply_change(&env, &ConfigChange::RiskQuorum(2));
+        assert!(vault.client.try_queue_config_change(&ConfigChange::RiskQuorum(4)).is_err());
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &String::from_str(&env, "BTC"),
+            &TradeAction::Buy,
+            &100000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+        let execute = || {
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None)
+        };
+
+        // One approval, even repeated, is not a quorum
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        assert!(!vault.client.approve_trade(&vault.risk_agent, &signal_id, &metrics));
+        assert!(execute().is_err());
+
+        assert!(vault.client.approve_trade(&third, &signal_id, &metrics));
+        assert_eq!(vault.client.get_trade_approvals(&signal_id).len(), 2);
+
+        // Approvals stop counting once the approver is revoked or blacklisted
+        vault.client.revoke_role(&roles::RISK_AGENT, &third);
+        assert!(execute().is_err());
+        assert!(vault.client.approve_trade(&second, &signal_id, &metrics));
+        vault.client.blacklist_address(&vault.admin, &second);
+        assert!(execute().is_err());
+        vault.client.unblacklist_address(&second);
+        assert!(execute().is_ok());
+    }
+
+    #[test]
+    fn test_approval_tiers() {
+        let env = Env::default();
+        let vault = setup(&env);
+        vault.apply_change(&env, &ConfigChange::ApprovalTiers(1_0000000, 10_0000000));
+        assert!(vault.client.try_queue_config_change(&ConfigChange::ApprovalTiers(5_0000000, 1_0000000)).is_err());
+
+        let submit = |amount: i128| {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+        let execute = |signal_id: u64| {
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None)
+        };
+
+        // Small trades go straight through
+        let small = submit(5000000);
+        assert!(execute(small).is_ok());
+
+        // Mid-size trades wait for the risk agent even with no quorum set
+        let mid = submit(5_0000000);
+        assert!(execute(mid).is_err());
+        vault.client.approve_trade(&vault.risk_agent, &mid, &metrics);
+        assert!(execute(mid).is_ok());
+
+        // Large trades also need the admin
+        let large = submit(10_0000000);
+        vault.client.approve_trade(&vault.risk_agent, &large, &metrics);
+        assert!(execute(large).is_err());
+        vault.client.cosign_trade(&large);
+        assert!(execute(large).is_ok());
+
+        assert!(vault.client.try_cosign_trade(&99).is_err());
+    }
+
+    #[test]
+    fn test_simulate_approval() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.set_max_concentration(&2500);
+
+        let buy = |amount: i128| {
+            vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        let metrics = RiskMetrics {
+            var_95: 300,
+            sharpe_ratio: 150,
+            max_drawdown: -1000,
+            portfolio_volatility: 20,
+            stop_loss_level: -500,
+        };
+
+        let small = buy(2_0000000);
+        assert_eq!(vault.client.simulate_approval(&small, &metrics), ApprovalOutcome::Approved);
+        assert_eq!(
+            vault.client.simulate_approval(&small, &RiskMetrics { var_95: 600, ..metrics.clone() }),
+            ApprovalOutcome::ValueAtRisk
+        );
+        assert_eq!(
+            vault.client.simulate_approval(&small, &RiskMetrics { stop_loss_level: -1600, ..metrics.clone() }),
+            ApprovalOutcome::StopLoss
+        );
+
+        // 3 BTC would be 30% of a 1000 NAV
+        let large = buy(3_0000000);
+        assert_eq!(vault.client.simulate_approval(&large, &metrics), ApprovalOutcome::Concentration);
+
+        // Simulating neither approves nor counts as a risk heartbeat
+        assert!(vault.client.get_trade_approvals(&small).is_empty());
+        assert_eq!(vault.client.get_last_risk_update(), 0);
+    }
+}
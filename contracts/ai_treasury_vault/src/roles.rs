@@ -0,0 +1,434 @@
+//! Role-based access control for the agents.
+//!
+//! Instead of one hard-coded address per agent in `VaultConfig`, each role
+//! maps to a list of member addresses, so a vault can run several trading,
+//! risk or payment agents and rotate them without an upgrade. Agent entry
+//! points take the calling address explicitly and check its role.
+//!
+//! `rotate_agent` swaps one member of a role for another without a gap: the
+//! new address is granted at once and the old one keeps acting until
+//! `VaultConfig.rotation_grace_secs` have passed, after which it no longer
+//! counts as a member, so transactions signed before the switch still land.
+//! Lapsed keys are pruned from storage on the role's next grant, revoke or
+//! rotation, or by anyone calling `prune_role`.
+//!
+//! A compromised key can be blacklisted by the admin or a guardian. The
+//! blacklist is checked on every role check, so it takes effect at once,
+//! before the key has been revoked or rotated out of its roles.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env, Map, Symbol, Vec};
+
+use crate::storage;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Submits trading signals and snapshots
+pub const TRADING_AGENT: Symbol = symbol_short!("trader");
+/// Approves or rejects signals against the risk limits
+pub const RISK_AGENT: Symbol = symbol_short!("risk");
+/// Executes approved trades
+pub const PAYMENT_AGENT: Symbol = symbol_short!("payment");
+/// May halt trading, but not resume it or change the config
+pub const GUARDIAN: Symbol = symbol_short!("guardian");
+/// Acts on the outcome of governance votes (e.g. a DAO contract)
+pub const GOVERNANCE: Symbol = symbol_short!("gov");
+/// Contracts allowed to deposit when the integrator allowlist is on
+pub const INTEGRATOR: Symbol = symbol_short!("integr");
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum RoleKey {
+    RoleExpiry(Symbol),  // role -> members being rotated out and when they lapse
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Add an address to a role
+    pub fn grant_role(env: Env, role: Symbol, account: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        check_grantable(&role);
+
+        grant(&env, &role, &account);
+        storage::extend_instance(&env);
+    }
+
+    /// Remove an address from a role
+    pub fn revoke_role(env: Env, role: Symbol, account: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        prune(&env, &role);
+        let mut accounts = members(&env, &role);
+        let index = match accounts.first_index_of(&account) {
+            Some(index) => index,
+            None => panic!("Account does not hold role"),
+        };
+        accounts.remove(index);
+        env.storage().instance().set(&DataKey::Role(role.clone()), &accounts);
+        set_expiry(&env, &role, &account, None);
+
+        env.events().publish((symbol_short!("role"), symbol_short!("revoked"), role), account);
+    }
+
+    /// Replace `old` with `new` in a role; `old` keeps acting for the grace period
+    ///
+    /// Returns when the old address stops counting as a member.
+    pub fn rotate_agent(env: Env, role: Symbol, old: Address, new: Address) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if old == new {
+            panic!("Cannot rotate an agent to itself");
+        }
+        if !members(&env, &role).contains(&old) {
+            panic!("Account does not hold role");
+        }
+        check_grantable(&role);
+        grant(&env, &role, &new);
+
+        let expires_at = env.ledger().timestamp() + config.rotation_grace_secs;
+        set_expiry(&env, &role, &old, Some(expires_at));
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("role"), symbol_short!("rotated"), role), (old, new, expires_at));
+        expires_at
+    }
+
+    /// Set how long a rotated-out agent key keeps working
+    pub fn set_rotation_grace(env: Env, rotation_grace_secs: u64) {
+        let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        config.rotation_grace_secs = rotation_grace_secs;
+        env.storage().instance().set(&DataKey::Config, &config);
+    }
+
+    /// Drop rotated-out members past their grace period from a role
+    ///
+    /// Returns how many were removed. Anyone may call it.
+    pub fn prune_role(env: Env, role: Symbol) -> u32 {
+        let pruned = prune(&env, &role);
+        storage::extend_instance(&env);
+        pruned
+    }
+
+    /// Get when a member being rotated out of a role lapses
+    pub fn get_role_expiry(env: Env, role: Symbol, account: Address) -> Option<u64> {
+        expiries(&env, &role).get(account)
+    }
+
+    /// Block an address from acting in any role (admin or guardian)
+    pub fn blacklist_address(env: Env, caller: Address, account: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        if caller == config.admin {
+            caller.require_auth();
+        } else {
+            require_role(&env, &caller, &GUARDIAN);
+        }
+
+        let mut blacklist = blacklist(&env);
+        if !blacklist.contains(&account) {
+            blacklist.push_back(account.clone());
+            env.storage().instance().set(&DataKey::Blacklist, &blacklist);
+        }
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("blacklist"), symbol_short!("added")), account);
+    }
+
+    /// Lift the blacklist on an address (admin)
+    pub fn unblacklist_address(env: Env, account: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let mut blacklist = blacklist(&env);
+        if let Some(index) = blacklist.first_index_of(&account) {
+            blacklist.remove(index);
+            env.storage().instance().set(&DataKey::Blacklist, &blacklist);
+        }
+
+        env.events().publish((symbol_short!("blacklist"), symbol_short!("removed")), account);
+    }
+
+    /// Check whether an address is blacklisted
+    pub fn is_blacklisted(env: Env, account: Address) -> bool {
+        blacklist(&env).contains(&account)
+    }
+
+    /// Check whether an address holds a role
+    pub fn has_role(env: Env, role: Symbol, account: Address) -> bool {
+        members(&env, &role).contains(&account)
+    }
+
+    /// Get every address holding a role
+    pub fn get_role_members(env: Env, role: Symbol) -> Vec<Address> {
+        members(&env, &role)
+    }
+}
+
+/// Current members of a role, leaving out rotated keys past their grace period
+pub(crate) fn members(env: &Env, role: &Symbol) -> Vec<Address> {
+    let accounts: Vec<Address> = env.storage().instance()
+        .get(&DataKey::Role(role.clone()))
+        .unwrap_or(Vec::new(env));
+
+    let expiries = expiries(env, role);
+    if expiries.is_empty() {
+        return accounts;
+    }
+    let now = env.ledger().timestamp();
+    let mut current = Vec::new(env);
+    for account in accounts.iter() {
+        if expiries.get(account.clone()).is_none_or(|expires_at| now < expires_at) {
+            current.push_back(account);
+        }
+    }
+    current
+}
+
+/// Remove lapsed members from the stored role and its expiry map
+fn prune(env: &Env, role: &Symbol) -> u32 {
+    let mut expiries = expiries(env, role);
+    let now = env.ledger().timestamp();
+    let mut lapsed = Vec::new(env);
+    for (account, expires_at) in expiries.iter() {
+        if now >= expires_at {
+            lapsed.push_back(account);
+        }
+    }
+    if lapsed.is_empty() {
+        return 0;
+    }
+
+    let mut accounts: Vec<Address> = env.storage().instance()
+        .get(&DataKey::Role(role.clone()))
+        .unwrap_or(Vec::new(env));
+    for account in lapsed.iter() {
+        if let Some(index) = accounts.first_index_of(&account) {
+            accounts.remove(index);
+        }
+        expiries.remove(account);
+    }
+    env.storage().instance().set(&DataKey::Role(role.clone()), &accounts);
+    let key = RoleKey::RoleExpiry(role.clone());
+    if expiries.is_empty() {
+        env.storage().instance().remove(&key);
+    } else {
+        env.storage().instance().set(&key, &expiries);
+    }
+    lapsed.len()
+}
+
+fn expiries(env: &Env, role: &Symbol) -> Map<Address, u64> {
+    env.storage().instance()
+        .get(&RoleKey::RoleExpiry(role.clone()))
+        .unwrap_or(Map::new(env))
+}
+
+fn set_expiry(env: &Env, role: &Symbol, account: &Address, expires_at: Option<u64>) {
+    let mut expiries = expiries(env, role);
+    match expires_at {
+        Some(expires_at) => expiries.set(account.clone(), expires_at),
+        None => {
+            if expiries.remove(account.clone()).is_none() {
+                return;
+            }
+        }
+    }
+    let key = RoleKey::RoleExpiry(role.clone());
+    if expiries.is_empty() {
+        env.storage().instance().remove(&key);
+    } else {
+        env.storage().instance().set(&key, &expiries);
+    }
+}
+
+fn blacklist(env: &Env) -> Vec<Address> {
+    env.storage().instance()
+        .get(&DataKey::Blacklist)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Governance members are added through a queued config change only
+fn check_grantable(role: &Symbol) {
+    if *role == GOVERNANCE {
+        panic!("Governance is granted through a queued config change");
+    }
+}
+
+pub(crate) fn grant(env: &Env, role: &Symbol, account: &Address) {
+    if blacklist(env).contains(account) {
+        panic!("Address is blacklisted");
+    }
+    prune(env, role);
+    // Granting again cancels a pending rotation
+    set_expiry(env, role, account, None);
+    let mut accounts: Vec<Address> = env.storage().instance()
+        .get(&DataKey::Role(role.clone()))
+        .unwrap_or(Vec::new(env));
+    if accounts.contains(account) {
+        return;
+    }
+    accounts.push_back(account.clone());
+    env.storage().instance().set(&DataKey::Role(role.clone()), &accounts);
+
+    env.events().publish((symbol_short!("role"), symbol_short!("granted"), role.clone()), account.clone());
+}
+
+/// Authenticate `caller` and check it holds `role`
+pub(crate) fn require_role(env: &Env, caller: &Address, role: &Symbol) {
+    caller.require_auth();
+    if blacklist(env).contains(caller) {
+        panic!("Address is blacklisted");
+    }
+    if !members(env, role).contains(caller) {
+        panic!("Caller lacks required role");
+    }
+}
+
+/// Whether `account` may currently act in `role`, without authenticating it
+pub(crate) fn acts_as(env: &Env, account: &Address, role: &Symbol) -> bool {
+    !blacklist(env).contains(account) && members(env, role).contains(account)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_multiple_agents_per_role() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let second_trader = Address::generate(&env);
+
+        let submit = |caller: &Address| {
+            vault.client.try_submit_trading_signal(
+                caller,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+
+        assert!(submit(&second_trader).is_err());
+        vault.client.grant_role(&TRADING_AGENT, &second_trader);
+        assert!(vault.client.has_role(&TRADING_AGENT, &second_trader));
+        assert_eq!(vault.client.get_role_members(&TRADING_AGENT).len(), 2);
+
+        // Both traders can submit, the risk agent cannot
+        assert!(submit(&vault.trading_agent).is_ok());
+        assert!(submit(&second_trader).is_ok());
+        assert!(submit(&vault.risk_agent).is_err());
+
+        vault.client.revoke_role(&TRADING_AGENT, &vault.trading_agent);
+        assert!(submit(&vault.trading_agent).is_err());
+        assert!(submit(&second_trader).is_ok());
+    }
+
+    #[test]
+    fn test_guardian_can_only_halt() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let guardian = Address::generate(&env);
+
+        assert!(vault.client.try_emergency_halt(&guardian).is_err());
+        vault.client.grant_role(&GUARDIAN, &guardian);
+        vault.client.emergency_halt(&guardian);
+        assert!(!vault.client.is_operational());
+
+        // Resuming stays with the admin
+        env.mock_auths(&[]);
+        assert!(vault.client.try_resume_trading().is_err());
+        env.mock_all_auths();
+        vault.client.resume_trading();
+        assert!(vault.client.is_operational());
+    }
+
+    #[test]
+    fn test_blacklist() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let guardian = Address::generate(&env);
+        vault.client.grant_role(&GUARDIAN, &guardian);
+
+        let submit = || {
+            vault.client.try_submit_trading_signal(
+                &vault.trading_agent,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+
+        // Blocked at once, while still holding the role
+        vault.client.blacklist_address(&guardian, &vault.trading_agent);
+        assert!(vault.client.is_blacklisted(&vault.trading_agent));
+        assert!(vault.client.has_role(&TRADING_AGENT, &vault.trading_agent));
+        assert!(submit().is_err());
+        assert!(vault.client.try_grant_role(&RISK_AGENT, &vault.trading_agent).is_err());
+
+        // Other agents cannot blacklist; only the admin lifts it
+        assert!(vault.client.try_blacklist_address(&vault.risk_agent, &vault.payment_agent).is_err());
+        vault.client.unblacklist_address(&vault.trading_agent);
+        assert!(submit().is_ok());
+    }
+
+    #[test]
+    fn test_rotation_grace_period() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let new_trader = Address::generate(&env);
+        vault.client.set_rotation_grace(&3600);
+
+        let submit = |caller: &Address| {
+            vault.client.try_submit_trading_signal(
+                caller,
+                &String::from_str(&env, "BTC"),
+                &TradeAction::Buy,
+                &100000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+
+        assert!(vault.client.try_rotate_agent(&TRADING_AGENT, &vault.trading_agent, &vault.trading_agent).is_err());
+        let expires_at = vault.client.rotate_agent(&TRADING_AGENT, &vault.trading_agent, &new_trader);
+        assert_eq!(vault.client.get_role_expiry(&TRADING_AGENT, &vault.trading_agent), Some(expires_at));
+
+        // Both keys work during the overlap
+        assert!(submit(&vault.trading_agent).is_ok());
+        assert!(submit(&new_trader).is_ok());
+
+        env.ledger().with_mut(|l| l.timestamp = expires_at);
+        assert!(submit(&vault.trading_agent).is_err());
+        assert!(submit(&new_trader).is_ok());
+        assert_eq!(vault.client.get_role_members(&TRADING_AGENT).len(), 1);
+
+        // The lapsed key is dropped from storage, not just filtered on read
+        assert_eq!(vault.client.prune_role(&TRADING_AGENT), 1);
+        assert_eq!(vault.client.prune_role(&TRADING_AGENT), 0);
+        assert_eq!(vault.client.get_role_expiry(&TRADING_AGENT, &vault.trading_agent), None);
+        let stored: Vec<Address> = env.as_contract(&vault.client.address, || {
+            env.storage().instance().get(&DataKey::Role(TRADING_AGENT)).unwrap()
+        });
+        assert_eq!(stored, Vec::from_array(&env, [new_trader.clone()]));
+    }
+}
@@ -0,0 +1,145 @@
+//! Session keys for the trading agent.
+//!
+//! A trading agent can delegate a hot session key that may submit signals
+//! for up to `MAX_SESSION_HOURS` and up to a total notional, valued at the
+//! oracle price when each signal is submitted. The session is stored as a
+//! temporary entry, so it also disappears on its own once expired. It stops
+//! working as soon as its owner loses the trading role or is blacklisted.
+
+use soroban_sdk::{contractimpl, contracttype, Address, Env, String};
+
+use crate::{portfolio, roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Longest a session key can stay valid
+pub const MAX_SESSION_HOURS: u32 = 24;
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Session {
+    pub owner: Address,  // trading agent that delegated the key
+    pub expires_at: u64,
+    pub max_notional: i128,  // base-asset value the key may submit in total
+    pub used_notional: i128,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Delegate signal submission to a session key for `hours`, up to `max_notional`
+    pub fn create_session(env: Env, caller: Address, session_key: Address, hours: u32, max_notional: i128) {
+        roles::require_role(&env, &caller, &roles::TRADING_AGENT);
+
+        if hours == 0 || hours > MAX_SESSION_HOURS {
+            panic!("Session length out of range");
+        }
+        if max_notional <= 0 {
+            panic!("Session notional must be positive");
+        }
+
+        let session = Session {
+            owner: caller,
+            expires_at: env.ledger().timestamp() + hours as u64 * 3600,
+            max_notional,
+            used_notional: 0,
+        };
+        storage::set_temporary(&env, &DataKey::Session(session_key), &session);
+    }
+
+    /// End a session early (its owner or the admin)
+    pub fn revoke_session(env: Env, caller: Address, session_key: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        caller.require_auth();
+
+        let key = DataKey::Session(session_key);
+        let session: Session = env.storage().temporary().get(&key).expect("No such session");
+        if caller != session.owner && caller != config.admin {
+            panic!("Not the session owner");
+        }
+        env.storage().temporary().remove(&key);
+    }
+
+    /// Get a session key's delegation
+    pub fn get_session(env: Env, session_key: Address) -> Option<Session> {
+        env.storage().temporary().get(&DataKey::Session(session_key))
+    }
+}
+
+/// The trading agent behind `caller`: a session key's owner, or `caller` itself
+pub(crate) fn principal(env: &Env, caller: &Address) -> Address {
+    let session: Option<Session> = env.storage().temporary().get(&DataKey::Session(caller.clone()));
+    session.map_or(caller.clone(), |session| session.owner)
+}
+
+/// Authenticate a signal submitter: a trading agent, or a session key whose
+/// remaining notional covers `amount` of `asset`
+pub(crate) fn require_trader(env: &Env, config: &VaultConfig, caller: &Address, asset: &String, amount: i128) {
+    let key = DataKey::Session(caller.clone());
+    let mut session: Session = match env.storage().temporary().get(&key) {
+        Some(session) => session,
+        None => return roles::require_role(env, caller, &roles::TRADING_AGENT),
+    };
+
+    caller.require_auth();
+    if env.ledger().timestamp() >= session.expires_at {
+        panic!("Session expired");
+    }
+    if !roles::acts_as(env, &session.owner, &roles::TRADING_AGENT) {
+        panic!("Session owner lacks required role");
+    }
+
+    session.used_notional += portfolio::value_of(env, config, asset, amount.abs());
+    if session.used_notional > session.max_notional {
+        panic!("Session notional exceeded");
+    }
+    env.storage().temporary().set(&key, &session);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+
+    #[test]
+    fn test_session_keys() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let btc = String::from_str(&env, "BTC");
+        oracle.set_price(&btc, &100_0000000);
+
+        let session_key = Address::generate(&env);
+        let submit = |amount: i128| {
+            vault.client.try_submit_trading_signal(
+                &session_key,
+                &btc,
+                &TradeAction::Buy,
+                &amount,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            )
+        };
+        assert!(submit(1_0000000).is_err());
+        assert!(vault.client.try_create_session(&vault.trading_agent, &session_key, &25, &1).is_err());
+
+        // Two hours, 300 in notional: three BTC at 100
+        vault.client.create_session(&vault.trading_agent, &session_key, &2, &300_0000000);
+        assert!(submit(2_0000000).is_ok());
+        assert!(submit(2_0000000).is_err());
+        assert!(submit(1_0000000).is_ok());
+        assert_eq!(vault.client.get_session(&session_key).unwrap().used_notional, 300_0000000);
+
+        vault.client.create_session(&vault.trading_agent, &session_key, &2, &300_0000000);
+        env.ledger().with_mut(|l| l.timestamp += 2 * 3600);
+        assert!(submit(1_0000000).is_err());
+
+        // Revoked with its owner's role
+        vault.client.create_session(&vault.trading_agent, &session_key, &2, &300_0000000);
+        vault.client.revoke_role(&roles::TRADING_AGENT, &vault.trading_agent);
+        assert!(submit(1_0000000).is_err());
+    }
+}
@@ -0,0 +1,364 @@
+//! Agent stake bonds and slashing.
+//!
+//! Once the admin sets a stake token and minimum, every agent call needs
+//! the calling agent (or, for a session key, its owner) to have at least
+//! that much of the token bonded in the vault. Governance can slash a bond
+//! when an agent is shown to have submitted bad data, e.g. prices far off
+//! the oracle. A slash is queued with its evidence, a reason code and the
+//! offending signal or trade, and only finalizes after the dispute period;
+//! until then governance or the admin can cancel it. Finalized slashes move
+//! the tokens into the insurance reserve.
+//!
+//! An agent can only unbond below the minimum once it holds no agent role,
+//! and never the part of its bond a queued slash claims, so it cannot pull
+//! the bond out before governance acts.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, token, Address, Env, Symbol};
+
+use crate::{guard, roles, sessions, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum StakeKey {
+    StakeConfig,
+    Stake(Address),  // agent -> bonded amount (persistent)
+    InsuranceReserve,  // slashed stake held by the vault
+    DisputePeriod,
+    SlashCount,
+    PendingSlash(u32),  // slash_id -> slash awaiting its dispute period (persistent)
+    Slashing(Address),  // agent -> stake claimed by its queued slashes
+}
+
+/// Dispute period until the admin sets one
+pub const DEFAULT_DISPUTE_SECS: u64 = 3 * 86400;
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub enum SlashEvidence {
+    Signal(u64),  // offending signal_id
+    Trade(u64),  // offending trade_id
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PendingSlash {
+    pub slash_id: u32,
+    pub agent: Address,
+    pub amount: i128,
+    pub reason: Symbol,
+    pub evidence: SlashEvidence,
+    pub executable_at: u64,  // end of the dispute period
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct StakeConfig {
+    pub token: Address,  // token agents bond
+    pub min_stake: i128,  // bond needed to act as an agent
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Require agents to bond `min_stake` of `token` (0 lifts the requirement)
+    pub fn set_agent_stake(env: Env, token: Address, min_stake: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if min_stake < 0 {
+            panic!("Stake cannot be negative");
+        }
+        if let Some(current) = stake_config(&env) {
+            if current.token != token && reserve(&env) > 0 {
+                panic!("Insurance reserve held in another token");
+            }
+        }
+
+        env.storage().instance().set(&StakeKey::StakeConfig, &StakeConfig { token, min_stake });
+        storage::extend_instance(&env);
+    }
+
+    /// Get the stake requirement
+    pub fn get_stake_config(env: Env) -> Option<StakeConfig> {
+        stake_config(&env)
+    }
+
+    /// Bond stake tokens for an agent
+    pub fn bond_stake(env: Env, agent: Address, amount: i128) {
+        agent.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("stake"));
+        let stake = stake_config(&env).expect("Staking not configured");
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+
+        set_stake(&env, &agent, stake_of(&env, &agent) + amount);
+        token::Client::new(&env, &stake.token).transfer(&agent, &env.current_contract_address(), &amount);
+
+        env.events().publish((symbol_short!("stake"), symbol_short!("bonded"), agent), amount);
+    }
+
+    /// Return bonded tokens to an agent
+    pub fn unbond_stake(env: Env, agent: Address, amount: i128) {
+        agent.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("stake"));
+        let stake = stake_config(&env).expect("Staking not configured");
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        let bonded = stake_of(&env, &agent);
+        if amount > bonded {
+            panic!("Insufficient stake");
+        }
+        if amount > bonded - slashing(&env, &agent) {
+            panic!("Stake is claimed by a pending slash");
+        }
+        if bonded - amount < stake.min_stake && holds_agent_role(&env, &agent) {
+            panic!("Stake is bonded to a role");
+        }
+
+        set_stake(&env, &agent, bonded - amount);
+        token::Client::new(&env, &stake.token).transfer(&env.current_contract_address(), &agent, &amount);
+
+        env.events().publish((symbol_short!("stake"), symbol_short!("unbonded"), agent), amount);
+    }
+
+    /// Queue a slash of part of an agent's bond (governance)
+    ///
+    /// Returns the slash id; it can be finalized after the dispute period.
+    pub fn slash_agent(
+        env: Env,
+        caller: Address,
+        agent: Address,
+        amount: i128,
+        reason: Symbol,
+        evidence: SlashEvidence,
+    ) -> u32 {
+        roles::require_role(&env, &caller, &roles::GOVERNANCE);
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        let slashing = slashing(&env, &agent);
+        if amount > stake_of(&env, &agent) - slashing {
+            panic!("Insufficient stake");
+        }
+
+        let slash_id: u32 = env.storage().instance().get(&StakeKey::SlashCount).unwrap_or(0) + 1;
+        let slash = PendingSlash {
+            slash_id,
+            agent: agent.clone(),
+            amount,
+            reason,
+            evidence,
+            executable_at: env.ledger().timestamp() + dispute_period(&env),
+        };
+        let key = StakeKey::PendingSlash(slash_id);
+        env.storage().persistent().set(&key, &slash);
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+        env.storage().instance().set(&StakeKey::SlashCount, &slash_id);
+        set_slashing(&env, &agent, slashing + amount);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("slash"), symbol_short!("queued"), agent), slash);
+        slash_id
+    }
+
+    /// Move a queued slash into the insurance reserve once its dispute period is over
+    pub fn finalize_slash(env: Env, slash_id: u32) {
+        let slash = take_slash(&env, slash_id);
+        if env.ledger().timestamp() < slash.executable_at {
+            panic!("Dispute period has not elapsed");
+        }
+
+        set_stake(&env, &slash.agent, stake_of(&env, &slash.agent) - slash.amount);
+        env.storage().instance().set(&StakeKey::InsuranceReserve, &(reserve(&env) + slash.amount));
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("slash"), symbol_short!("final"), slash.agent), (slash.amount, slash.reason));
+    }
+
+    /// Cancel a queued slash during its dispute period (governance or admin)
+    pub fn cancel_slash(env: Env, caller: Address, slash_id: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        caller.require_auth();
+
+        if caller != config.admin && !roles::acts_as(&env, &caller, &roles::GOVERNANCE) {
+            panic!("Caller cannot cancel slashes");
+        }
+
+        let slash = take_slash(&env, slash_id);
+        env.events().publish((symbol_short!("slash"), symbol_short!("cancel"), slash.agent), slash_id);
+    }
+
+    /// Get a slash still in its dispute period or awaiting finalization
+    pub fn get_pending_slash(env: Env, slash_id: u32) -> Option<PendingSlash> {
+        env.storage().persistent().get(&StakeKey::PendingSlash(slash_id))
+    }
+
+    /// Set how long a queued slash can be disputed
+    pub fn set_slash_dispute_period(env: Env, dispute_secs: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        env.storage().instance().set(&StakeKey::DisputePeriod, &dispute_secs);
+        storage::extend_instance(&env);
+    }
+
+    /// Get how long a queued slash can be disputed
+    pub fn get_slash_dispute_period(env: Env) -> u64 {
+        dispute_period(&env)
+    }
+
+    /// Get an agent's bonded stake
+    pub fn get_stake(env: Env, agent: Address) -> i128 {
+        stake_of(&env, &agent)
+    }
+
+    /// Get the slashed stake held in the insurance reserve
+    pub fn get_insurance_reserve(env: Env) -> i128 {
+        reserve(&env)
+    }
+}
+
+fn stake_config(env: &Env) -> Option<StakeConfig> {
+    env.storage().instance().get(&StakeKey::StakeConfig)
+}
+
+/// Token agents bond stake in, if staking is configured
+pub(crate) fn stake_token(env: &Env) -> Option<Address> {
+    stake_config(env).map(|stake| stake.token)
+}
+
+fn reserve(env: &Env) -> i128 {
+    env.storage().instance().get(&StakeKey::InsuranceReserve).unwrap_or(0)
+}
+
+fn dispute_period(env: &Env) -> u64 {
+    env.storage().instance().get(&StakeKey::DisputePeriod).unwrap_or(DEFAULT_DISPUTE_SECS)
+}
+
+fn slashing(env: &Env, agent: &Address) -> i128 {
+    env.storage().instance().get(&StakeKey::Slashing(agent.clone())).unwrap_or(0)
+}
+
+fn set_slashing(env: &Env, agent: &Address, amount: i128) {
+    let key = StakeKey::Slashing(agent.clone());
+    if amount == 0 {
+        env.storage().instance().remove(&key);
+    } else {
+        env.storage().instance().set(&key, &amount);
+    }
+}
+
+/// Remove a queued slash and release its claim on the agent's stake
+fn take_slash(env: &Env, slash_id: u32) -> PendingSlash {
+    let key = StakeKey::PendingSlash(slash_id);
+    let slash: PendingSlash = env.storage().persistent().get(&key).expect("No such slash");
+    env.storage().persistent().remove(&key);
+    set_slashing(env, &slash.agent, slashing(env, &slash.agent) - slash.amount);
+    slash
+}
+
+pub(crate) fn stake_of(env: &Env, agent: &Address) -> i128 {
+    env.storage().persistent().get(&StakeKey::Stake(agent.clone())).unwrap_or(0)
+}
+
+fn set_stake(env: &Env, agent: &Address, amount: i128) {
+    let key = StakeKey::Stake(agent.clone());
+    if amount == 0 {
+        env.storage().persistent().remove(&key);
+        return;
+    }
+    env.storage().persistent().set(&key, &amount);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+fn holds_agent_role(env: &Env, agent: &Address) -> bool {
+    [roles::TRADING_AGENT, roles::RISK_AGENT, roles::PAYMENT_AGENT]
+        .iter()
+        .any(|role| roles::members(env, role).contains(agent))
+}
+
+/// Fail unless the calling agent has the minimum stake bonded
+pub(crate) fn require_bond(env: &Env, caller: &Address) {
+    let stake = match stake_config(env) {
+        Some(stake) if stake.min_stake > 0 => stake,
+        _ => return,
+    };
+    if stake_of(env, &sessions::principal(env, caller)) < stake.min_stake {
+        panic!("Agent stake below minimum");
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::String;
+
+    #[test]
+    fn test_stake_and_slash() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let stake_token = env.register_stellar_asset_contract_v2(vault.admin.clone()).address();
+        StellarAssetClient::new(&env, &stake_token).mint(&vault.trading_agent, &500_0000000);
+        let governance = Address::generate(&env);
+        env.as_contract(&vault.client.address, || roles::grant(&env, &roles::GOVERNANCE, &governance));
+
+        let submit = || vault.client.try_submit_trading_signal(
+            &vault.trading_agent,
+            &String::from_str(&env, "BTC"),
+            &TradeAction::Buy,
+            &100000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+
+        // Unbonded agents cannot act once a stake is required
+        vault.client.set_agent_stake(&stake_token, &300_0000000);
+        assert!(submit().is_err());
+        vault.client.bond_stake(&vault.trading_agent, &400_0000000);
+        assert!(submit().is_ok());
+        assert!(vault.client.try_unbond_stake(&vault.trading_agent, &200_0000000).is_err());
+
+        // Only governance queues slashes, and they wait out the dispute period
+        let reason = Symbol::new(&env, "price_off_oracle");
+        let evidence = SlashEvidence::Signal(1);
+        assert!(vault.client.try_slash_agent(&vault.admin, &vault.trading_agent, &150_0000000, &reason, &evidence).is_err());
+        let slash_id = vault.client.slash_agent(&governance, &vault.trading_agent, &150_0000000, &reason, &evidence);
+        assert_eq!(vault.client.get_pending_slash(&slash_id).unwrap().evidence, evidence);
+        assert!(vault.client.try_finalize_slash(&slash_id).is_err());
+        assert!(vault.client.try_slash_agent(&governance, &vault.trading_agent, &300_0000000, &reason, &evidence).is_err());
+
+        // The admin can cancel a disputed slash
+        let disputed = vault.client.slash_agent(&governance, &vault.trading_agent, &100_0000000, &reason, &evidence);
+        assert!(vault.client.try_cancel_slash(&vault.trading_agent, &disputed).is_err());
+        vault.client.cancel_slash(&vault.admin, &disputed);
+        assert_eq!(vault.client.get_pending_slash(&disputed), None);
+        assert!(vault.client.try_finalize_slash(&disputed).is_err());
+
+        // Finalized stake moves to the insurance reserve
+        env.ledger().with_mut(|l| l.timestamp += DEFAULT_DISPUTE_SECS);
+        vault.client.finalize_slash(&slash_id);
+        assert_eq!(vault.client.get_stake(&vault.trading_agent), 250_0000000);
+        assert_eq!(vault.client.get_insurance_reserve(), 150_0000000);
+        assert!(submit().is_err());
+
+        // Once out of its roles the agent can take back what no slash claims
+        vault.client.slash_agent(&governance, &vault.trading_agent, &50_0000000, &reason, &SlashEvidence::Trade(1));
+        vault.client.revoke_role(&roles::TRADING_AGENT, &vault.trading_agent);
+        assert!(vault.client.try_unbond_stake(&vault.trading_agent, &250_0000000).is_err());
+        vault.client.unbond_stake(&vault.trading_agent, &200_0000000);
+        assert_eq!(token::Client::new(&env, &stake_token).balance(&vault.trading_agent), 300_0000000);
+    }
+}
@@ -0,0 +1,176 @@
+//! Storage TTL management.
+//!
+//! Instance storage only holds config, counters and other small bounded
+//! values; per-trade and per-snapshot records live in persistent storage with
+//! their own TTLs. Persistent entries are bumped whenever they are read or
+//! written through these helpers, and the instance is bumped on every
+//! mutating entry point. `extend_storage` lets anyone keep rarely touched
+//! entries alive.
+
+use soroban_sdk::{contractimpl, Env, IntoVal, TryFromVal, Val, Vec};
+
+use crate::{
+    AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, PortfolioSnapshot, TradeRecord,
+    VaultConfig,
+};
+
+pub(crate) const DAY_IN_LEDGERS: u32 = 17280;
+pub(crate) const INSTANCE_BUMP_AMOUNT: u32 = 30 * DAY_IN_LEDGERS;
+pub(crate) const INSTANCE_LIFETIME_THRESHOLD: u32 = INSTANCE_BUMP_AMOUNT - DAY_IN_LEDGERS;
+pub(crate) const PERSISTENT_BUMP_AMOUNT: u32 = 90 * DAY_IN_LEDGERS;
+pub(crate) const PERSISTENT_LIFETIME_THRESHOLD: u32 = PERSISTENT_BUMP_AMOUNT - 7 * DAY_IN_LEDGERS;
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Extend the TTL of the instance and of the given persistent entries
+    ///
+    /// Returns how many of the keys were found in persistent storage.
+    pub fn extend_storage(env: Env, keys: Vec<DataKey>) -> u32 {
+        extend_instance(&env);
+
+        let mut extended = 0;
+        for key in keys.iter() {
+            if env.storage().persistent().has(&key) {
+                extend_persistent(&env, &key);
+                extended += 1;
+            }
+        }
+        extended
+    }
+
+    /// Move trade and snapshot records written by older versions out of
+    /// instance storage, for ids in `[start_id, start_id + limit)`
+    pub fn migrate_history_storage(env: Env, start_id: u64, limit: u32) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let mut moved = 0;
+        for id in start_id..start_id + limit as u64 {
+            let trade_key = DataKey::Trade(id);
+            if let Some(trade) = env.storage().instance().get::<_, TradeRecord>(&trade_key) {
+                set_persistent(&env, &trade_key, &trade);
+                env.storage().instance().remove(&trade_key);
+                moved += 1;
+            }
+
+            let snapshot_key = DataKey::Snapshot(id);
+            if let Some(snapshot) = env.storage().instance().get::<_, PortfolioSnapshot>(&snapshot_key) {
+                set_persistent(&env, &snapshot_key, &snapshot);
+                env.storage().instance().remove(&snapshot_key);
+                moved += 1;
+            }
+        }
+
+        extend_instance(&env);
+        moved
+    }
+}
+
+pub(crate) fn extend_instance(env: &Env) {
+    env.storage()
+        .instance()
+        .extend_ttl(INSTANCE_LIFETIME_THRESHOLD, INSTANCE_BUMP_AMOUNT);
+}
+
+pub(crate) fn extend_persistent(env: &Env, key: &DataKey) {
+    env.storage()
+        .persistent()
+        .extend_ttl(key, PERSISTENT_LIFETIME_THRESHOLD, PERSISTENT_BUMP_AMOUNT);
+}
+
+/// Read a persistent entry, bumping its TTL if present
+pub(crate) fn get_persistent<V: TryFromVal<Env, Val>>(env: &Env, key: &DataKey) -> Option<V> {
+    let value = env.storage().persistent().get(key);
+    if value.is_some() {
+        extend_persistent(env, key);
+    }
+    value
+}
+
+/// Write a persistent entry and bump its TTL
+pub(crate) fn set_persistent<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().persistent().set(key, value);
+    extend_persistent(env, key);
+}
+
+/// Write a temporary entry that lives for about a day
+pub(crate) fn set_temporary<V: IntoVal<Env, Val>>(env: &Env, key: &DataKey, value: &V) {
+    env.storage().temporary().set(key, value);
+    env.storage().temporary().extend_ttl(key, DAY_IN_LEDGERS, DAY_IN_LEDGERS);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use crate::TradeAction;
+    use soroban_sdk::testutils::storage::Persistent;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::{Address, String};
+
+    #[test]
+    fn test_extend_storage() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &100_0000000);
+        vault.client.deposit(&alice, &100_0000000);
+
+        let key = DataKey::Shares(alice.clone());
+        let ttl = || {
+            env.as_contract(&vault.client.address, || env.storage().persistent().get_ttl(&key))
+        };
+        assert_eq!(ttl(), PERSISTENT_BUMP_AMOUNT);
+
+        // Let the entry age past the bump threshold, then keep it alive
+        env.ledger().with_mut(|l| l.sequence_number += 10 * DAY_IN_LEDGERS);
+        assert_eq!(ttl(), PERSISTENT_BUMP_AMOUNT - 10 * DAY_IN_LEDGERS);
+
+        let keys = Vec::from_array(&env, [key.clone(), DataKey::Shares(Address::generate(&env))]);
+        assert_eq!(vault.client.extend_storage(&keys), 1);
+        assert_eq!(ttl(), PERSISTENT_BUMP_AMOUNT);
+    }
+
+    #[test]
+    fn test_history_in_persistent_storage() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let contract_id = vault.client.address.clone();
+
+        let signal_id = vault.client.submit_trading_signal(
+            &vault.trading_agent,
+            &String::from_str(&env, "BTC"),
+            &TradeAction::Buy,
+            &100000,
+            &String::from_str(&env, "LSTM"),
+            &85,
+            &250,
+            &None,
+            &None,
+        );
+        vault.client.execute_trade(&vault.payment_agent, &signal_id, &45000_0000000, &None);
+        vault.client.create_snapshot(&vault.trading_agent, &1000, &1, &0);
+
+        env.as_contract(&contract_id, || {
+            assert!(env.storage().persistent().has(&DataKey::Trade(1)));
+            assert!(env.storage().persistent().has(&DataKey::Snapshot(1)));
+            assert!(!env.storage().instance().has(&DataKey::Trade(1)));
+
+            // Simulate a record written by an older version
+            let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+            let mut legacy = crate::records::load_trade(&env, &config, 1).unwrap();
+            legacy.trade_id = 2;
+            env.storage().instance().set(&DataKey::Trade(2), &legacy);
+        });
+
+        // Readable before and after moving it
+        assert_eq!(vault.client.get_trade(&2).trade_id, 2);
+        assert_eq!(vault.client.migrate_history_storage(&1, &5), 1);
+        assert_eq!(vault.client.get_trade(&2).trade_id, 2);
+        env.as_contract(&contract_id, || {
+            assert!(!env.storage().instance().has(&DataKey::Trade(2)));
+        });
+    }
+}
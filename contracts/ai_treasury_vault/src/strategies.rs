@@ -0,0 +1,200 @@
+//! External strategy-vault adapters.
+//!
+//! The admin can register other Soroban contracts implementing
+//! `IStrategyVault` and allocate base-asset capital to them up to a
+//! per-adapter cap. Allocated capital leaves the base position and is valued
+//! through each adapter's `report`, so NAV keeps counting it.
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, Vec};
+
+use crate::{assets, guard, portfolio, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Interface an external strategy contract exposes to the treasury
+#[allow(dead_code)]
+#[contractclient(name = "StrategyVaultClient")]
+pub trait IStrategyVault {
+    /// Called after `amount` base tokens have been transferred to the strategy
+    fn deposit(env: Env, from: Address, amount: i128);
+    /// Return up to `amount` base tokens to `to`; returns the amount sent
+    fn withdraw(env: Env, to: Address, amount: i128) -> i128;
+    /// Current value of the treasury's holdings in the strategy (base units)
+    fn report(env: Env) -> i128;
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub enum StrategyKey {
+    Adapter(Address),
+    Adapters,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct StrategyAdapter {
+    pub adapter: Address,
+    pub cap: i128,  // most base capital that may be allocated at once
+    pub allocated: i128,  // principal currently allocated
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Register an external strategy contract, or update its cap
+    pub fn register_adapter(env: Env, adapter: Address, cap: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if cap < 0 {
+            panic!("Cap must be non-negative");
+        }
+
+        let info = match adapter_info(&env, &adapter) {
+            Some(mut info) => {
+                info.cap = cap;
+                info
+            }
+            None => {
+                let mut adapters = adapter_list(&env);
+                adapters.push_back(adapter.clone());
+                env.storage().instance().set(&StrategyKey::Adapters, &adapters);
+                StrategyAdapter { adapter: adapter.clone(), cap, allocated: 0 }
+            }
+        };
+        env.storage().instance().set(&StrategyKey::Adapter(adapter), &info);
+    }
+
+    /// Move base capital into a registered strategy
+    pub fn allocate_to_adapter(env: Env, adapter: Address, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("adapter"));
+
+        let mut info = adapter_info(&env, &adapter).expect("Adapter not registered");
+        if amount <= 0 || info.allocated + amount > info.cap {
+            panic!("Allocation exceeds adapter cap");
+        }
+        if portfolio::position(&env, &config.base_asset) < amount {
+            panic!("Insufficient base asset liquidity");
+        }
+
+        let base_token = assets::token_address(&env, &config, &config.base_asset)
+            .expect("Base token not configured");
+        info.allocated += amount;
+        env.storage().instance().set(&StrategyKey::Adapter(adapter.clone()), &info);
+        portfolio::adjust_position(&env, &config.base_asset, -amount);
+        storage::extend_instance(&env);
+
+        let vault = env.current_contract_address();
+        token::Client::new(&env, &base_token).transfer(&vault, &adapter, &amount);
+        StrategyVaultClient::new(&env, &adapter).deposit(&vault, &amount);
+    }
+
+    /// Pull base capital back from a strategy; returns the amount received
+    pub fn recall_from_adapter(env: Env, adapter: Address, amount: i128) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("adapter"));
+
+        let mut info = adapter_info(&env, &adapter).expect("Adapter not registered");
+        let received = StrategyVaultClient::new(&env, &adapter)
+            .withdraw(&env.current_contract_address(), &amount);
+
+        info.allocated = (info.allocated - received).max(0);
+        env.storage().instance().set(&StrategyKey::Adapter(adapter), &info);
+        portfolio::adjust_position(&env, &config.base_asset, received);
+        storage::extend_instance(&env);
+
+        received
+    }
+
+    /// Get a registered strategy adapter
+    pub fn get_adapter(env: Env, adapter: Address) -> Option<StrategyAdapter> {
+        adapter_info(&env, &adapter)
+    }
+
+    /// Get every registered strategy adapter
+    pub fn get_adapters(env: Env) -> Vec<Address> {
+        adapter_list(&env)
+    }
+}
+
+fn adapter_info(env: &Env, adapter: &Address) -> Option<StrategyAdapter> {
+    env.storage().instance().get(&StrategyKey::Adapter(adapter.clone()))
+}
+
+fn adapter_list(env: &Env) -> Vec<Address> {
+    env.storage().instance()
+        .get(&StrategyKey::Adapters)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Reported value of everything allocated to strategies
+pub(crate) fn adapters_value(env: &Env) -> i128 {
+    let mut total = 0;
+    for adapter in adapter_list(env).iter() {
+        if adapter_info(env, &adapter).map_or(0, |info| info.allocated) > 0 {
+            total += StrategyVaultClient::new(env, &adapter).report();
+        }
+    }
+    total
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::{contract, symbol_short, String};
+
+    /// Strategy that just holds the tokens it receives
+    #[contract]
+    pub struct MockStrategy;
+
+    #[contractimpl]
+    impl MockStrategy {
+        pub fn init(env: Env, token: Address) {
+            env.storage().instance().set(&symbol_short!("token"), &token);
+        }
+
+        pub fn deposit(_env: Env, _from: Address, _amount: i128) {}
+
+        pub fn withdraw(env: Env, to: Address, amount: i128) -> i128 {
+            let token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+            token::Client::new(&env, &token).transfer(&env.current_contract_address(), &to, &amount);
+            amount
+        }
+
+        pub fn report(env: Env) -> i128 {
+            let token: Address = env.storage().instance().get(&symbol_short!("token")).unwrap();
+            token::Client::new(&env, &token).balance(&env.current_contract_address())
+        }
+    }
+
+    #[test]
+    fn test_allocate_to_strategy() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let strategy = env.register_contract(None, MockStrategy);
+        MockStrategyClient::new(&env, &strategy).init(&token.address);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        assert!(vault.client.try_allocate_to_adapter(&strategy, &100_0000000).is_err());
+        vault.client.register_adapter(&strategy, &400_0000000);
+        vault.client.allocate_to_adapter(&strategy, &300_0000000);
+        assert!(vault.client.try_allocate_to_adapter(&strategy, &200_0000000).is_err());
+        assert_eq!(vault.client.get_adapter(&strategy).unwrap().allocated, 300_0000000);
+
+        // The strategy earns 30; NAV sees it through `report`
+        token.mint(&strategy, &30_0000000);
+        assert_eq!(vault.client.compute_nav(), 1030_0000000);
+
+        assert_eq!(vault.client.recall_from_adapter(&strategy, &330_0000000), 330_0000000);
+        assert_eq!(vault.client.get_adapter(&strategy).unwrap().allocated, 0);
+        assert_eq!(vault.client.get_token_balance(&String::from_str(&env, "XLM")), 1030_0000000);
+        assert_eq!(vault.client.compute_nav(), 1030_0000000);
+    }
+}
@@ -0,0 +1,144 @@
+//! Streaming payments.
+//!
+//! A stream vests `total` of a token to its recipient linearly, second by
+//! second, between `start` and `end`. The recipient withdraws whatever has
+//! vested and not yet been withdrawn whenever they like, so contributors
+//! are paid continuously instead of in lump sums. Withdrawals go through
+//! the same payout path as scheduled payments.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env};
+
+use crate::{guard, payouts, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum StreamKey {
+    StreamCount,
+    Stream(u64),  // stream_id -> stream and amount withdrawn (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct PaymentStream {
+    pub stream_id: u64,
+    pub recipient: Address,
+    pub token: Address,
+    pub total: i128,
+    pub start: u64,
+    pub end: u64,
+    pub withdrawn: i128,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Stream `total` of `token` to `recipient` between `start` and `end`
+    pub fn create_stream(env: Env, recipient: Address, token: Address, total: i128, start: u64, end: u64) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if total <= 0 {
+            panic!("Amount must be positive");
+        }
+        if start >= end {
+            panic!("Invalid stream period");
+        }
+
+        let stream_id: u64 = env.storage().instance().get(&StreamKey::StreamCount).unwrap_or(0) + 1;
+        store_stream(&env, &PaymentStream { stream_id, recipient, token, total, start, end, withdrawn: 0 });
+        env.storage().instance().set(&StreamKey::StreamCount, &stream_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("stream"), symbol_short!("create"), stream_id), total);
+        stream_id
+    }
+
+    /// Withdraw everything vested so far to the stream's recipient; returns the amount
+    pub fn withdraw_from_stream(env: Env, stream_id: u64) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        let mut stream = stream(&env, stream_id);
+        stream.recipient.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("stream"));
+
+        let amount = vested(&env, &stream) - stream.withdrawn;
+        if amount <= 0 {
+            panic!("Nothing vested to withdraw");
+        }
+        stream.withdrawn += amount;
+        store_stream(&env, &stream);
+        storage::extend_instance(&env);
+
+        payouts::pay_out(&env, &config, &stream.token, &stream.recipient, amount);
+        env.events().publish((symbol_short!("stream"), symbol_short!("withdraw"), stream_id), amount);
+        amount
+    }
+
+    /// Get a stream
+    pub fn get_stream(env: Env, stream_id: u64) -> Option<PaymentStream> {
+        env.storage().persistent().get(&StreamKey::Stream(stream_id))
+    }
+
+    /// Get the amount a stream has vested so far, withdrawn or not
+    pub fn get_stream_vested(env: Env, stream_id: u64) -> i128 {
+        vested(&env, &stream(&env, stream_id))
+    }
+}
+
+fn vested(env: &Env, stream: &PaymentStream) -> i128 {
+    let now = env.ledger().timestamp();
+    if now <= stream.start {
+        return 0;
+    }
+    if now >= stream.end {
+        return stream.total;
+    }
+    stream.total * (now - stream.start) as i128 / (stream.end - stream.start) as i128
+}
+
+fn stream(env: &Env, stream_id: u64) -> PaymentStream {
+    env.storage().persistent()
+        .get(&StreamKey::Stream(stream_id))
+        .expect("No such stream")
+}
+
+fn store_stream(env: &Env, stream: &PaymentStream) {
+    let key = StreamKey::Stream(stream.stream_id);
+    env.storage().persistent().set(&key, stream);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::{token, String};
+
+    #[test]
+    fn test_stream_vests_linearly() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        let contributor = Address::generate(&env);
+        let stream_id = vault.client.create_stream(&contributor, &token.address, &100_0000000, &1000, &2000);
+        assert!(vault.client.try_withdraw_from_stream(&stream_id).is_err());
+
+        // A quarter of the way through, a quarter has vested
+        env.ledger().with_mut(|l| l.timestamp = 1250);
+        assert_eq!(vault.client.withdraw_from_stream(&stream_id), 25_0000000);
+        assert!(vault.client.try_withdraw_from_stream(&stream_id).is_err());
+
+        // Past the end, the rest
+        env.ledger().with_mut(|l| l.timestamp = 5000);
+        assert_eq!(vault.client.get_stream_vested(&stream_id), 100_0000000);
+        assert_eq!(vault.client.withdraw_from_stream(&stream_id), 75_0000000);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&contributor), 100_0000000);
+        assert_eq!(vault.client.get_stream(&stream_id).unwrap().withdrawn, 100_0000000);
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 900_0000000);
+    }
+}
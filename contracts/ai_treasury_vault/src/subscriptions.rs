@@ -0,0 +1,201 @@
+//! Recurring payment subscriptions.
+//!
+//! A subscription pays a fixed amount of a token to its payee every
+//! `interval_secs`. Payments are pushed by keepers through
+//! `process_subscription`, which pays at most once per interval and earns
+//! the keeper fee. When a payment falls due while the vault cannot fund it
+//! (too little of the token, or it would breach the operating reserve) the
+//! period is counted as missed and skipped; after the configured number of
+//! consecutive misses the subscription cancels itself.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env};
+
+use crate::{fees, guard, payouts, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+/// Consecutive missed periods that cancel a subscription unless the admin
+/// sets another limit
+pub const DEFAULT_MAX_MISSED_PERIODS: u32 = 3;
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum SubscriptionKey {
+    SubscriptionCount,
+    MaxMissedPeriods,
+    Subscription(u64),  // subscription_id -> subscription (persistent)
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Subscription {
+    pub subscription_id: u64,
+    pub payee: Address,
+    pub token: Address,
+    pub amount: i128,
+    pub interval_secs: u64,
+    pub next_due: u64,
+    pub missed: u32,  // consecutive periods the vault could not fund
+    pub cancelled_at: Option<u64>,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Pay `amount` of `token` to `payee` every `interval_secs`, starting now
+    pub fn create_subscription(env: Env, payee: Address, token: Address, amount: i128, interval_secs: u64) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if interval_secs == 0 {
+            panic!("Interval must be positive");
+        }
+
+        let subscription_id: u64 = env.storage().instance().get(&SubscriptionKey::SubscriptionCount).unwrap_or(0) + 1;
+        store_subscription(&env, &Subscription {
+            subscription_id,
+            payee,
+            token,
+            amount,
+            interval_secs,
+            next_due: env.ledger().timestamp(),
+            missed: 0,
+            cancelled_at: None,
+        });
+        env.storage().instance().set(&SubscriptionKey::SubscriptionCount, &subscription_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("sub"), symbol_short!("create"), subscription_id), amount);
+        subscription_id
+    }
+
+    /// Make a subscription's due payment, or count the period as missed if
+    /// the vault cannot fund it; returns whether it paid
+    ///
+    /// Anyone may call this; the caller is paid the keeper fee.
+    pub fn process_subscription(env: Env, keeper: Address, subscription_id: u64) -> bool {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        keeper.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("sub"));
+
+        let mut subscription = subscription(&env, subscription_id);
+        if subscription.cancelled_at.is_some() {
+            panic!("Subscription cancelled");
+        }
+        let now = env.ledger().timestamp();
+        if now < subscription.next_due {
+            panic!("Subscription payment not due");
+        }
+        subscription.next_due = now + subscription.interval_secs;
+
+        let paid = payouts::can_pay(&env, &config, &subscription.token, subscription.amount);
+        if paid {
+            subscription.missed = 0;
+        } else {
+            subscription.missed += 1;
+            if subscription.missed >= max_missed_periods(&env) {
+                subscription.cancelled_at = Some(now);
+                env.events().publish((symbol_short!("sub"), symbol_short!("lapsed"), subscription_id), subscription.missed);
+            }
+        }
+        store_subscription(&env, &subscription);
+
+        if paid {
+            payouts::pay_out(&env, &config, &subscription.token, &subscription.payee, subscription.amount);
+            env.events().publish((symbol_short!("sub"), symbol_short!("paid"), subscription_id), subscription.amount);
+        }
+        fees::pay_keeper(&env, &config, &keeper);
+        storage::extend_instance(&env);
+        paid
+    }
+
+    /// Cancel a subscription (admin)
+    pub fn cancel_subscription(env: Env, subscription_id: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let mut subscription = subscription(&env, subscription_id);
+        if subscription.cancelled_at.is_some() {
+            panic!("Subscription cancelled");
+        }
+        subscription.cancelled_at = Some(env.ledger().timestamp());
+        store_subscription(&env, &subscription);
+
+        env.events().publish((symbol_short!("sub"), symbol_short!("cancel"), subscription_id), subscription.amount);
+    }
+
+    /// Set how many consecutive missed periods cancel a subscription
+    pub fn set_max_missed_periods(env: Env, max_missed: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if max_missed == 0 {
+            panic!("Missed period limit must be positive");
+        }
+        env.storage().instance().set(&SubscriptionKey::MaxMissedPeriods, &max_missed);
+    }
+
+    /// Get a subscription
+    pub fn get_subscription(env: Env, subscription_id: u64) -> Option<Subscription> {
+        env.storage().persistent().get(&SubscriptionKey::Subscription(subscription_id))
+    }
+}
+
+fn max_missed_periods(env: &Env) -> u32 {
+    env.storage().instance()
+        .get(&SubscriptionKey::MaxMissedPeriods)
+        .unwrap_or(DEFAULT_MAX_MISSED_PERIODS)
+}
+
+fn subscription(env: &Env, subscription_id: u64) -> Subscription {
+    env.storage().persistent()
+        .get(&SubscriptionKey::Subscription(subscription_id))
+        .expect("No such subscription")
+}
+
+fn store_subscription(env: &Env, subscription: &Subscription) {
+    let key = SubscriptionKey::Subscription(subscription.subscription_id);
+    env.storage().persistent().set(&key, subscription);
+    env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::token;
+
+    #[test]
+    fn test_subscription_spacing_and_lapse() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let alice = Address::generate(&env);
+        token.mint(&alice, &250_0000000);
+        vault.client.deposit(&alice, &250_0000000);
+        vault.client.set_max_missed_periods(&2);
+        let keeper = Address::generate(&env);
+
+        let payee = Address::generate(&env);
+        let subscription_id = vault.client.create_subscription(&payee, &token.address, &100_0000000, &1000);
+        assert!(vault.client.process_subscription(&keeper, &subscription_id));
+        assert!(vault.client.try_process_subscription(&keeper, &subscription_id).is_err());
+
+        env.ledger().with_mut(|l| l.timestamp = 1000);
+        assert!(vault.client.process_subscription(&keeper, &subscription_id));
+        assert_eq!(token::Client::new(&env, &token.address).balance(&payee), 200_0000000);
+
+        // 50 left cannot fund a payment of 100: two misses cancel it
+        env.ledger().with_mut(|l| l.timestamp = 2000);
+        assert!(!vault.client.process_subscription(&keeper, &subscription_id));
+        env.ledger().with_mut(|l| l.timestamp = 3000);
+        assert!(!vault.client.process_subscription(&keeper, &subscription_id));
+        let subscription = vault.client.get_subscription(&subscription_id).unwrap();
+        assert_eq!((subscription.missed, subscription.cancelled_at), (2, Some(3000)));
+        env.ledger().with_mut(|l| l.timestamp = 4000);
+        assert!(vault.client.try_process_subscription(&keeper, &subscription_id).is_err());
+    }
+}
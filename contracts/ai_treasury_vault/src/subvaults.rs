@@ -0,0 +1,527 @@
+//! Per-strategy sub-vault accounting.
+//!
+//! The admin can ring-fence base-asset capital for a strategy in a named
+//! sub-vault. Every fill booked for that strategy then debits or credits
+//! only the sub-vault: buys spend its cash and fail once it runs out, sells
+//! need the sub-vault's own holdings (unless shorting is enabled) and return
+//! the proceeds to it. A model that blows up can therefore lose at most what
+//! it was given. Strategies without a sub-vault trade against the shared
+//! book as before.
+//!
+//! Sub-vaults are a partition of the vault's positions, not separate
+//! balances: funding one only moves unallocated base capital into it.
+//!
+//! Cash can be moved between sub-vaults with `reallocate`, which needs both
+//! a risk agent and the admin; every move is kept in a reallocation history.
+//!
+//! `rebalance_strategy_capital` redistributes sub-vault cash automatically.
+//! It is a keeper call, paid like scheduled snapshots, that splits the cash
+//! in proportion to each strategy's score under the allocation policy (win
+//! rate, average return, or a score such as a rolling Sharpe reported by a
+//! risk agent) and clamps the shares to per-strategy bounds. Only cash is
+//! moved; positions stay with the strategy that opened them.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, Address, Env, Map, String, Vec};
+
+use crate::{fees, guard, portfolio, roles, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, Fill, TradeAction, VaultConfig};
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum SubVaultKey {
+    SubVaults,  // strategies that have a sub-vault
+    ReallocationCount,
+    Reallocation(u32),  // reallocation_id -> record (persistent)
+    AllocationPolicy,
+    AllocationBounds(String),  // strategy -> share of sub-vault cash it may hold
+    StrategyScore(String),  // strategy -> score last reported by a risk agent
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+#[contracttype]
+pub enum AllocationScore {
+    WinRate,  // winning trades per trade (bps)
+    AvgReturn,  // strategy performance `avg_return`
+    Reported,  // score reported by a risk agent, e.g. a rolling Sharpe
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct AllocationPolicy {
+    pub score: AllocationScore,
+    pub interval_secs: u64,  // minimum time between keeper rebalances
+    pub last_rebalance: Option<u64>,  // None until the first keeper rebalance
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct AllocationBounds {
+    pub min_bps: u32,
+    pub max_bps: u32,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct Reallocation {
+    pub reallocation_id: u32,
+    pub from_strategy: String,
+    pub to_strategy: String,
+    pub amount: i128,
+    pub risk_agent: Address,  // risk agent that signed off alongside the admin
+    pub timestamp: u64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct SubVault {
+    pub cash: i128,  // base-asset capital not in positions
+    pub positions: Map<String, i128>,  // asset -> quantity held by the strategy
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Move unallocated base capital into a strategy's sub-vault, creating it
+    pub fn fund_sub_vault(env: Env, strategy: String, amount: i128) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if amount > unallocated(&env, &config) {
+            panic!("Insufficient unallocated capital");
+        }
+
+        let mut sub_vault = open_sub_vault(&env, &strategy);
+        sub_vault.cash += amount;
+        env.storage().instance().set(&DataKey::SubVault(strategy), &sub_vault);
+        storage::extend_instance(&env);
+    }
+
+    /// Move sub-vault cash from one strategy to another (risk agent and admin)
+    ///
+    /// Returns the reallocation id.
+    pub fn reallocate(env: Env, caller: Address, from_strategy: String, to_strategy: String, amount: i128) -> u32 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        roles::require_role(&env, &caller, &roles::RISK_AGENT);
+        config.admin.require_auth();
+
+        if amount <= 0 {
+            panic!("Amount must be positive");
+        }
+        if from_strategy == to_strategy {
+            panic!("Strategies must differ");
+        }
+
+        let mut from = sub_vault(&env, &from_strategy).expect("No such sub-vault");
+        if amount > from.cash {
+            panic!("Insufficient sub-vault cash");
+        }
+        let mut to = open_sub_vault(&env, &to_strategy);
+        from.cash -= amount;
+        to.cash += amount;
+        env.storage().instance().set(&DataKey::SubVault(from_strategy.clone()), &from);
+        env.storage().instance().set(&DataKey::SubVault(to_strategy.clone()), &to);
+
+        let reallocation_id: u32 = env.storage().instance()
+            .get(&SubVaultKey::ReallocationCount).unwrap_or(0) + 1;
+        let record = Reallocation {
+            reallocation_id,
+            from_strategy,
+            to_strategy,
+            amount,
+            risk_agent: caller,
+            timestamp: env.ledger().timestamp(),
+        };
+        let key = SubVaultKey::Reallocation(reallocation_id);
+        env.storage().persistent().set(&key, &record);
+        env.storage().persistent().extend_ttl(&key, storage::PERSISTENT_LIFETIME_THRESHOLD, storage::PERSISTENT_BUMP_AMOUNT);
+        env.storage().instance().set(&SubVaultKey::ReallocationCount, &reallocation_id);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("realloc"), reallocation_id), record);
+        reallocation_id
+    }
+
+    /// Set how `rebalance_strategy_capital` scores strategies and how often it may run
+    pub fn set_allocation_policy(env: Env, score: AllocationScore, interval_secs: u64) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if interval_secs == 0 {
+            panic!("Interval must be positive");
+        }
+
+        let last_rebalance = allocation_policy(&env).and_then(|policy| policy.last_rebalance);
+        let policy = AllocationPolicy { score, interval_secs, last_rebalance };
+        env.storage().instance().set(&SubVaultKey::AllocationPolicy, &policy);
+        storage::extend_instance(&env);
+    }
+
+    /// Get the automatic allocation policy
+    pub fn get_allocation_policy(env: Env) -> Option<AllocationPolicy> {
+        allocation_policy(&env)
+    }
+
+    /// Bound a strategy's share of sub-vault cash under automatic allocation (bps)
+    pub fn set_allocation_bounds(env: Env, strategy: String, min_bps: u32, max_bps: u32) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if min_bps > max_bps || max_bps > 10000 {
+            panic!("Invalid allocation bounds");
+        }
+
+        let bounds = AllocationBounds { min_bps, max_bps };
+        env.storage().instance().set(&SubVaultKey::AllocationBounds(strategy), &bounds);
+        storage::extend_instance(&env);
+    }
+
+    /// Get a strategy's allocation bounds (unbounded by default)
+    pub fn get_allocation_bounds(env: Env, strategy: String) -> AllocationBounds {
+        allocation_bounds(&env, &strategy)
+    }
+
+    /// Report a strategy's score for `AllocationScore::Reported` (risk agent)
+    pub fn report_strategy_score(env: Env, caller: Address, strategy: String, score: i128) {
+        roles::require_role(&env, &caller, &roles::RISK_AGENT);
+
+        env.storage().instance().set(&SubVaultKey::StrategyScore(strategy), &score);
+        storage::extend_instance(&env);
+    }
+
+    /// Redistribute sub-vault cash by strategy score once the policy interval has passed
+    ///
+    /// Anyone may call this; the caller is paid the keeper fee from the fee
+    /// schedule. Returns the cash moved between sub-vaults.
+    pub fn rebalance_strategy_capital(env: Env, keeper: Address) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        keeper.require_auth();
+        let _lock = guard::lock(&env, symbol_short!("realloc"));
+
+        let mut policy = allocation_policy(&env).expect("Allocation policy not configured");
+        let now = env.ledger().timestamp();
+        if policy.last_rebalance.is_some_and(|last| now < last + policy.interval_secs) {
+            panic!("Rebalance interval has not elapsed");
+        }
+
+        let names = sub_vault_names(&env);
+        let mut total = 0;
+        let mut total_score = 0;
+        let mut scores = Vec::new(&env);
+        for name in names.iter() {
+            total += sub_vault(&env, &name).unwrap().cash;
+            let score = strategy_score(&env, policy.score, &name).max(0);
+            total_score += score;
+            scores.push_back(score);
+        }
+        if total == 0 {
+            panic!("No sub-vault capital");
+        }
+
+        // Proportional shares clamped to the bounds, then whatever the
+        // clamping left over handed to strategies with room
+        let mut targets = Vec::new(&env);
+        let mut left = total;
+        for (i, name) in names.iter().enumerate() {
+            let share = if total_score > 0 {
+                total * scores.get(i as u32).unwrap() / total_score
+            } else {
+                total / names.len() as i128
+            };
+            let bounds = allocation_bounds(&env, &name);
+            let target = share
+                .max(total * bounds.min_bps as i128 / 10000)
+                .min(total * bounds.max_bps as i128 / 10000);
+            left -= target;
+            targets.push_back(target);
+        }
+        for (i, name) in names.iter().enumerate() {
+            let bounds = allocation_bounds(&env, &name);
+            let target = targets.get(i as u32).unwrap();
+            let adjusted = if left > 0 {
+                target + left.min(total * bounds.max_bps as i128 / 10000 - target)
+            } else {
+                target - (-left).min(target - total * bounds.min_bps as i128 / 10000)
+            };
+            left -= adjusted - target;
+            targets.set(i as u32, adjusted);
+        }
+        if left < 0 {
+            panic!("Allocation bounds infeasible");
+        }
+
+        // Anything the bounds cannot place goes back to unallocated capital
+        let mut moved = 0;
+        for (i, name) in names.iter().enumerate() {
+            let mut sub_vault = sub_vault(&env, &name).unwrap();
+            let target = targets.get(i as u32).unwrap();
+            moved += (target - sub_vault.cash).max(0);
+            sub_vault.cash = target;
+            env.storage().instance().set(&DataKey::SubVault(name), &sub_vault);
+        }
+
+        policy.last_rebalance = Some(now);
+        env.storage().instance().set(&SubVaultKey::AllocationPolicy, &policy);
+        fees::pay_keeper(&env, &config, &keeper);
+        storage::extend_instance(&env);
+
+        env.events().publish((symbol_short!("realloc"), symbol_short!("keeper")), moved);
+        moved
+    }
+
+    /// Get a recorded reallocation
+    pub fn get_reallocation(env: Env, reallocation_id: u32) -> Option<Reallocation> {
+        env.storage().persistent().get(&SubVaultKey::Reallocation(reallocation_id))
+    }
+
+    /// Get how many reallocations have been made
+    pub fn get_reallocation_count(env: Env) -> u32 {
+        env.storage().instance().get(&SubVaultKey::ReallocationCount).unwrap_or(0)
+    }
+
+    /// Get a strategy's sub-vault
+    pub fn get_sub_vault(env: Env, strategy: String) -> Option<SubVault> {
+        sub_vault(&env, &strategy)
+    }
+
+    /// Get the base capital not allocated to any sub-vault
+    pub fn get_unallocated_capital(env: Env) -> i128 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        unallocated(&env, &config)
+    }
+}
+
+pub(crate) fn sub_vault(env: &Env, strategy: &String) -> Option<SubVault> {
+    env.storage().instance().get(&DataKey::SubVault(strategy.clone()))
+}
+
+fn allocation_policy(env: &Env) -> Option<AllocationPolicy> {
+    env.storage().instance().get(&SubVaultKey::AllocationPolicy)
+}
+
+fn allocation_bounds(env: &Env, strategy: &String) -> AllocationBounds {
+    env.storage().instance()
+        .get(&SubVaultKey::AllocationBounds(strategy.clone()))
+        .unwrap_or(AllocationBounds { min_bps: 0, max_bps: 10000 })
+}
+
+fn strategy_score(env: &Env, score: AllocationScore, strategy: &String) -> i128 {
+    match score {
+        AllocationScore::Reported => env.storage().instance()
+            .get(&SubVaultKey::StrategyScore(strategy.clone()))
+            .unwrap_or(0),
+        AllocationScore::WinRate | AllocationScore::AvgReturn => {
+            let perf = AITreasuryVaultV2::get_strategy_performance(env.clone(), strategy.clone());
+            match score {
+                AllocationScore::WinRate if perf.total_trades > 0 => {
+                    perf.winning_trades as i128 * 10000 / perf.total_trades as i128
+                }
+                AllocationScore::AvgReturn => perf.avg_return as i128,
+                _ => 0,
+            }
+        }
+    }
+}
+
+/// A strategy's sub-vault, registering an empty one if it has none
+fn open_sub_vault(env: &Env, strategy: &String) -> SubVault {
+    if let Some(sub_vault) = sub_vault(env, strategy) {
+        return sub_vault;
+    }
+    let mut names = sub_vault_names(env);
+    names.push_back(strategy.clone());
+    env.storage().instance().set(&SubVaultKey::SubVaults, &names);
+    SubVault { cash: 0, positions: Map::new(env) }
+}
+
+pub(crate) fn sub_vault_names(env: &Env) -> Vec<String> {
+    env.storage().instance()
+        .get(&SubVaultKey::SubVaults)
+        .unwrap_or(Vec::new(env))
+}
+
+/// Base position not held as cash by any sub-vault
+pub(crate) fn unallocated(env: &Env, config: &VaultConfig) -> i128 {
+    let mut allocated = 0;
+    for name in sub_vault_names(env).iter() {
+        allocated += sub_vault(env, &name).map_or(0, |sub_vault| sub_vault.cash);
+    }
+    portfolio::position(env, &config.base_asset) - allocated
+}
+
+/// Quantity of `asset` held by sub-vaults rather than the shared book
+pub(crate) fn held_by_sub_vaults(env: &Env, asset: &String) -> i128 {
+    let mut held = 0;
+    for name in sub_vault_names(env).iter() {
+        held += sub_vault(env, &name)
+            .and_then(|sub_vault| sub_vault.positions.get(asset.clone()))
+            .unwrap_or(0);
+    }
+    held
+}
+
+/// Book a fill against the strategy's sub-vault, if it has one
+pub(crate) fn book_fill(env: &Env, config: &VaultConfig, strategy: &String, fill: &Fill) {
+    if fill.asset == config.base_asset {
+        return;
+    }
+    let mut sub_vault = match sub_vault(env, strategy) {
+        Some(sub_vault) => sub_vault,
+        None => return,
+    };
+
+    let notional = portfolio::notional(env, &fill.asset, fill.amount, fill.price);
+    let held = sub_vault.positions.get(fill.asset.clone()).unwrap_or(0);
+    let held = match fill.action {
+        TradeAction::Buy => {
+            if notional > sub_vault.cash {
+                panic!("Sub-vault cash exhausted");
+            }
+            sub_vault.cash -= notional;
+            held + fill.amount
+        }
+        TradeAction::Sell => {
+            if !config.allow_shorting && held < fill.amount {
+                panic!("Insufficient sub-vault position");
+            }
+            sub_vault.cash += notional;
+            held - fill.amount
+        }
+        TradeAction::Hold => return,
+    };
+
+    if held == 0 {
+        sub_vault.positions.remove(fill.asset.clone());
+    } else {
+        sub_vault.positions.set(fill.asset.clone(), held);
+    }
+    env.storage().instance().set(&DataKey::SubVault(strategy.clone()), &sub_vault);
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::{Address as _, Ledger};
+    use soroban_sdk::Address;
+
+    #[test]
+    fn test_sub_vault_accounting() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        let lstm = String::from_str(&env, "LSTM");
+        let dqn = String::from_str(&env, "DQN");
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        vault.client.fund_sub_vault(&lstm, &300_0000000);
+        assert_eq!(vault.client.get_unallocated_capital(), 700_0000000);
+        assert!(vault.client.try_fund_sub_vault(&dqn, &800_0000000).is_err());
+
+        let trade = |strategy: &String, action: TradeAction, amount: i128| {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &amount,
+                strategy,
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            vault.client.try_execute_trade(&vault.payment_agent, &signal_id, &100_0000000, &None)
+        };
+
+        // LSTM spends only its own 300
+        assert!(trade(&lstm, TradeAction::Buy, 2_0000000).is_ok());
+        assert!(trade(&lstm, TradeAction::Buy, 2_0000000).is_err());
+        let sub_vault = vault.client.get_sub_vault(&lstm).unwrap();
+        assert_eq!(sub_vault.cash, 100_0000000);
+        assert_eq!(sub_vault.positions.get(btc.clone()), Some(2_0000000));
+
+        // Strategies without a sub-vault trade against the shared book
+        assert!(trade(&dqn, TradeAction::Buy, 5_0000000).is_ok());
+        assert!(trade(&lstm, TradeAction::Sell, 3_0000000).is_err());
+        assert!(trade(&lstm, TradeAction::Sell, 2_0000000).is_ok());
+        assert_eq!(vault.client.get_sub_vault(&lstm).unwrap().cash, 300_0000000);
+        assert_eq!(vault.client.get_unallocated_capital(), 200_0000000);
+    }
+
+    #[test]
+    fn test_reallocate() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let lstm = String::from_str(&env, "LSTM");
+        let dqn = String::from_str(&env, "DQN");
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.fund_sub_vault(&lstm, &300_0000000);
+
+        assert!(vault.client.try_reallocate(&vault.trading_agent, &lstm, &dqn, &100_0000000).is_err());
+        assert!(vault.client.try_reallocate(&vault.risk_agent, &lstm, &dqn, &400_0000000).is_err());
+
+        let id = vault.client.reallocate(&vault.risk_agent, &lstm, &dqn, &100_0000000);
+        assert_eq!(vault.client.get_sub_vault(&lstm).unwrap().cash, 200_0000000);
+        assert_eq!(vault.client.get_sub_vault(&dqn).unwrap().cash, 100_0000000);
+        assert_eq!(vault.client.get_unallocated_capital(), 700_0000000);
+
+        let record = vault.client.get_reallocation(&id).unwrap();
+        assert_eq!((record.from_strategy, record.to_strategy, record.amount), (lstm, dqn, 100_0000000));
+        assert_eq!(record.risk_agent, vault.risk_agent);
+        assert_eq!(vault.client.get_reallocation_count(), 1);
+    }
+
+    #[test]
+    fn test_rebalance_strategy_capital() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let token = vault.register_base_token(&env);
+        let lstm = String::from_str(&env, "LSTM");
+        let dqn = String::from_str(&env, "DQN");
+        let keeper = Address::generate(&env);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+        vault.client.fund_sub_vault(&lstm, &300_0000000);
+        vault.client.fund_sub_vault(&dqn, &100_0000000);
+        assert!(vault.client.try_rebalance_strategy_capital(&keeper).is_err());
+
+        // Reported scores of 1 and 3 split the 400 one to three
+        vault.client.set_allocation_policy(&AllocationScore::Reported, &3600);
+        assert!(vault.client.try_report_strategy_score(&vault.trading_agent, &lstm, &1).is_err());
+        vault.client.report_strategy_score(&vault.risk_agent, &lstm, &1);
+        vault.client.report_strategy_score(&vault.risk_agent, &dqn, &3);
+        assert_eq!(vault.client.rebalance_strategy_capital(&keeper), 200_0000000);
+        assert_eq!(vault.client.get_sub_vault(&lstm).unwrap().cash, 100_0000000);
+        assert_eq!(vault.client.get_sub_vault(&dqn).unwrap().cash, 300_0000000);
+        assert!(vault.client.try_rebalance_strategy_capital(&keeper).is_err());
+
+        // A floor of 40% for LSTM is taken from DQN
+        vault.client.set_allocation_bounds(&lstm, &4000, &10000);
+        assert!(vault.client.try_set_allocation_bounds(&dqn, &5000, &4000).is_err());
+        env.ledger().with_mut(|l| l.timestamp += 3600);
+        assert_eq!(vault.client.rebalance_strategy_capital(&keeper), 60_0000000);
+        assert_eq!(vault.client.get_sub_vault(&lstm).unwrap().cash, 160_0000000);
+        assert_eq!(vault.client.get_sub_vault(&dqn).unwrap().cash, 240_0000000);
+
+        // A cap DQN cannot fill leaves the rest unallocated
+        vault.client.set_allocation_bounds(&lstm, &0, &2500);
+        vault.client.set_allocation_bounds(&dqn, &0, &5000);
+        env.ledger().with_mut(|l| l.timestamp += 3600);
+        vault.client.rebalance_strategy_capital(&keeper);
+        assert_eq!(vault.client.get_sub_vault(&lstm).unwrap().cash, 100_0000000);
+        assert_eq!(vault.client.get_sub_vault(&dqn).unwrap().cash, 200_0000000);
+        assert_eq!(vault.client.get_unallocated_capital(), 700_0000000);
+    }
+}
@@ -0,0 +1,131 @@
+//! Timelocked contract upgrades.
+//!
+//! A new wasm hash has to be proposed first and can only be installed once
+//! `VaultConfig.timelock_secs` has elapsed, giving depositors time to exit
+//! before code they have not reviewed takes over the vault. The delay is
+//! fixed when the upgrade is proposed and is never shorter than
+//! `changes::MIN_TIMELOCK_SECS`; the timelock itself only changes through
+//! the change queue.
+
+use soroban_sdk::{contractimpl, contracttype, symbol_short, BytesN, Env};
+
+use crate::changes;
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, VaultConfig};
+
+#[derive(Clone)]
+#[contracttype]
+pub struct PendingUpgrade {
+    pub wasm_hash: BytesN<32>,
+    pub proposed_at: u64,
+    pub delay_secs: u64,  // timelock in force when proposed
+    pub executable_at: u64,
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Propose a new contract wasm; executable after the timelock
+    pub fn propose_upgrade(env: Env, new_wasm_hash: BytesN<32>) -> u64 {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let now = env.ledger().timestamp();
+        let delay_secs = config.timelock_secs.max(changes::MIN_TIMELOCK_SECS);
+        let pending = PendingUpgrade {
+            wasm_hash: new_wasm_hash.clone(),
+            proposed_at: now,
+            delay_secs,
+            executable_at: now + delay_secs,
+        };
+        env.storage().instance().set(&DataKey::PendingUpgrade, &pending);
+
+        env.events().publish(
+            (symbol_short!("upgrade"), symbol_short!("proposed")),
+            (new_wasm_hash, pending.executable_at),
+        );
+
+        pending.executable_at
+    }
+
+    /// Drop the pending upgrade proposal
+    pub fn cancel_upgrade(env: Env) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let pending: PendingUpgrade = env.storage().instance()
+            .get(&DataKey::PendingUpgrade)
+            .expect("No pending upgrade");
+        env.storage().instance().remove(&DataKey::PendingUpgrade);
+
+        env.events().publish(
+            (symbol_short!("upgrade"), symbol_short!("cancelled")),
+            pending.wasm_hash,
+        );
+    }
+
+    /// Install the proposed wasm once its timelock has elapsed
+    pub fn upgrade(env: Env, new_wasm_hash: BytesN<32>) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let pending: PendingUpgrade = env.storage().instance()
+            .get(&DataKey::PendingUpgrade)
+            .expect("No pending upgrade");
+
+        if pending.wasm_hash != new_wasm_hash {
+            panic!("Wasm hash does not match proposal");
+        }
+
+        if env.ledger().timestamp() < pending.executable_at {
+            panic!("Upgrade timelock has not elapsed");
+        }
+
+        env.storage().instance().remove(&DataKey::PendingUpgrade);
+        env.deployer().update_current_contract_wasm(new_wasm_hash.clone());
+
+        env.events().publish(
+            (symbol_short!("upgrade"), symbol_short!("executed")),
+            new_wasm_hash,
+        );
+    }
+
+    /// Get the pending upgrade proposal, if any
+    pub fn get_pending_upgrade(env: Env) -> Option<PendingUpgrade> {
+        env.storage().instance().get(&DataKey::PendingUpgrade)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Ledger;
+
+    #[test]
+    fn test_upgrade_timelock() {
+        let env = Env::default();
+        let vault = setup(&env);
+
+        let wasm_hash = BytesN::from_array(&env, &[7; 32]);
+        let executable_at = vault.client.propose_upgrade(&wasm_hash);
+        assert_eq!(executable_at, env.ledger().timestamp() + 86400);
+
+        // Too early, and only the proposed hash may be installed
+        assert!(vault.client.try_upgrade(&wasm_hash).is_err());
+        env.ledger().with_mut(|l| l.timestamp += 86400);
+        assert!(vault.client.try_upgrade(&BytesN::from_array(&env, &[8; 32])).is_err());
+
+        vault.client.cancel_upgrade();
+        assert!(vault.client.get_pending_upgrade().is_none());
+        assert!(vault.client.try_upgrade(&wasm_hash).is_err());
+
+        // A config stored with a shorter timelock still waits the minimum
+        env.as_contract(&vault.client.address, || {
+            let mut config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+            config.timelock_secs = 0;
+            env.storage().instance().set(&DataKey::Config, &config);
+        });
+        let executable_at = vault.client.propose_upgrade(&wasm_hash);
+        assert_eq!(executable_at, env.ledger().timestamp() + changes::MIN_TIMELOCK_SECS);
+        assert!(vault.client.try_upgrade(&wasm_hash).is_err());
+    }
+}
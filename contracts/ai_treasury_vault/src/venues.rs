@@ -0,0 +1,295 @@
+//! Best-execution routing.
+//!
+//! Each asset can be given several trading venues: registered DEX routers
+//! that swap it against the base asset. `execute_best` asks every venue for
+//! a quote on an approved signal and routes the trade through the one that
+//! buys the asset for the least base asset or sells it for the most. Venues
+//! that fail to quote are skipped. The fill is booked at the best quote,
+//! with the venue on its trade record, and goes through the same checks as
+//! any other execution before the swap is settled; the swap must honour
+//! the quote, and any price improvement is booked as realized profit.
+
+use core::cell::Cell;
+
+use soroban_sdk::{contractclient, contractimpl, contracttype, symbol_short, token, Address, Env, String, Vec};
+
+use crate::{assets, pnl, portfolio, records, storage};
+use crate::{AITreasuryVaultV2, AITreasuryVaultV2Client, DataKey, TradeAction, TradingSignal, VaultConfig};
+
+/// Most venues an asset can be routed through
+pub const MAX_VENUES_PER_ASSET: u32 = 5;
+
+/// Interface of the DEX routers trades and payments are swapped through
+#[allow(dead_code)]
+#[contractclient(name = "DexRouterClient")]
+pub trait IDexRouter {
+    /// Output `amount_in` of `token_in` would currently buy
+    fn quote_exact_in(env: Env, token_in: Address, token_out: Address, amount_in: i128) -> i128;
+    /// Input `amount_out` of `token_out` would currently cost
+    fn quote_exact_out(env: Env, token_in: Address, token_out: Address, amount_out: i128) -> i128;
+    /// Called after `amount_in` of `token_in` has been transferred to the
+    /// router; send at least `min_out` of `token_out` to `to` and return it
+    fn swap_exact_in(env: Env, to: Address, token_in: Address, token_out: Address, amount_in: i128, min_out: i128) -> i128;
+    /// Called after `max_in` of `token_in` has been transferred to the router;
+    /// send `amount_out` of `token_out` to `to`, refund the unused input and
+    /// return the amount used
+    fn swap_exact_out(env: Env, to: Address, token_in: Address, token_out: Address, amount_out: i128, max_in: i128) -> i128;
+}
+
+// Keys encode as their variant name only, so names must not clash with `DataKey`
+#[derive(Clone)]
+#[contracttype]
+pub enum VenueKey {
+    Venues(String),  // asset -> DEX routers it can be traded through
+}
+
+#[contractimpl]
+impl AITreasuryVaultV2 {
+    /// Let trades in `asset` be routed through a registered DEX
+    pub fn register_venue(env: Env, asset: String, router: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        if Self::get_dex_fee_tier(env.clone(), router.clone()).is_none() {
+            panic!("DEX not registered");
+        }
+        let mut venues = venues(&env, &asset);
+        if venues.contains(&router) {
+            return;
+        }
+        if venues.len() >= MAX_VENUES_PER_ASSET {
+            panic!("Too many venues");
+        }
+        venues.push_back(router);
+        env.storage().instance().set(&VenueKey::Venues(asset), &venues);
+        storage::extend_instance(&env);
+    }
+
+    /// Stop routing trades in `asset` through a DEX
+    pub fn remove_venue(env: Env, asset: String, router: Address) {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        config.admin.require_auth();
+
+        let mut venues = venues(&env, &asset);
+        if let Some(index) = venues.first_index_of(&router) {
+            venues.remove(index);
+            env.storage().instance().set(&VenueKey::Venues(asset), &venues);
+        }
+    }
+
+    /// Get the venues trades in `asset` can be routed through
+    pub fn get_venues(env: Env, asset: String) -> Vec<Address> {
+        venues(&env, &asset)
+    }
+
+    /// Execute an approved trade through the venue quoting the best price
+    ///
+    /// Repeats are answered like `execute_trade`'s, with the trade id
+    /// recorded the first time.
+    pub fn execute_best(env: Env, caller: Address, signal_id: u64, nonce: Option<u64>) -> u64 {
+        let quoted = Cell::new(None);
+        crate::execute_signal(
+            &env,
+            &caller,
+            signal_id,
+            nonce,
+            |config, signal| {
+                let (router, quote) = best_quote(&env, config, signal);
+                quoted.set(Some((router.clone(), quote)));
+                (quote * assets::unit_scale(&env, &signal.asset) / signal.amount, Some(router))
+            },
+            |config, signal, trade_id| {
+                let (router, quote) = quoted.take().unwrap();
+                settle(&env, config, signal, &router, quote);
+                env.events().publish((symbol_short!("trade"), symbol_short!("venue"), trade_id), router);
+            },
+        )
+    }
+
+    /// Get the venue a trade was routed through, if it was routed
+    pub fn get_trade_venue(env: Env, trade_id: u64) -> Option<Address> {
+        let config: VaultConfig = env.storage().instance().get(&DataKey::Config).unwrap();
+        records::load_trade(&env, &config, trade_id).and_then(|trade| trade.venue)
+    }
+}
+
+fn venues(env: &Env, asset: &String) -> Vec<Address> {
+    env.storage().instance()
+        .get(&VenueKey::Venues(asset.clone()))
+        .unwrap_or(Vec::new(env))
+}
+
+/// Venue quoting the best price for a signal's trade, and its quote in the
+/// base asset
+fn best_quote(env: &Env, config: &VaultConfig, signal: &TradingSignal) -> (Address, i128) {
+    let (token_in, token_out) = swap_tokens(env, config, signal);
+    let buying = signal.action == TradeAction::Buy;
+
+    // Buys want the smallest input, sells the largest output
+    let mut best: Option<(Address, i128)> = None;
+    for router in venues(env, &signal.asset).iter() {
+        let client = DexRouterClient::new(env, &router);
+        let quote = if buying {
+            client.try_quote_exact_out(&token_in, &token_out, &signal.amount)
+        } else {
+            client.try_quote_exact_in(&token_in, &token_out, &signal.amount)
+        };
+        let Ok(Ok(quote)) = quote else { continue };
+        let better = best.as_ref().is_none_or(|(_, best)| if buying { quote < *best } else { quote > *best });
+        if quote > 0 && better {
+            best = Some((router, quote));
+        }
+    }
+    best.expect("No venue quoted the trade")
+}
+
+/// Swap a booked trade through `router` at no worse than its `quote`
+fn settle(env: &Env, config: &VaultConfig, signal: &TradingSignal, router: &Address, quote: i128) {
+    let (token_in, token_out) = swap_tokens(env, config, signal);
+    let buying = signal.action == TradeAction::Buy;
+
+    // Reconcile with what actually moved rather than what the router reports
+    let vault = env.current_contract_address();
+    let in_client = token::Client::new(env, &token_in);
+    let out_client = token::Client::new(env, &token_out);
+    let (in_balance, out_balance) = (in_client.balance(&vault), out_client.balance(&vault));
+    let client = DexRouterClient::new(env, router);
+    if buying {
+        in_client.transfer(&vault, router, &quote);
+        client.swap_exact_out(&vault, &token_in, &token_out, &signal.amount, &quote);
+    } else {
+        in_client.transfer(&vault, router, &signal.amount);
+        client.swap_exact_in(&vault, &token_in, &token_out, &signal.amount, &quote);
+    }
+    let spent = in_balance - in_client.balance(&vault);
+    let received = out_client.balance(&vault) - out_balance;
+
+    let (filled, base_moved) = if buying {
+        (received >= signal.amount && spent <= quote, -spent)
+    } else {
+        (received >= quote && spent <= signal.amount, received)
+    };
+    if !filled {
+        panic!("Venue did not honour its quote");
+    }
+
+    // The fill was booked at the quote; keep the book on the balances
+    let price = quote * assets::unit_scale(env, &signal.asset) / signal.amount;
+    let booked = portfolio::notional(env, &signal.asset, signal.amount, price);
+    let improvement = base_moved - if buying { -booked } else { booked };
+    if improvement != 0 {
+        portfolio::adjust_position(env, &config.base_asset, improvement);
+        pnl::book_realized(env, improvement);
+    }
+}
+
+/// Tokens a signal's trade swaps from and to
+fn swap_tokens(env: &Env, config: &VaultConfig, signal: &TradingSignal) -> (Address, Address) {
+    let base_token = assets::token_address(env, config, &config.base_asset)
+        .expect("Base token not configured");
+    let asset_token = assets::token_address(env, config, &signal.asset).expect("Asset has no token");
+    if signal.action == TradeAction::Buy {
+        (base_token, asset_token)
+    } else {
+        (asset_token, base_token)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::setup;
+    use soroban_sdk::testutils::Address as _;
+    use soroban_sdk::token::StellarAssetClient;
+    use soroban_sdk::contract;
+
+    /// Router trading the asset at a fixed rate of base units per whole unit
+    #[contract]
+    pub struct MockVenue;
+
+    #[contractimpl]
+    impl MockVenue {
+        pub fn set_rate(env: Env, rate: i128) {
+            env.storage().instance().set(&symbol_short!("rate"), &rate);
+        }
+
+        pub fn quote_exact_in(env: Env, _token_in: Address, _token_out: Address, amount_in: i128) -> i128 {
+            let rate: i128 = env.storage().instance().get(&symbol_short!("rate")).unwrap();
+            amount_in * rate / 1_0000000
+        }
+
+        pub fn quote_exact_out(env: Env, _token_in: Address, _token_out: Address, amount_out: i128) -> i128 {
+            let rate: i128 = env.storage().instance().get(&symbol_short!("rate")).unwrap();
+            amount_out * rate / 1_0000000
+        }
+
+        pub fn swap_exact_in(env: Env, to: Address, token_in: Address, token_out: Address, amount_in: i128, _min_out: i128) -> i128 {
+            let out = Self::quote_exact_in(env.clone(), token_in, token_out.clone(), amount_in);
+            token::Client::new(&env, &token_out).transfer(&env.current_contract_address(), &to, &out);
+            out
+        }
+
+        pub fn swap_exact_out(env: Env, to: Address, token_in: Address, token_out: Address, amount_out: i128, max_in: i128) -> i128 {
+            let used = Self::quote_exact_out(env.clone(), token_in.clone(), token_out.clone(), amount_out);
+            let venue = env.current_contract_address();
+            token::Client::new(&env, &token_out).transfer(&venue, &to, &amount_out);
+            token::Client::new(&env, &token_in).transfer(&venue, &to, &(max_in - used));
+            used
+        }
+    }
+
+    #[test]
+    fn test_execute_best_routes_to_best_quote() {
+        let env = Env::default();
+        let vault = setup(&env);
+        let oracle = vault.register_oracle(&env);
+        let token = vault.register_base_token(&env);
+        let btc = String::from_str(&env, "BTC");
+        let btc_token = StellarAssetClient::new(&env, &env.register_stellar_asset_contract_v2(vault.admin.clone()).address());
+        vault.client.register_asset(&btc, &btc_token.address, &7, &btc);
+        oracle.set_price(&btc, &100_0000000);
+
+        let alice = Address::generate(&env);
+        token.mint(&alice, &1000_0000000);
+        vault.client.deposit(&alice, &1000_0000000);
+
+        // Venues must be registered DEXes
+        let (cheap, rich) = (env.register_contract(None, MockVenue), env.register_contract(None, MockVenue));
+        assert!(vault.client.try_register_venue(&btc, &cheap).is_err());
+        for (venue, rate) in [(&cheap, 99_0000000), (&rich, 101_0000000)] {
+            vault.client.register_dex_fee_tier(venue, &30);
+            vault.client.register_venue(&btc, venue);
+            MockVenueClient::new(&env, venue).set_rate(&rate);
+            token.mint(venue, &1000_0000000);
+            btc_token.mint(venue, &10_0000000);
+        }
+
+        // Buy where the asset is cheapest, sell where it fetches most
+        let mut trade_ids = Vec::new(&env);
+        for action in [TradeAction::Buy, TradeAction::Sell] {
+            let signal_id = vault.client.submit_trading_signal(
+                &vault.trading_agent,
+                &btc,
+                &action,
+                &1_0000000,
+                &String::from_str(&env, "LSTM"),
+                &85,
+                &250,
+                &None,
+                &None,
+            );
+            trade_ids.push_back(vault.client.execute_best(&vault.payment_agent, &signal_id, &None));
+        }
+        let (buy, sell) = (trade_ids.get(0).unwrap(), trade_ids.get(1).unwrap());
+        assert_eq!(vault.client.get_trade_venue(&buy), Some(cheap.clone()));
+        assert_eq!(vault.client.get_trade(&buy).venue, Some(cheap));
+        assert_eq!(vault.client.get_trade(&buy).price, 99_0000000);
+        assert_eq!(vault.client.get_trade_venue(&sell), Some(rich));
+        assert_eq!(vault.client.get_trade(&sell).profit_loss, 2_0000000);
+
+        // The book matches the balances the swaps moved
+        assert_eq!(vault.client.get_position(&String::from_str(&env, "XLM")), 1002_0000000);
+        assert_eq!(token::Client::new(&env, &token.address).balance(&vault.client.address), 1002_0000000);
+        assert_eq!(vault.client.get_position(&btc), 0);
+    }
+}
@@ -0,0 +1,276 @@
+#![no_std]
+
+//! Vault Factory
+//!
+//! Deploys `AITreasuryVaultV2` instances for separate tenants from uploaded
+//! vault WASM and keeps a registry of every vault it created:
+//! - Named templates pin the vault WASM hash and the initial trade limit
+//! - `deploy_vault` deploys and initializes a vault in one call, signed by
+//!   the tenant's admin
+//! - Deployed vaults can be listed in full or per admin
+//!
+//! A template only covers what the vault's `initialize` takes. Risk limits
+//! and every other setting start at the vault's defaults and are changed by
+//! its admin after deployment, risk limits through the vault's timelocked
+//! change queue.
+//!
+//! The tests deploy the real vault WASM, so build it first with
+//! `cargo build --target wasm32-unknown-unknown --release` in
+//! `contracts/ai_treasury_vault`.
+
+use soroban_sdk::{
+    contract, contractclient, contractimpl, contracttype, Address, BytesN, Env, Symbol, Vec,
+};
+
+const DAY_IN_LEDGERS: u32 = 17280;
+const BUMP_AMOUNT: u32 = 90 * DAY_IN_LEDGERS;
+const LIFETIME_THRESHOLD: u32 = BUMP_AMOUNT - 7 * DAY_IN_LEDGERS;
+
+/// The part of the vault interface the factory calls
+#[allow(dead_code)]
+#[contractclient(name = "VaultClient")]
+pub trait Vault {
+    fn initialize(
+        env: Env,
+        admin: Address,
+        trading_agent: Address,
+        risk_agent: Address,
+        payment_agent: Address,
+        max_single_trade: i128,
+    );
+}
+
+#[derive(Clone, Debug, PartialEq)]
+#[contracttype]
+pub struct VaultTemplate {
+    pub wasm_hash: BytesN<32>,  // uploaded vault WASM to deploy
+    pub max_single_trade: i128,
+}
+
+#[derive(Clone)]
+#[contracttype]
+pub enum DataKey {
+    Owner,
+    Template(Symbol),
+    Vaults,  // every vault deployed, in order (persistent)
+    AdminVaults(Address),  // admin -> vaults it administers (persistent)
+}
+
+#[contract]
+pub struct VaultFactory;
+
+#[contractimpl]
+impl VaultFactory {
+    /// Initialize the factory with the account that manages templates
+    pub fn initialize(env: Env, owner: Address) {
+        if env.storage().instance().has(&DataKey::Owner) {
+            panic!("Already initialized");
+        }
+        owner.require_auth();
+
+        env.storage().instance().set(&DataKey::Owner, &owner);
+        extend_instance(&env);
+    }
+
+    /// Add or replace a named vault template (owner)
+    pub fn set_template(env: Env, name: Symbol, template: VaultTemplate) {
+        owner(&env).require_auth();
+
+        if template.max_single_trade <= 0 {
+            panic!("Trade limit must be positive");
+        }
+
+        env.storage().instance().set(&DataKey::Template(name), &template);
+        extend_instance(&env);
+    }
+
+    /// Get a vault template
+    pub fn get_template(env: Env, name: Symbol) -> Option<VaultTemplate> {
+        env.storage().instance().get(&DataKey::Template(name))
+    }
+
+    /// Deploy and initialize a vault from a template; returns its address
+    pub fn deploy_vault(
+        env: Env,
+        admin: Address,
+        trading_agent: Address,
+        risk_agent: Address,
+        payment_agent: Address,
+        template: Symbol,
+        salt: BytesN<32>,
+    ) -> Address {
+        admin.require_auth();
+
+        let template: VaultTemplate = env.storage().instance()
+            .get(&DataKey::Template(template))
+            .expect("No such template");
+
+        let vault = env.deployer()
+            .with_current_contract(salt)
+            .deploy(template.wasm_hash);
+        VaultClient::new(&env, &vault).initialize(
+            &admin,
+            &trading_agent,
+            &risk_agent,
+            &payment_agent,
+            &template.max_single_trade,
+        );
+
+        push(&env, &DataKey::Vaults, &vault);
+        push(&env, &DataKey::AdminVaults(admin.clone()), &vault);
+        extend_instance(&env);
+
+        env.events().publish((Symbol::new(&env, "vault_deployed"), admin), vault.clone());
+        vault
+    }
+
+    /// Get every vault the factory has deployed
+    pub fn get_vaults(env: Env) -> Vec<Address> {
+        list(&env, &DataKey::Vaults)
+    }
+
+    /// Get the vaults deployed for an admin
+    pub fn get_vaults_by_admin(env: Env, admin: Address) -> Vec<Address> {
+        list(&env, &DataKey::AdminVaults(admin))
+    }
+}
+
+fn owner(env: &Env) -> Address {
+    env.storage().instance().get(&DataKey::Owner).expect("Not initialized")
+}
+
+fn extend_instance(env: &Env) {
+    env.storage().instance().extend_ttl(LIFETIME_THRESHOLD, BUMP_AMOUNT);
+}
+
+fn list(env: &Env, key: &DataKey) -> Vec<Address> {
+    let vaults: Option<Vec<Address>> = env.storage().persistent().get(key);
+    match vaults {
+        Some(vaults) => {
+            env.storage().persistent().extend_ttl(key, LIFETIME_THRESHOLD, BUMP_AMOUNT);
+            vaults
+        }
+        None => Vec::new(env),
+    }
+}
+
+fn push(env: &Env, key: &DataKey, vault: &Address) {
+    let mut vaults = list(env, key);
+    vaults.push_back(vault.clone());
+    env.storage().persistent().set(key, &vaults);
+    env.storage().persistent().extend_ttl(key, LIFETIME_THRESHOLD, BUMP_AMOUNT);
+}
+
+// ============================================================================
+// Tests
+// ============================================================================
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use soroban_sdk::testutils::Address as _;
+
+    // The vault's entry points take their inputs as plain arguments
+    #[allow(clippy::too_many_arguments)]
+    mod vault {
+        soroban_sdk::contractimport!(
+            file = "../ai_treasury_vault/target/wasm32-unknown-unknown/release/ai_treasury_vault.wasm"
+        );
+    }
+
+    fn setup(env: &Env) -> (VaultFactoryClient<'_>, Address) {
+        env.mock_all_auths();
+        let factory = VaultFactoryClient::new(env, &env.register_contract(None, VaultFactory));
+        let owner = Address::generate(env);
+        factory.initialize(&owner);
+        (factory, owner)
+    }
+
+    #[test]
+    fn test_templates() {
+        let env = Env::default();
+        let (factory, owner) = setup(&env);
+        assert!(factory.try_initialize(&owner).is_err());
+
+        let name = Symbol::new(&env, "standard");
+        let template = VaultTemplate {
+            wasm_hash: BytesN::from_array(&env, &[1; 32]),
+            max_single_trade: 1000_0000000,
+        };
+        factory.set_template(&name, &template);
+        assert_eq!(factory.get_template(&name), Some(template.clone()));
+
+        let invalid = VaultTemplate { max_single_trade: 0, ..template };
+        assert!(factory.try_set_template(&name, &invalid).is_err());
+    }
+
+    #[test]
+    fn test_deploy_requires_template() {
+        let env = Env::default();
+        let (factory, _) = setup(&env);
+        let admin = Address::generate(&env);
+
+        let deployed = factory.try_deploy_vault(
+            &admin,
+            &Address::generate(&env),
+            &Address::generate(&env),
+            &Address::generate(&env),
+            &Symbol::new(&env, "missing"),
+            &BytesN::from_array(&env, &[0; 32]),
+        );
+        assert!(deployed.is_err());
+        assert!(factory.get_vaults().is_empty());
+        assert!(factory.get_vaults_by_admin(&admin).is_empty());
+    }
+
+    #[test]
+    fn test_deploy_vault() {
+        let env = Env::default();
+        // Parsing the full vault WASM is costlier than one transaction allows
+        env.budget().reset_unlimited();
+        let (factory, _) = setup(&env);
+        let name = Symbol::new(&env, "standard");
+        let template = VaultTemplate {
+            wasm_hash: env.deployer().upload_contract_wasm(vault::WASM),
+            max_single_trade: 1000_0000000,
+        };
+        factory.set_template(&name, &template);
+
+        let (alice, bob) = (Address::generate(&env), Address::generate(&env));
+        let trading_agent = Address::generate(&env);
+        let deploy = |admin: &Address, salt: u8| {
+            factory.deploy_vault(
+                admin,
+                &trading_agent,
+                &Address::generate(&env),
+                &Address::generate(&env),
+                &name,
+                &BytesN::from_array(&env, &[salt; 32]),
+            )
+        };
+        let first = deploy(&alice, 1);
+        let second = deploy(&bob, 2);
+        let third = deploy(&alice, 3);
+
+        assert_eq!(factory.get_vaults(), Vec::from_array(&env, [first.clone(), second.clone(), third.clone()]));
+        assert_eq!(factory.get_vaults_by_admin(&alice), Vec::from_array(&env, [first.clone(), third]));
+        assert_eq!(factory.get_vaults_by_admin(&bob), Vec::from_array(&env, [second]));
+
+        // Each vault is initialized from the template for its own admin
+        let client = vault::Client::new(&env, &first);
+        let config = client.get_config();
+        assert_eq!(config.admin, alice);
+        assert_eq!(config.max_single_trade, 1000_0000000);
+        assert!(client.has_role(&Symbol::new(&env, "trader"), &trading_agent));
+
+        // A salt can only be used once
+        assert!(factory.try_deploy_vault(
+            &alice,
+            &trading_agent,
+            &Address::generate(&env),
+            &Address::generate(&env),
+            &name,
+            &BytesN::from_array(&env, &[1; 32]),
+        ).is_err());
+    }
+}
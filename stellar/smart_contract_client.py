@@ -14,6 +14,7 @@ import os
 from typing import Dict, Any, Optional, List
 from dataclasses import dataclass
 from datetime import datetime
+from stellar_sdk import Keypair
 
 
 @dataclass
@@ -147,6 +148,11 @@ class SmartContractClientV2:
         except Exception as e:
             return {"success": False, "error": str(e)}
     
+    @staticmethod
+    def _caller_address(signer_secret: str) -> str:
+        """Public address passed as `caller` to role-checked contract functions"""
+        return Keypair.from_secret(signer_secret).public_key
+    
     # ========================================================================
     # Trading Signal Functions (Enhanced)
     # ========================================================================
@@ -168,8 +174,9 @@ class SmartContractClientV2:
             Dict with success status and signal_id
         """
         args = [
+            "--caller", self._caller_address(signer_secret),
             "--asset", asset,
-            "--action", action,
+            "--action", action.capitalize(),  # TradeAction enum: Buy / Sell / Hold
             "--amount", str(amount),
             "--strategy", strategy,
             "--confidence", str(confidence),
@@ -222,6 +229,7 @@ class SmartContractClientV2:
         
         # Create risk_metrics struct
         args = [
+            "--caller", self._caller_address(signer_secret),
             "--signal_id", str(signal_id),
             "--risk_metrics", json.dumps({
                 "var_95": var_bps,
@@ -253,25 +261,26 @@ class SmartContractClientV2:
         self,
         signal_id: int,
         executed_price: int,
-        profit_loss: int,
         signer_secret: str
     ) -> Dict[str, Any]:
         """
         Execute trade and record history (V2)
         
+        Realized P&L is computed by the contract from each position's
+        average cost.
+        
         Args:
             signal_id: Signal ID to execute
             executed_price: Actual execution price (scaled by 1e7)
-            profit_loss: Realized P&L in stroops
             signer_secret: Payment Agent secret key
         
         Returns:
             Dict with success status and trade_id
         """
         args = [
+            "--caller", self._caller_address(signer_secret),
             "--signal_id", str(signal_id),
-            "--executed_price", str(executed_price),
-            "--profit_loss", str(profit_loss)
+            "--executed_price", str(executed_price)
         ]
         
         result = self._run_contract_command(
@@ -283,7 +292,7 @@ class SmartContractClientV2:
         if result["success"]:
             try:
                 trade_id = int(result["output"])
-                print(f"✅ Trade executed: ID={trade_id}, P&L={profit_loss}")
+                print(f"✅ Trade executed: ID={trade_id}")
                 return {"success": True, "trade_id": trade_id}
             except:
                 return {"success": True, "trade_id": None}
@@ -449,6 +458,7 @@ class SmartContractClientV2:
             Dict with success status and snapshot_id
         """
         args = [
+            "--caller", self._caller_address(signer_secret),
             "--total_value", str(total_value),
             "--num_assets", str(num_assets),
             "--cumulative_return", str(cumulative_return)
@@ -554,10 +564,10 @@ class SmartContractClientV2:
         return True  # Default to operational on error
     
     def emergency_halt(self, signer_secret: str) -> Dict[str, Any]:
-        """Emergency halt all trading"""
+        """Emergency halt all trading (admin or guardian)"""
         result = self._run_contract_command(
             "emergency_halt",
-            [],
+            ["--caller", self._caller_address(signer_secret)],
             signer_secret
         )
         